@@ -0,0 +1,49 @@
+//! # Cfg
+//!
+//! In this file, we build the control flow graph of a function from the terminators of its blocks.
+//!
+
+use koopa::ir::{ BasicBlock, FunctionData };
+use std::collections::HashMap;
+
+pub struct Cfg {
+    /// Blocks in layout order.
+    blocks: Vec<BasicBlock>,
+    succs: HashMap<BasicBlock, Vec<BasicBlock>>,
+    preds: HashMap<BasicBlock, Vec<BasicBlock>>,
+}
+
+impl Cfg {
+    pub fn new(func: &FunctionData) -> Self {
+        let mut blocks = vec![];
+        let mut succs: HashMap<BasicBlock, Vec<BasicBlock>> = HashMap::new();
+        let mut preds: HashMap<BasicBlock, Vec<BasicBlock>> = HashMap::new();
+
+        for (&bb, node) in func.layout().bbs() {
+            blocks.push(bb);
+            preds.entry(bb).or_default();
+            let targets: Vec<BasicBlock> = match node.insts().back_key() {
+                Some(&last) => func.dfg().value(last).kind().bb_uses().collect(),
+                None => vec![],
+            };
+            for &target in &targets {
+                preds.entry(target).or_default().push(bb);
+            }
+            succs.insert(bb, targets);
+        }
+
+        Self { blocks, succs, preds }
+    }
+
+    pub fn blocks(&self) -> &[BasicBlock] {
+        &self.blocks
+    }
+
+    pub fn succs(&self, bb: BasicBlock) -> &[BasicBlock] {
+        &self.succs[&bb]
+    }
+
+    pub fn preds(&self, bb: BasicBlock) -> &[BasicBlock] {
+        &self.preds[&bb]
+    }
+}
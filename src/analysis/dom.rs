@@ -0,0 +1,154 @@
+//! # Dom
+//!
+//! In this file, we compute the dominator tree of a function,
+//! with the iterative algorithm by Cooper, Harvey and Kennedy over reverse postorder.
+//! Blocks unreachable from the entry have no immediate dominator and dominate nothing.
+//! Dominance frontiers are computed from the tree when asked for.
+//!
+
+use crate::analysis::cfg::Cfg;
+use koopa::ir::BasicBlock;
+use std::collections::{ HashMap, HashSet };
+
+pub struct Dominators {
+    /// Reachable blocks in reverse postorder, the entry first.
+    order: Vec<BasicBlock>,
+    /// Immediate dominator of each reachable block, the entry is its own.
+    idom: HashMap<BasicBlock, BasicBlock>,
+    children: HashMap<BasicBlock, Vec<BasicBlock>>,
+}
+
+impl Dominators {
+    pub fn new(cfg: &Cfg) -> Self {
+        let order = match cfg.blocks().first() {
+            Some(&entry) => reverse_postorder(cfg, entry),
+            None => vec![],
+        };
+        let number: HashMap<BasicBlock, usize> = order.iter().enumerate().map(|(i, &bb)| (bb, i)).collect();
+
+        let mut idom: HashMap<BasicBlock, BasicBlock> = HashMap::new();
+        if let Some(&entry) = order.first() {
+            idom.insert(entry, entry);
+        }
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in order.iter().skip(1) {
+                let mut new_idom: Option<BasicBlock> = None;
+                for &pred in cfg.preds(bb) {
+                    if !idom.contains_key(&pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &number, pred, other),
+                    });
+                }
+                let new_idom = new_idom.unwrap();
+                if idom.get(&bb) != Some(&new_idom) {
+                    idom.insert(bb, new_idom);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children: HashMap<BasicBlock, Vec<BasicBlock>> = order.iter().map(|&bb| (bb, vec![])).collect();
+        for &bb in order.iter().skip(1) {
+            children.get_mut(&idom[&bb]).unwrap().push(bb);
+        }
+
+        Self { order, idom, children }
+    }
+
+    /// Reachable blocks in reverse postorder.
+    pub fn order(&self) -> &[BasicBlock] {
+        &self.order
+    }
+
+    /// Immediate dominator, `None` for the entry and unreachable blocks.
+    pub fn idom(&self, bb: BasicBlock) -> Option<BasicBlock> {
+        self.idom.get(&bb).copied().filter(|&idom| idom != bb)
+    }
+
+    /// Blocks immediately dominated by `bb`, in reverse postorder.
+    pub fn children(&self, bb: BasicBlock) -> &[BasicBlock] {
+        self.children.get(&bb).map_or(&[], |children| children.as_slice())
+    }
+
+    /// Dominance frontier of each reachable block.
+    /// A join point is in the frontier of every block between its predecessors and its immediate dominator.
+    pub fn frontiers(&self, cfg: &Cfg) -> HashMap<BasicBlock, HashSet<BasicBlock>> {
+        let mut frontiers: HashMap<BasicBlock, HashSet<BasicBlock>> = self.order.iter().map(|&bb| (bb, HashSet::new())).collect();
+        for &bb in &self.order {
+            let preds: Vec<BasicBlock> = cfg.preds(bb).iter().copied().filter(|&pred| self.is_reachable(pred)).collect();
+            if preds.len() < 2 {
+                continue;
+            }
+            for pred in preds {
+                let mut runner = pred;
+                while Some(runner) != self.idom(bb) {
+                    frontiers.get_mut(&runner).unwrap().insert(bb);
+                    match self.idom(runner) {
+                        Some(idom) => runner = idom,
+                        None => break,
+                    }
+                }
+            }
+        }
+        frontiers
+    }
+
+    pub fn is_reachable(&self, bb: BasicBlock) -> bool {
+        self.idom.contains_key(&bb)
+    }
+
+    /// Whether `a` dominates `b`, every block dominates itself.
+    pub fn dominates(&self, a: BasicBlock, b: BasicBlock) -> bool {
+        if !self.is_reachable(a) || !self.is_reachable(b) {
+            return false;
+        }
+        let mut bb = b;
+        loop {
+            if bb == a {
+                return true;
+            }
+            match self.idom(bb) {
+                Some(idom) => bb = idom,
+                None => return false,
+            }
+        }
+    }
+}
+
+fn reverse_postorder(cfg: &Cfg, entry: BasicBlock) -> Vec<BasicBlock> {
+    let mut order = vec![];
+    let mut visited = HashSet::from([entry]);
+    // explicit stack of (block, index of the next successor to visit)
+    let mut stack = vec![(entry, 0)];
+    while let Some((bb, next)) = stack.pop() {
+        match cfg.succs(bb).get(next) {
+            Some(&succ) => {
+                stack.push((bb, next + 1));
+                if visited.insert(succ) {
+                    stack.push((succ, 0));
+                }
+            }
+            None => order.push(bb),
+        }
+    }
+    order.reverse();
+    order
+}
+
+fn intersect(idom: &HashMap<BasicBlock, BasicBlock>, number: &HashMap<BasicBlock, usize>, a: BasicBlock, b: BasicBlock) -> BasicBlock {
+    let (mut a, mut b) = (a, b);
+    while a != b {
+        while number[&a] > number[&b] {
+            a = idom[&a];
+        }
+        while number[&b] > number[&a] {
+            b = idom[&b];
+        }
+    }
+    a
+}
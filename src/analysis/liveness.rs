@@ -0,0 +1,217 @@
+//! # Liveness
+//!
+//! In this file, we compute liveness of values in a function with a backward dataflow analysis over its CFG.
+//! * live-in and live-out sets of each basic block.
+//! * live intervals over instructions numbered in layout order.
+//! * positions of the uses of each value.
+//!
+//! Values defined by instructions and parameters of the function and its blocks are tracked.
+//! Parameters of the function are defined at position 0, before any instruction,
+//! and parameters of a block are defined just before its first instruction, at the position of the one before,
+//! so that they are live across a call starting the block.
+//!
+
+use crate::analysis::cfg::Cfg;
+use koopa::ir::{ BasicBlock, FunctionData, Value };
+use std::collections::{ HashMap, HashSet };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Interval {
+    pub start: usize,
+    pub end: usize,
+}
+
+pub struct Liveness {
+    /// Number of each instruction in layout order, starting from 1.
+    index: HashMap<Value, usize>,
+    /// Parameters of each block.
+    params: HashMap<BasicBlock, Vec<Value>>,
+    live_in: HashMap<BasicBlock, HashSet<Value>>,
+    live_out: HashMap<BasicBlock, HashSet<Value>>,
+    intervals: HashMap<Value, Interval>,
+    /// Positions of the instructions using a value, in ascending order.
+    uses: HashMap<Value, Vec<usize>>,
+}
+
+impl Liveness {
+    pub fn new(func: &FunctionData) -> Self {
+        let cfg = Cfg::new(func);
+
+        let mut index: HashMap<Value, usize> = func.params().iter().map(|&param| (param, 0)).collect();
+        let mut position = 0;
+        let mut insts: HashMap<BasicBlock, Vec<Value>> = HashMap::new();
+        let mut params: HashMap<BasicBlock, Vec<Value>> = HashMap::new();
+        for (&bb, node) in func.layout().bbs() {
+            let values: Vec<Value> = node.insts().keys().copied().collect();
+            for &param in func.dfg().bb(bb).params() {
+                index.insert(param, position);
+            }
+            for &value in &values {
+                position += 1;
+                index.insert(value, position);
+            }
+            insts.insert(bb, values);
+            params.insert(bb, func.dfg().bb(bb).params().to_vec());
+        }
+        let operands = |value: Value| -> Vec<Value> {
+            func.dfg().value(value).kind().value_uses().filter(|used| index.contains_key(used)).collect()
+        };
+
+        // upward exposed uses and definitions of each block
+        let mut gen: HashMap<BasicBlock, HashSet<Value>> = HashMap::new();
+        let mut kill: HashMap<BasicBlock, HashSet<Value>> = HashMap::new();
+        for &bb in cfg.blocks() {
+            let mut used = HashSet::new();
+            let mut defined: HashSet<Value> = params[&bb].iter().copied().collect();
+            for &value in &insts[&bb] {
+                for operand in operands(value) {
+                    if !defined.contains(&operand) {
+                        used.insert(operand);
+                    }
+                }
+                defined.insert(value);
+            }
+            gen.insert(bb, used);
+            kill.insert(bb, defined);
+        }
+
+        let mut live_in: HashMap<BasicBlock, HashSet<Value>> = cfg.blocks().iter().map(|&bb| (bb, HashSet::new())).collect();
+        let mut live_out: HashMap<BasicBlock, HashSet<Value>> = live_in.clone();
+        let mut worklist: Vec<BasicBlock> = cfg.blocks().to_vec();
+        let mut queued: HashSet<BasicBlock> = worklist.iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            queued.remove(&bb);
+            let out: HashSet<Value> = cfg.succs(bb).iter().flat_map(|succ| live_in[succ].iter().copied()).collect();
+            let mut new_in: HashSet<Value> = out.difference(&kill[&bb]).copied().collect();
+            new_in.extend(gen[&bb].iter().copied());
+            live_out.insert(bb, out);
+
+            if new_in != live_in[&bb] {
+                live_in.insert(bb, new_in);
+                for &pred in cfg.preds(bb) {
+                    if queued.insert(pred) {
+                        worklist.push(pred);
+                    }
+                }
+            }
+        }
+
+        let mut intervals: HashMap<Value, Interval> = index.iter().map(|(&value, &idx)| (value, Interval { start: idx, end: idx })).collect();
+        let mut uses: HashMap<Value, Vec<usize>> = index.keys().map(|&value| (value, vec![])).collect();
+        for &bb in cfg.blocks() {
+            let (first, last) = match (insts[&bb].first(), insts[&bb].last()) {
+                (Some(first), Some(last)) => (index[first], index[last]),
+                _ => continue,
+            };
+            for value in live_in[&bb].iter() {
+                let interval = intervals.get_mut(value).unwrap();
+                interval.start = interval.start.min(first);
+                interval.end = interval.end.max(first);
+            }
+            for value in &params[&bb] {
+                let interval = intervals.get_mut(value).unwrap();
+                interval.end = interval.end.max(first);
+            }
+            for value in &live_out[&bb] {
+                let interval = intervals.get_mut(value).unwrap();
+                interval.end = interval.end.max(last);
+            }
+            for &value in &insts[&bb] {
+                for operand in operands(value) {
+                    let interval = intervals.get_mut(&operand).unwrap();
+                    interval.end = interval.end.max(index[&value]);
+                    uses.get_mut(&operand).unwrap().push(index[&value]);
+                }
+            }
+        }
+        for positions in uses.values_mut() {
+            positions.sort_unstable();
+            positions.dedup();
+        }
+
+        Self { index, params, live_in, live_out, intervals, uses }
+    }
+
+    pub fn index(&self, value: Value) -> usize {
+        self.index[&value]
+    }
+
+    /// Parameters of a block.
+    pub fn params(&self, bb: BasicBlock) -> &[Value] {
+        &self.params[&bb]
+    }
+
+    // the allocators only need live-out sets and intervals
+    #[allow(dead_code)]
+    pub fn live_in(&self, bb: BasicBlock) -> &HashSet<Value> {
+        &self.live_in[&bb]
+    }
+
+    pub fn live_out(&self, bb: BasicBlock) -> &HashSet<Value> {
+        &self.live_out[&bb]
+    }
+
+    pub fn interval(&self, value: Value) -> Interval {
+        self.intervals[&value]
+    }
+
+    /// Position of the first use of a value at or after `point`.
+    pub fn next_use(&self, value: Value, point: usize) -> Option<usize> {
+        let positions = &self.uses[&value];
+        positions.get(positions.partition_point(|&position| position < point)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use koopa::front::Driver;
+    use koopa::ir::Program;
+
+    /// A loop whose body uses `%x`, defined before it.
+    const LOOP: &str = "\
+fun @f(@n: i32): i32 {
+%entry:
+  %x = add @n, 1
+  jump %header(0)
+%header(%i: i32):
+  %c = lt %i, @n
+  br %c, %body, %end
+%body:
+  %y = add %i, %x
+  jump %header(%y)
+%end:
+  ret %i
+}
+";
+
+    fn value(func: &FunctionData, name: &str) -> Value {
+        func.dfg().values().iter().find(|(_, data)| data.name().as_deref() == Some(name)).map(|(&value, _)| value).unwrap()
+    }
+
+    fn block(func: &FunctionData, name: &str) -> BasicBlock {
+        func.dfg().bbs().iter().find(|(_, data)| data.name().as_deref() == Some(name)).map(|(&bb, _)| bb).unwrap()
+    }
+
+    #[test]
+    fn back_edge() {
+        let program: Program = Driver::from(LOOP).generate_program().unwrap();
+        let func = program.func(*program.func_layout().first().unwrap());
+        let liveness = Liveness::new(func);
+        let (x, i, y) = (value(func, "%x"), value(func, "%i"), value(func, "%y"));
+        let (header, body, end) = (block(func, "%header"), block(func, "%body"), block(func, "%end"));
+
+        // `%x` is used again in the next iteration, so the back edge keeps it live through the header
+        assert!(liveness.live_in(header).contains(&x));
+        assert!(liveness.live_in(body).contains(&x));
+        assert!(liveness.live_out(body).contains(&x));
+        assert!(!liveness.live_in(end).contains(&x));
+        // parameters of a block are defined in it, and its arguments die on the edge
+        assert!(!liveness.live_in(header).contains(&i));
+        assert!(liveness.live_in(end).contains(&i));
+        assert!(!liveness.live_out(body).contains(&y));
+
+        let last = liveness.index(*func.layout().bbs().node(&body).unwrap().insts().back_key().unwrap());
+        assert!(liveness.interval(x).end >= last);
+    }
+}
@@ -0,0 +1,75 @@
+//! # Loops
+//!
+//! In this file, we find natural loops of a function.
+//! An edge whose target dominates its source is a back edge, its target is the header of a loop,
+//! and the loop contains the blocks reaching the source without passing the header.
+//! Loops sharing a header are merged into one, and inner loops come before the loops containing them.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use koopa::ir::BasicBlock;
+use std::collections::{ HashMap, HashSet };
+
+pub struct Loop {
+    pub header: BasicBlock,
+    /// Blocks of the loop, the header and blocks of inner loops included.
+    pub blocks: HashSet<BasicBlock>,
+}
+
+pub struct Loops {
+    /// Loops from inner to outer, an inner loop is always before loops containing it.
+    loops: Vec<Loop>,
+    depth: HashMap<BasicBlock, usize>,
+}
+
+impl Loops {
+    pub fn new(cfg: &Cfg, dom: &Dominators) -> Self {
+        let mut headers: Vec<BasicBlock> = vec![];
+        let mut bodies: HashMap<BasicBlock, HashSet<BasicBlock>> = HashMap::new();
+        for &bb in dom.order() {
+            for &succ in cfg.succs(bb) {
+                if !dom.dominates(succ, bb) {
+                    continue;
+                }
+                if !bodies.contains_key(&succ) {
+                    headers.push(succ);
+                }
+                let body = bodies.entry(succ).or_insert_with(|| HashSet::from([succ]));
+
+                let mut worklist = vec![bb];
+                while let Some(block) = worklist.pop() {
+                    if body.insert(block) {
+                        worklist.extend(cfg.preds(block).iter().filter(|&&pred| dom.is_reachable(pred)));
+                    }
+                }
+            }
+        }
+
+        let mut depth: HashMap<BasicBlock, usize> = HashMap::new();
+        for body in bodies.values() {
+            for &bb in body {
+                *depth.entry(bb).or_default() += 1;
+            }
+        }
+
+        // a loop inside another has fewer blocks, so it comes first
+        headers.sort_by_key(|header| bodies[header].len());
+        let loops: Vec<Loop> = headers.iter().map(|&header| Loop {
+            header,
+            blocks: bodies.remove(&header).unwrap(),
+        }).collect();
+
+        Self { loops, depth }
+    }
+
+    /// Loops from inner to outer.
+    pub fn loops(&self) -> &[Loop] {
+        &self.loops
+    }
+
+    /// Number of loops containing the block, zero outside loops.
+    pub fn depth(&self, bb: BasicBlock) -> usize {
+        self.depth.get(&bb).copied().unwrap_or(0)
+    }
+}
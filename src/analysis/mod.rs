@@ -0,0 +1,14 @@
+//! # Analysis
+//!
+//! This module provides analyses over the Koopa IR of a function.
+//! They don't change the program, passes and the backend query them.
+//! * `cfg`: successors and predecessors of basic blocks.
+//! * `liveness`: live-in/live-out sets, live intervals and uses of values.
+//! * `dom`: dominator tree.
+//! * `loops`: natural loops, their nesting and loop depth of blocks.
+//!
+
+pub mod cfg;
+pub mod liveness;
+pub mod dom;
+pub mod loops;
@@ -0,0 +1,431 @@
+//! # Coloring
+//!
+//! In this file, we implement the graph-coloring register allocator,
+//! the iterated register coalescing by George and Appel.
+//!
+//! Registers are precolored nodes of the interference graph, a value which can't live in a register
+//! because of calls or arguments interferes with it.
+//! Moves between values and `a0` at calls and returns, arguments and parameters of the function,
+//! and arguments and parameters of blocks are coalesced when it's safe.
+//! A spilled value simply lives in a stack slot, the generator reaches it through scratch registers,
+//! so the graph never needs to be built again.
+//!
+
+use crate::asm::alloc::{ Allocate, Clobbers, candidates };
+use crate::asm::register::{ Registers, Class };
+use crate::asm::scope::{ Scope, Entry };
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use crate::analysis::loops::Loops;
+use koopa::ir::{ FunctionData, Value, ValueKind };
+use std::collections::{ BTreeSet, HashMap, HashSet };
+
+pub struct Coloring;
+
+impl Allocate for Coloring {
+    fn allocate(&self, func: &FunctionData, scope: &mut Scope) -> Vec<&'static str> {
+        let registers = Registers::new();
+        let k = registers.registers().len();
+        let a0 = registers.registers().iter().position(|register| register.name == "a0").unwrap();
+        let clobbers = Clobbers::new(func, scope);
+
+        let values = candidates(func, scope);
+        let node: HashMap<Value, usize> = values.iter().enumerate().map(|(i, &value)| (value, k + i)).collect();
+        let mut graph = Graph::new(k, k + values.len());
+
+        let cfg = Cfg::new(func);
+        let loops = Loops::new(&cfg, &Dominators::new(&cfg));
+        let mut costs = vec![0.0; k + values.len()];
+
+        let liveness = scope.liveness();
+        for (&bb, block) in func.layout().bbs() {
+            // uses in deeper loops are executed more times
+            let weight = 10f64.powi(loops.depth(bb).min(8) as i32);
+            let mut live: HashSet<usize> = liveness.live_out(bb).iter().filter_map(|value| node.get(value).copied()).collect();
+            let insts: Vec<Value> = block.insts().keys().copied().collect();
+            for &inst in insts.iter().rev() {
+                let data = func.dfg().value(inst);
+                if let Some(&def) = node.get(&inst) {
+                    for &other in &live {
+                        graph.add_edge(def, other);
+                    }
+                    live.remove(&def);
+                    costs[def] += weight;
+                }
+                for used in data.kind().value_uses() {
+                    if let Some(&used) = node.get(&used) {
+                        live.insert(used);
+                        costs[used] += weight;
+                    }
+                }
+
+                match data.kind() {
+                    ValueKind::Return(ret) => {
+                        if let Some(&value) = ret.value().as_ref().and_then(|value| node.get(value)) {
+                            graph.add_move(value, a0);
+                        }
+                    }
+                    ValueKind::Call(_) => {
+                        if let Some(&value) = node.get(&inst) {
+                            graph.add_move(value, a0);
+                        }
+                    }
+                    ValueKind::Jump(jump) => {
+                        add_moves(&mut graph, &node, func.dfg().bb(jump.target()).params(), jump.args());
+                    }
+                    ValueKind::Branch(branch) => {
+                        add_moves(&mut graph, &node, func.dfg().bb(branch.true_bb()).params(), branch.true_args());
+                        add_moves(&mut graph, &node, func.dfg().bb(branch.false_bb()).params(), branch.false_args());
+                    }
+                    _ => {}
+                }
+            }
+
+            // parameters are defined together at the start of the block
+            let mut params: Vec<usize> = liveness.params(bb).iter().map(|param| node[param]).collect();
+            if Some(bb) == func.layout().entry_bb() {
+                params.extend(func.params().iter().map(|param| node[param]));
+            }
+            for &param in &params {
+                for &other in live.iter().chain(params.iter()) {
+                    graph.add_edge(param, other);
+                }
+            }
+        }
+
+        // arguments of the function come in argument registers
+        for (i, param) in func.params().iter().enumerate().take(8) {
+            graph.add_move(node[param], a0 + i);
+        }
+
+        for &value in &values {
+            let interval = liveness.interval(value);
+            for (register, data) in registers.registers().iter().enumerate() {
+                if !clobbers.allows(interval, data.class) {
+                    graph.add_edge(node[&value], register);
+                }
+            }
+        }
+
+        let colors = graph.color(&costs);
+
+        let mut saved: Vec<&'static str> = vec![];
+        for &value in &values {
+            match colors[node[&value]] {
+                Some(color) => {
+                    let register = &registers.registers()[color];
+                    if register.class == Class::Saved && !saved.contains(&register.name) {
+                        saved.push(register.name);
+                    }
+                    scope.new_value(value, Entry::Register(register.name.to_string()));
+                }
+                None => {
+                    let slot = scope.new_slot();
+                    scope.new_value(value, Entry::Slot(slot));
+                }
+            }
+        }
+        // keep the order of saving stable
+        saved.sort_by_key(|name| registers.registers().iter().position(|register| register.name == *name));
+        saved
+    }
+}
+
+fn add_moves(graph: &mut Graph, node: &HashMap<Value, usize>, params: &[Value], args: &[Value]) {
+    for (param, arg) in params.iter().zip(args) {
+        if let Some(&arg) = node.get(arg) {
+            graph.add_move(node[param], arg);
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Precolored,
+    Initial,
+    Simplify,
+    Freeze,
+    Spill,
+    Stack,
+    Coalesced,
+}
+
+/// Interference graph, nodes below `k` are registers and the others are values.
+struct Graph {
+    k: usize,
+    state: Vec<State>,
+    adj_set: HashSet<(usize, usize)>,
+    adj_list: Vec<Vec<usize>>,
+    degree: Vec<usize>,
+    alias: Vec<usize>,
+
+    moves: Vec<(usize, usize)>,
+    move_list: Vec<BTreeSet<usize>>,
+    worklist_moves: BTreeSet<usize>,
+    active_moves: BTreeSet<usize>,
+
+    simplify: BTreeSet<usize>,
+    freeze: BTreeSet<usize>,
+    spill: BTreeSet<usize>,
+    stack: Vec<usize>,
+}
+
+impl Graph {
+    fn new(k: usize, nodes: usize) -> Self {
+        Self {
+            k,
+            state: (0..nodes).map(|node| if node < k { State::Precolored } else { State::Initial }).collect(),
+            adj_set: HashSet::new(),
+            adj_list: vec![vec![]; nodes],
+            degree: (0..nodes).map(|node| if node < k { usize::MAX } else { 0 }).collect(),
+            alias: (0..nodes).collect(),
+
+            moves: vec![],
+            move_list: vec![BTreeSet::new(); nodes],
+            worklist_moves: BTreeSet::new(),
+            active_moves: BTreeSet::new(),
+
+            simplify: BTreeSet::new(),
+            freeze: BTreeSet::new(),
+            spill: BTreeSet::new(),
+            stack: vec![],
+        }
+    }
+
+    fn is_precolored(&self, node: usize) -> bool {
+        node < self.k
+    }
+
+    fn add_edge(&mut self, u: usize, v: usize) {
+        if u == v || self.adj_set.contains(&(u, v)) {
+            return;
+        }
+        self.adj_set.insert((u, v));
+        self.adj_set.insert((v, u));
+        if !self.is_precolored(u) {
+            self.adj_list[u].push(v);
+            self.degree[u] += 1;
+        }
+        if !self.is_precolored(v) {
+            self.adj_list[v].push(u);
+            self.degree[v] += 1;
+        }
+    }
+
+    fn add_move(&mut self, u: usize, v: usize) {
+        let id = self.moves.len();
+        self.moves.push((u, v));
+        self.move_list[u].insert(id);
+        self.move_list[v].insert(id);
+        self.worklist_moves.insert(id);
+    }
+
+    fn adjacent(&self, node: usize) -> Vec<usize> {
+        self.adj_list[node].iter()
+            .copied()
+            .filter(|&adj| !matches!(self.state[adj], State::Stack | State::Coalesced))
+            .collect()
+    }
+
+    fn node_moves(&self, node: usize) -> Vec<usize> {
+        self.move_list[node].iter()
+            .copied()
+            .filter(|id| self.active_moves.contains(id) || self.worklist_moves.contains(id))
+            .collect()
+    }
+
+    fn move_related(&self, node: usize) -> bool {
+        !self.node_moves(node).is_empty()
+    }
+
+    fn set_state(&mut self, node: usize, state: State) {
+        match self.state[node] {
+            State::Simplify => { self.simplify.remove(&node); }
+            State::Freeze => { self.freeze.remove(&node); }
+            State::Spill => { self.spill.remove(&node); }
+            _ => {}
+        }
+        match state {
+            State::Simplify => { self.simplify.insert(node); }
+            State::Freeze => { self.freeze.insert(node); }
+            State::Spill => { self.spill.insert(node); }
+            State::Stack => self.stack.push(node),
+            _ => {}
+        }
+        self.state[node] = state;
+    }
+
+    /// Color value nodes, `None` for values that must be spilled.
+    fn color(mut self, costs: &[f64]) -> Vec<Option<usize>> {
+        for node in self.k..self.state.len() {
+            let state = if self.degree[node] >= self.k {
+                State::Spill
+            }
+            else if self.move_related(node) {
+                State::Freeze
+            }
+            else {
+                State::Simplify
+            };
+            self.set_state(node, state);
+        }
+
+        loop {
+            if let Some(&node) = self.simplify.iter().next() {
+                self.simplify_node(node);
+            }
+            else if let Some(&id) = self.worklist_moves.iter().next() {
+                self.coalesce(id);
+            }
+            else if let Some(&node) = self.freeze.iter().next() {
+                self.set_state(node, State::Simplify);
+                self.freeze_moves(node);
+            }
+            else if !self.spill.is_empty() {
+                // the cheapest value to keep in stack, considering how many values it blocks
+                let node = *self.spill.iter()
+                    .min_by(|&&a, &&b| {
+                        let a = costs[a] / self.degree[a] as f64;
+                        let b = costs[b] / self.degree[b] as f64;
+                        a.total_cmp(&b)
+                    })
+                    .unwrap();
+                self.set_state(node, State::Simplify);
+                self.freeze_moves(node);
+            }
+            else {
+                break;
+            }
+        }
+
+        self.assign_colors()
+    }
+
+    fn simplify_node(&mut self, node: usize) {
+        self.set_state(node, State::Stack);
+        for adj in self.adjacent(node) {
+            self.decrement_degree(adj);
+        }
+    }
+
+    fn decrement_degree(&mut self, node: usize) {
+        if self.is_precolored(node) {
+            return;
+        }
+        let degree = self.degree[node];
+        self.degree[node] -= 1;
+        if degree == self.k {
+            let mut nodes = self.adjacent(node);
+            nodes.push(node);
+            self.enable_moves(&nodes);
+            if self.state[node] == State::Spill {
+                let state = if self.move_related(node) { State::Freeze } else { State::Simplify };
+                self.set_state(node, state);
+            }
+        }
+    }
+
+    fn enable_moves(&mut self, nodes: &[usize]) {
+        for &node in nodes {
+            for id in self.node_moves(node) {
+                if self.active_moves.remove(&id) {
+                    self.worklist_moves.insert(id);
+                }
+            }
+        }
+    }
+
+    fn alias(&self, node: usize) -> usize {
+        let mut node = node;
+        while self.state[node] == State::Coalesced {
+            node = self.alias[node];
+        }
+        node
+    }
+
+    fn coalesce(&mut self, id: usize) {
+        self.worklist_moves.remove(&id);
+        let (x, y) = self.moves[id];
+        let (x, y) = (self.alias(x), self.alias(y));
+        let (u, v) = if self.is_precolored(y) { (y, x) } else { (x, y) };
+
+        if u == v {
+            self.add_work_list(u);
+        }
+        else if self.is_precolored(v) || self.adj_set.contains(&(u, v)) {
+            self.add_work_list(u);
+            self.add_work_list(v);
+        }
+        else if (self.is_precolored(u) && self.adjacent(v).iter().all(|&t| self.ok(t, u)))
+            || (!self.is_precolored(u) && self.conservative(&self.adjacent(u), &self.adjacent(v))) {
+            self.combine(u, v);
+            self.add_work_list(u);
+        }
+        else {
+            self.active_moves.insert(id);
+        }
+    }
+
+    fn add_work_list(&mut self, node: usize) {
+        if !self.is_precolored(node) && !self.move_related(node) && self.degree[node] < self.k && self.state[node] == State::Freeze {
+            self.set_state(node, State::Simplify);
+        }
+    }
+
+    fn ok(&self, t: usize, r: usize) -> bool {
+        self.degree[t] < self.k || self.is_precolored(t) || self.adj_set.contains(&(t, r))
+    }
+
+    /// Briggs: the combined node has fewer than `k` neighbors of significant degree.
+    fn conservative(&self, u: &[usize], v: &[usize]) -> bool {
+        let nodes: HashSet<usize> = u.iter().chain(v.iter()).copied().collect();
+        nodes.into_iter().filter(|&node| self.degree[node] >= self.k).count() < self.k
+    }
+
+    fn combine(&mut self, u: usize, v: usize) {
+        self.set_state(v, State::Coalesced);
+        self.alias[v] = u;
+        let moves = self.move_list[v].clone();
+        self.move_list[u].extend(moves);
+        self.enable_moves(&[v]);
+        for t in self.adjacent(v) {
+            self.add_edge(t, u);
+            self.decrement_degree(t);
+        }
+        if !self.is_precolored(u) && self.degree[u] >= self.k && self.state[u] == State::Freeze {
+            self.set_state(u, State::Spill);
+        }
+    }
+
+    fn freeze_moves(&mut self, node: usize) {
+        for id in self.node_moves(node) {
+            let (x, y) = self.moves[id];
+            let v = if self.alias(y) == self.alias(node) { self.alias(x) } else { self.alias(y) };
+            self.active_moves.remove(&id);
+            self.worklist_moves.remove(&id);
+            if !self.is_precolored(v) && !self.move_related(v) && self.degree[v] < self.k && self.state[v] == State::Freeze {
+                self.set_state(v, State::Simplify);
+            }
+        }
+    }
+
+    fn assign_colors(&mut self) -> Vec<Option<usize>> {
+        let mut colors: Vec<Option<usize>> = (0..self.state.len()).map(|node| if node < self.k { Some(node) } else { None }).collect();
+        while let Some(node) = self.stack.pop() {
+            let mut available = vec![true; self.k];
+            for &adj in &self.adj_list[node] {
+                if let Some(color) = colors[self.alias(adj)] {
+                    available[color] = false;
+                }
+            }
+            // registers are ordered so that the cheapest to use comes first
+            colors[node] = available.iter().position(|&available| available);
+        }
+        for node in self.k..self.state.len() {
+            if self.state[node] == State::Coalesced {
+                colors[node] = colors[self.alias(node)];
+            }
+        }
+        colors
+    }
+}
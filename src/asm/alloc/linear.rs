@@ -0,0 +1,80 @@
+//! # Linear
+//!
+//! In this file, we implement the linear-scan register allocator.
+//! Values are visited by the start of their live intervals,
+//! and when no register is free, the value whose next use is farthest is spilled.
+//!
+
+use crate::asm::alloc::{ Allocate, Clobbers, candidates };
+use crate::asm::register::{ Registers, Class };
+use crate::asm::scope::{ Scope, Entry };
+use koopa::ir::{ FunctionData, Value };
+
+pub struct LinearScan;
+
+impl Allocate for LinearScan {
+    fn allocate(&self, func: &FunctionData, scope: &mut Scope) -> Vec<&'static str> {
+        let registers = Registers::new();
+        let clobbers = Clobbers::new(func, scope);
+
+        let mut values = candidates(func, scope);
+        values.sort_by_key(|value| scope.liveness().interval(*value).start);
+
+        let mut active: Vec<(Value, usize)> = vec![];
+        let mut free: Vec<bool> = vec![true; registers.registers().len()];
+        let mut saved: Vec<&'static str> = vec![];
+        for value in values {
+            let interval = scope.liveness().interval(value);
+
+            // intervals ended before this one gives back their registers
+            active.retain(|(active, register)| {
+                let expired = scope.liveness().interval(*active).end < interval.start;
+                if expired {
+                    free[*register] = true;
+                }
+                !expired
+            });
+
+            let allowed = |register: usize| clobbers.allows(interval, registers.registers()[register].class);
+            let chosen = match (0..free.len()).find(|&register| free[register] && allowed(register)) {
+                Some(register) => Some(register),
+                None => {
+                    // spill the value used farthest in the future, maybe the current one
+                    let next_use = |value: Value| scope.liveness().next_use(value, interval.start).unwrap_or(usize::MAX);
+                    let victim = active.iter()
+                        .enumerate()
+                        .filter(|(_, (_, register))| allowed(*register))
+                        .max_by_key(|(_, (value, _))| next_use(*value))
+                        .filter(|(_, (victim, _))| next_use(*victim) > next_use(value))
+                        .map(|(position, _)| position);
+                    match victim {
+                        Some(position) => {
+                            let (victim, register) = active.remove(position);
+                            let slot = scope.new_slot();
+                            scope.new_value(victim, Entry::Slot(slot));
+                            Some(register)
+                        }
+                        None => None,
+                    }
+                }
+            };
+
+            match chosen {
+                Some(register) => {
+                    let register_data = &registers.registers()[register];
+                    if register_data.class == Class::Saved && !saved.contains(&register_data.name) {
+                        saved.push(register_data.name);
+                    }
+                    free[register] = false;
+                    active.push((value, register));
+                    scope.new_value(value, Entry::Register(register_data.name.to_string()));
+                }
+                None => {
+                    let slot = scope.new_slot();
+                    scope.new_value(value, Entry::Slot(slot));
+                }
+            }
+        }
+        saved
+    }
+}
@@ -0,0 +1,127 @@
+//! # Alloc
+//!
+//! In this module, we decide where every value of a function lives before generating its code.
+//! An allocator gives each value a register or a slot in stack, and records it in the scope.
+//!
+//! Two allocators are provided, the linear scan is fast and used by default,
+//! and the graph coloring with coalescing gives better code with `-O2`.
+//!
+//! Registers clobbered by calls can't hold a value living across a call,
+//! and argument registers can't hold a value passed to a call, since they are overwritten when passing arguments.
+//! Parameters of the function are moved out of argument registers at the entry, like any other value they can live anywhere.
+//!
+
+mod linear;
+mod coloring;
+
+pub use linear::LinearScan;
+pub use coloring::Coloring;
+
+use crate::asm::scope::{ Scope, Entry };
+use crate::asm::register::Class;
+use crate::analysis::liveness::Interval;
+use crate::tools::get_size_form_ty;
+use koopa::ir::{ FunctionData, TypeKind, Value, ValueKind };
+
+pub trait Allocate {
+    /// Give every value of the function an entry, return the callee-saved registers used.
+    fn allocate(&self, func: &FunctionData, scope: &mut Scope) -> Vec<&'static str>;
+}
+
+/// The allocator chosen by options.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Allocator {
+    LinearScan,
+    Coloring,
+}
+
+impl Allocate for Allocator {
+    fn allocate(&self, func: &FunctionData, scope: &mut Scope) -> Vec<&'static str> {
+        match self {
+            Allocator::LinearScan => LinearScan.allocate(func, scope),
+            Allocator::Coloring => Coloring.allocate(func, scope),
+        }
+    }
+}
+
+/// Values which need a register or a slot, parameters of the function first, then in layout order.
+/// Stack objects, constants and addresses at constant offsets of stack objects are settled here,
+/// they never take a register or a slot, and neither do comparisons done by branches.
+pub fn candidates(func: &FunctionData, scope: &mut Scope) -> Vec<Value> {
+    let mut values = func.params().to_vec();
+    for (&bb, node) in func.layout().bbs() {
+        values.extend(func.dfg().bb(bb).params());
+        for &value in node.insts().keys() {
+            let data = func.dfg().value(value);
+            match data.kind() {
+                ValueKind::Alloc(_) => {}
+                ValueKind::Integer(integer) if integer.value() == 0 => scope.new_value(value, Entry::Register("x0".to_string())),
+                ValueKind::Integer(integer) => scope.new_value(value, Entry::Imm(integer.value())),
+                _ if data.ty().is_unit() || scope.is_fused(&value) => {}
+                _ => match frame_offset(func, scope, value) {
+                    Some(slot) => scope.new_value(value, Entry::Frame(slot)),
+                    None => values.push(value),
+                },
+            }
+        }
+    }
+    values
+}
+
+/// The slot a pointer points to, if it's a stack object or a constant offset of one.
+fn frame_offset(func: &FunctionData, scope: &Scope, value: Value) -> Option<usize> {
+    let (src, index) = match func.dfg().value(value).kind() {
+        ValueKind::GetElemPtr(gep) => (gep.src(), gep.index()),
+        ValueKind::GetPtr(get_ptr) => (get_ptr.src(), get_ptr.index()),
+        _ => return None,
+    };
+    // globals are not in the frame, and a source after its user in layout is not settled yet
+    let slot = match scope.get(&src) {
+        Some(Entry::Frame(slot)) if func.dfg().values().contains_key(&src) => *slot,
+        _ => return None,
+    };
+    let index = match func.dfg().value(index).kind() {
+        ValueKind::Integer(integer) => integer.value() as i64,
+        _ => return None,
+    };
+    let stride = match (func.dfg().value(value).kind(), func.dfg().value(src).ty().kind()) {
+        (ValueKind::GetElemPtr(_), TypeKind::Pointer(base)) => match base.kind() {
+            TypeKind::Array(elem, _) => get_size_form_ty(elem),
+            _ => return None,
+        },
+        (_, TypeKind::Pointer(base)) => get_size_form_ty(base),
+        _ => return None,
+    };
+    usize::try_from(slot as i64 + index * stride as i64).ok()
+}
+
+/// Where registers are overwritten by the function itself.
+pub struct Clobbers {
+    /// Positions of calls.
+    calls: Vec<usize>,
+}
+
+impl Clobbers {
+    pub fn new(func: &FunctionData, scope: &Scope) -> Self {
+        let mut calls = vec![];
+        for (_, node) in func.layout().bbs() {
+            for &value in node.insts().keys() {
+                if let ValueKind::Call(_) = func.dfg().value(value).kind() {
+                    calls.push(scope.liveness().index(value));
+                }
+            }
+        }
+        Self { calls }
+    }
+
+    /// Whether a value living in the interval can be held by a register of the class.
+    pub fn allows(&self, interval: Interval, class: Class) -> bool {
+        let across = self.calls.iter().any(|&call| interval.start < call && call < interval.end);
+        let passed = self.calls.iter().any(|&call| interval.start < call && call == interval.end);
+        match class {
+            Class::Saved => true,
+            Class::Temporary => !across,
+            Class::Argument => !across && !passed,
+        }
+    }
+}
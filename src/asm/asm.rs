@@ -1,17 +1,21 @@
 //! # Asm
-//! 
+//!
 //! In this file, we define core part of my compiler.
 //! We use the program and information generated by the `mem` module to generate assembly code.
-//! 
-#![allow(unused_assignments)]
+//! Where each value lives is decided by a register allocator before the code of a function is generated.
+//!
 
-use crate::asm::scope::{ Scope, Entry, new_register };
+use crate::asm::scope::{ Scope, Entry };
 use crate::asm::writer::Writer;
-use crate::tools::get_size_form_ty;
+use crate::asm::alloc::Allocate;
+use crate::asm::reduce::{ self, Inst, Reg };
+use crate::tools::{ get_size_form_ty, tail_calls };
 use crate::mem::info::Info;
-use koopa::ir::{ Program, FunctionData, Value, ValueKind, TypeKind, BinaryOp };
+use crate::analysis::liveness::Liveness;
+use koopa::ir::{ Program, FunctionData, BasicBlock, Value, ValueKind, TypeKind, BinaryOp };
 use koopa::ir::entities::ValueData;
-use koopa::ir::values::{ Integer, Return, Binary, Alloc, Load, Store, Branch, Jump, Call, GlobalAlloc, GetElemPtr, GetPtr };
+use koopa::ir::values::{ Return, Binary, Alloc, Load, Store, Branch, Jump, Call, GlobalAlloc, GetElemPtr, GetPtr };
+use std::collections::HashSet;
 
 pub trait Asm {
     fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info);
@@ -44,165 +48,87 @@ impl Asm for FunctionData {
             return;
         }
 
-        let mut slots = 0;
         let mut max_args = 0;
+        scope.set_caller(false);
         for (_, value) in self.dfg().values() {
-            match value.kind() {
-                ValueKind::Integer(_) => slots += 1,
-                ValueKind::Alloc(_) => {
-                    match value.ty().kind() {
+            if let ValueKind::Call(call) = value.kind() {
+                scope.set_caller(true);
+                max_args = max_args.max(call.args().len());
+            }
+        }
+        // the bottom of the frame is for arguments passed in stack
+        scope.set_used_slots(max_args.max(8) - 8);
+
+        // objects allocated in stack
+        for (_, node) in self.layout().bbs() {
+            for value in node.insts().keys() {
+                let data = self.dfg().value(*value);
+                if let ValueKind::Alloc(_) = data.kind() {
+                    let size = match data.ty().kind() {
                         TypeKind::Pointer(base) => {
                             match base.kind() {
-                                TypeKind::Int32 => slots += 1,
-                                TypeKind::Array(_, _) => slots += get_size_form_ty(base),
-                                TypeKind::Pointer(_) => slots += 1,
+                                TypeKind::Int32 => 1,
+                                TypeKind::Array(_, _) => get_size_form_ty(base),
+                                TypeKind::Pointer(_) => 1,
                                 _ => panic!("in my compiler, only allocat int32, array and pointer")
                             }
                         }
                         _ => panic!("we expect allocate value should be pointer type")
-                    }
-                }
-                ValueKind::Load(_) => slots += 1,
-                ValueKind::Binary(_) => slots += 1,
-                ValueKind::Call(call) => {
-                    slots += 2;
-                    scope.set_caller(true);
-                    max_args = max_args.max(call.args().len());
+                    };
+                    let slot = scope.new_slots(size);
+                    scope.new_value(*value, Entry::Frame(slot));
                 }
-                ValueKind::GetElemPtr(_) => slots += 1,
-                ValueKind::GetPtr(_) => slots += 1,
-                _ => {}
             }
         }
 
-        slots = slots + max_args.max(8) - 8;
+        // arguments in stack would be in the frame given up, so tail calls take at most eight
+        let tails = tail_calls(self).into_iter()
+            .filter(|(call, _)| matches!(self.dfg().value(*call).kind(), ValueKind::Call(call) if call.args().len() <= 8))
+            .flat_map(|(call, ret)| [call, ret])
+            .collect();
+        scope.set_tails(tails);
+        scope.set_fused(fused(self));
+
+        scope.set_liveness(Some(Liveness::new(self)));
+        let saved = scope.allocator().allocate(self, scope);
+        let saved = saved.into_iter().map(|register| (register, scope.new_slot())).collect();
+        scope.set_saved(saved);
+
+        // the top of the frame is for return address
+        let mut slots = scope.used_slots() + if scope.caller() { 1 } else { 0 };
         slots = if slots % 4 == 0 { slots } else { slots + 4 - slots % 4 };
         scope.set_total_slots(slots);
-        scope.set_used_slots(max_args.max(8) - 8);
 
         let name = self.name()[1..].to_string();
         w.prologue(&name, scope);
 
-        let mut with_ret = false;
-        let mut current = 0;
+        // arguments after the eighth are in the bottom of caller's frame
+        let moves = self.params().iter().enumerate().map(|(i, param)| {
+            let src = if i < 8 { Entry::Register(format!("a{}", i)) } else { Entry::Slot(i - 8 + scope.total_slots()) };
+            (scope.value(param).clone(), src)
+        }).collect();
+        w.parallel_move(moves);
+
+        // a test folded into every branch going to it is never reached
+        let folded: HashSet<BasicBlock> = self.layout().bbs().keys().copied().filter(|bb| {
+            let users = self.dfg().bb(*bb).used_by();
+            !users.is_empty() && users.iter().all(|user| matches!(self.dfg().value(*user).kind(),
+                ValueKind::Branch(branch) if branch.false_bb() != *bb && range(self, scope, branch).is_some()))
+        }).collect();
+
         for (bb, node) in self.layout().bbs() {
+            if folded.contains(bb) {
+                continue;
+            }
             let name = self.dfg().bb(bb.clone()).name().as_ref().unwrap()[1..].to_string();
             if name != "entry" {
                 w.note(&format!("{}:", name));
             }
 
-            let mut end = false;
-            for(value, _) in node.insts() {
-                if let ValueKind::Return(_) = self.dfg().value(value.clone()).kind() {
-                    with_ret = true;
-                }
-                current = info.info(value.clone()).unwrap().birth;
+            for (value, _) in node.insts() {
                 scope.set_cur_value(Some(value.clone()));
-                let data = self.dfg().value(value.clone());
-
-                match data.kind() {
-                    ValueKind::Return(_) => end = true,
-                    ValueKind::Jump(_) => end = true,
-                    ValueKind::Branch(_) => end = true,
-                    _ => {}
-                }
-
-                if end {
-                    // because end value won't allocat register, so we can do register work before
-
-                    // after a value, all the value dead before current should be removed from registers
-                    for register in scope.registers_mut() {
-                        match register.value {
-                            Some(value) => {
-                                if info.info(value.clone()).unwrap().death <= current {
-                                    register.used = false;
-                                    register.value = None;
-                                }
-                            }
-                            None => {}
-                        }
-                    }
-
-                    // after a block, all the registers should be free and all the values in registers should be spilled
-                    let mut values: Vec<(String, Value)> = vec![];
-                    for register in scope.registers_mut() {
-                        match register.value {
-                            Some(value) => {
-                                register.used = false;
-                                register.value = None;
-                                values.push((register.name().to_string(), value.clone()));
-                            }
-                            None => {}
-                        }
-                    }
-
-                    for (name, value) in values {
-                        let slot = scope.new_slot();
-                        if slot < 512 {
-                            w.op2("sw", &name, &format!("{}(sp)", slot * 4));
-                        }
-                        else {
-                            w.op2("li", "t6", &format!("{}", slot * 4));
-                            w.op3("add", "t6", "t6", "sp");
-                            w.op2("sw", &name, "0(t6)");
-                        }
-                        scope.new_value(value, Entry::Slot(slot));
-                    }
-
-                    data.asm(program, scope, w, info);
-                    continue;
-                }
-
-                data.asm(program, scope, w, info);
-
-                // after a value, all the value dead before current should be removed from registers
-                for register in scope.registers_mut() {
-                    match register.value {
-                        Some(value) => {
-                            if info.info(value.clone()).unwrap().death <= current {
-                                register.used = false;
-                                register.value = None;
-                            }
-                        }
-                        None => {}
-                    }
-                }
+                self.dfg().value(*value).asm(program, scope, w, info);
             }
-
-            if end == false {
-                // after a block, all the registers should be free and all the values in registers should be spilled
-                let mut values: Vec<(String, Value)> = vec![];
-                for register in scope.registers_mut() {
-                    match register.value {
-                        Some(value) => {
-                            register.used = false;
-                            register.value = None;
-                            values.push((register.name().to_string(), value.clone()));
-                        }
-                        None => {}
-                    }
-                }
-
-                for (name, value) in values {
-                    let slot = scope.new_slot();
-                    if slot < 512 {
-                        w.op2("sw", &name, &format!("{}(sp)", slot * 4));
-                    }
-                    else {
-                        w.op2("li", "t6", &format!("{}", slot * 4));
-                        w.op3("add", "t6", "t6", "sp");
-                        w.op2("sw", &name, "0(t6)");
-                    }
-                    scope.new_value(value, Entry::Slot(slot));
-                }
-
-                // When in this block, no leaving value, we will give it a leaving value.
-                w.epilogue(scope);
-            }
-        }
-
-        if with_ret == false {
-            w.epilogue(scope);
         }
 
         w.line();
@@ -213,7 +139,8 @@ impl Asm for FunctionData {
 impl Asm for ValueData {
     fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
         match self.kind() {
-            ValueKind::Integer(integer) => integer.asm(program, scope, w, info),
+            // zero is always in `x0`, other constants are folded into their users or loaded by them
+            ValueKind::Integer(_) => return,
             ValueKind::Return(ret) => ret.asm(program, scope, w, info),
             ValueKind::Binary(binary) => binary.asm(program, scope, w, info),
             ValueKind::Alloc(alloc) => alloc.asm(program, scope, w, info),
@@ -231,51 +158,17 @@ impl Asm for ValueData {
     }
 }
 
-impl Asm for Integer {
-    fn asm(&self, _: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
-        w.note("# integer");
-        if self.value() == 0 {
-            scope.new_value(scope.cur_value().clone(), Entry::Register("x0".to_string()));
-        }
-        else {
-            let value = scope.cur_value().clone();
-            let current = info.info(scope.cur_value().clone()).unwrap().birth;
-            let register = new_register!(scope, w);
-            register.used = true;
-            register.stamp = current;
-            register.value = Some(value.clone());
-
-            w.op2("li", &register.name(), &format!("{}", self.value()));
-
-            let name = register.name().to_string();
-            scope.new_value(value.clone(), Entry::Register(name));
-        }
-    }
-}
-
 impl Asm for Return {
     fn asm(&self, _: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# return");
+        // the callee of a tail call returns for us
+        if scope.is_tail(scope.cur_value()) {
+            return;
+        }
         if let Some(value) = self.value() {
-            let entry = scope.value(&value).clone();
-            match entry {
-                Entry::Slot(slot) => {
-                    if slot <512 {
-                        w.op2("lw", "a0", &format!("{}(sp)", slot * 4))
-                    }
-                    else {
-                        let temporary = scope.new_register(w).name().to_string();
-                        w.op2("li", &temporary, &format!("{}", slot * 4));
-                        w.op3("add", &temporary, &temporary, "sp");
-                        w.op2("lw", "a0", &format!("0({})", temporary));
-                    }
-                }
-                Entry::Register(name) => w.op2("mv", "a0", &name),
-                Entry::Label(label) => {
-                    let temporary = scope.new_register(w);
-                    w.op2("la", temporary.name(), &label);
-                    w.op2("lw", "a0", &format!("0({})", temporary.name()));
-                }
+            let src = w.operand(scope, value, "a0");
+            if src != "a0" {
+                w.op2("mv", "a0", &src);
             }
         }
         w.epilogue(scope);
@@ -283,473 +176,419 @@ impl Asm for Return {
 }
 
 impl Asm for Binary {
-    fn asm(&self, _: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# binary");
-        let entry = scope.value(&self.lhs()).clone();
-
-        let mut lhs_flag = false;
-        let lhs = match entry {
-            Entry::Slot(slot) => {
-                let temporary = scope.new_register(w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                lhs_flag = true;
-                if slot < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", slot * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", slot * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = scope.new_register(w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                lhs_flag = true;
-                w.op2("la", &temporary, &label);
-                w.op2("lw", &temporary, &format!("0({})", temporary));
-                temporary
-            }
-        };
-        let entry = scope.value(&self.rhs()).clone();
-        let rhs = match entry {
-            Entry::Slot(slot) => {
-                let temporary = scope.new_register(w).name().to_string();
-                if slot < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", slot * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", slot * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
+        if scope.is_fused(scope.cur_value()) {
+            return;
+        }
+        if let Some((src, insts)) = reduce(program, scope, self) {
+            let src = w.operand(scope, src, "t4");
+            let dst = scope.target();
+            for inst in insts {
+                let reg = |reg: Reg| match reg {
+                    Reg::Dst => dst.as_str(),
+                    Reg::Src => src.as_str(),
+                    Reg::T1 => "t5",
+                    Reg::T2 => "t6",
+                    Reg::Zero => "x0",
+                };
+                match inst {
+                    Inst::R(op, d, a, b) => w.op3(op, reg(d), reg(a), reg(b)),
+                    Inst::I(op, d, a, imm) => w.op3(op, reg(d), reg(a), &format!("{}", imm)),
+                    Inst::Li(d, imm) => w.op2("li", reg(d), &format!("{}", imm)),
                 }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = scope.new_register(w).name().to_string();
-                w.op2("la", &temporary, &label);
-                w.op2("lw", &temporary, &format!("0({})", &temporary));
-                temporary
             }
-        };
+            w.write_back(scope, &dst);
+            return;
+        }
 
-        if lhs_flag {
-            scope.register_mut(&lhs).fixed = false;
+        if let Some((op, lhs, imm)) = immediate(scope, self) {
+            let lhs = w.operand(scope, lhs, "t4");
+            let dst = scope.target();
+            match op {
+                BinaryOp::Add => w.op3("addi", &dst, &lhs, &format!("{}", imm)),
+                BinaryOp::Sub => w.op3("addi", &dst, &lhs, &format!("{}", -imm)),
+                BinaryOp::And => w.op3("andi", &dst, &lhs, &format!("{}", imm)),
+                BinaryOp::Or => w.op3("ori", &dst, &lhs, &format!("{}", imm)),
+                BinaryOp::Xor => w.op3("xori", &dst, &lhs, &format!("{}", imm)),
+                BinaryOp::Eq => {
+                    w.op3("xori", &dst, &lhs, &format!("{}", imm));
+                    w.op2("seqz", &dst, &dst);
+                }
+                BinaryOp::NotEq => {
+                    w.op3("xori", &dst, &lhs, &format!("{}", imm));
+                    w.op2("snez", &dst, &dst);
+                }
+                BinaryOp::Lt => w.op3("slti", &dst, &lhs, &format!("{}", imm)),
+                // `lhs > imm` is `!(lhs < imm + 1)`, and `lhs <= imm` is `lhs < imm + 1`
+                BinaryOp::Gt => {
+                    w.op3("slti", &dst, &lhs, &format!("{}", imm + 1));
+                    w.op3("xori", &dst, &dst, "1");
+                }
+                BinaryOp::Le => w.op3("slti", &dst, &lhs, &format!("{}", imm + 1)),
+                BinaryOp::Ge => {
+                    w.op3("slti", &dst, &lhs, &format!("{}", imm));
+                    w.op3("xori", &dst, &dst, "1");
+                }
+                _ => unreachable!(),
+            }
+            w.write_back(scope, &dst);
+            return;
         }
 
-        let value = scope.cur_value().clone();
-        let register = new_register!(scope, w);
-        register.used = true;
-        register.stamp = info.info(value.clone()).unwrap().birth;
-        register.value = Some(value.clone());
+        let lhs = w.operand(scope, self.lhs(), "t4");
+        let rhs = w.operand(scope, self.rhs(), "t5");
+        let dst = scope.target();
 
         match self.op() {
-            BinaryOp::Add => w.op3("add", register.name(), &lhs, &rhs),
-            BinaryOp::Sub => w.op3("sub", register.name(), &lhs, &rhs),
-            BinaryOp::Mul => w.op3("mul", register.name(), &lhs, &rhs),
-            BinaryOp::Div => w.op3("div", register.name(), &lhs, &rhs),
-            BinaryOp::Mod => w.op3("rem", register.name(), &lhs, &rhs),
-            BinaryOp::And => w.op3("and", register.name(), &lhs, &rhs),
-            BinaryOp::Or => w.op3("or", register.name(), &lhs, &rhs),
+            BinaryOp::Add => w.op3("add", &dst, &lhs, &rhs),
+            BinaryOp::Sub => w.op3("sub", &dst, &lhs, &rhs),
+            BinaryOp::Mul => w.op3("mul", &dst, &lhs, &rhs),
+            BinaryOp::Div => w.op3("div", &dst, &lhs, &rhs),
+            BinaryOp::Mod => w.op3("rem", &dst, &lhs, &rhs),
+            BinaryOp::And => w.op3("and", &dst, &lhs, &rhs),
+            BinaryOp::Or => w.op3("or", &dst, &lhs, &rhs),
+            BinaryOp::Xor => w.op3("xor", &dst, &lhs, &rhs),
             BinaryOp::Eq => {
-                w.op3("sub", register.name(), &lhs, &rhs);
-                w.op2("seqz", register.name(), register.name());
+                w.op3("sub", &dst, &lhs, &rhs);
+                w.op2("seqz", &dst, &dst);
             }
             BinaryOp::NotEq => {
-                w.op3("sub", register.name(), &lhs, &rhs);
-                w.op2("snez", register.name(), register.name());
+                w.op3("sub", &dst, &lhs, &rhs);
+                w.op2("snez", &dst, &dst);
             }
-            BinaryOp::Lt => w.op3("slt", register.name(), &lhs, &rhs),
-            BinaryOp::Gt => w.op3("sgt", register.name(), &lhs, &rhs),
+            BinaryOp::Lt => w.op3("slt", &dst, &lhs, &rhs),
+            BinaryOp::Gt => w.op3("sgt", &dst, &lhs, &rhs),
             BinaryOp::Le => {
-                w.op3("sgt", register.name(), &lhs, &rhs);
-                w.op2("seqz", register.name(), register.name());
+                w.op3("sgt", &dst, &lhs, &rhs);
+                w.op2("seqz", &dst, &dst);
             }
             BinaryOp::Ge => {
-                w.op3("slt", register.name(), &lhs, &rhs);
-                w.op2("seqz", register.name(), register.name());
+                w.op3("slt", &dst, &lhs, &rhs);
+                w.op2("seqz", &dst, &dst);
             }
             _ => panic!("not support this binary operation")
         }
 
-        let name = register.name().to_string();
-        scope.new_value(scope.cur_value().clone(), Entry::Register(name));
+        w.write_back(scope, &dst);
+    }
+}
+
+/// The operand and the instructions computing a multiplication, division or modulo by a constant,
+/// if it can be done without `mul`, `div` or `rem`.
+fn reduce(program: &Program, scope: &Scope, binary: &Binary) -> Option<(Value, Vec<Inst>)> {
+    let dfg = program.func(*scope.cur_func()).dfg();
+    let constant = |value: Value| match dfg.values().get(&value).map(|data| data.kind()) {
+        Some(ValueKind::Integer(integer)) => Some(integer.value()),
+        _ => None,
+    };
+    let (lhs, rhs) = (binary.lhs(), binary.rhs());
+    match binary.op() {
+        BinaryOp::Mul => match (constant(lhs), constant(rhs)) {
+            (_, Some(c)) => Some((lhs, reduce::mul(c)?)),
+            (Some(c), None) => Some((rhs, reduce::mul(c)?)),
+            _ => None,
+        },
+        BinaryOp::Div => Some((lhs, reduce::div(constant(rhs)?)?)),
+        BinaryOp::Mod => Some((lhs, reduce::rem(constant(rhs)?)?)),
+        _ => None,
+    }
+}
+
+/// Compute the address of the element `index` of `src`, elements being `size` bytes.
+fn address(scope: &Scope, w: &mut Writer, src: Value, index: Value, size: usize) {
+    let base = w.operand(scope, src, "t4");
+    let dst = scope.target();
+    let offset = scope.imm(&index).map(|index| index as i64 * size as i64).filter(|offset| (-2048..2048).contains(offset));
+    match offset {
+        Some(offset) => w.op3("addi", &dst, &base, &format!("{}", offset)),
+        None => {
+            let index = w.operand(scope, index, "t5");
+            w.op2("li", "t6", &format!("{}", size));
+            w.op3("mul", "t6", &index, "t6");
+            w.op3("add", &dst, &base, "t6");
+        }
+    }
+    w.write_back(scope, &dst);
+}
+
+/// The operation, the other operand and the constant, if the constant operand fits in an immediate.
+/// A constant on the left is swapped to the right, and comparisons are mirrored.
+fn immediate(scope: &Scope, binary: &Binary) -> Option<(BinaryOp, Value, i32)> {
+    let (lhs, rhs) = (binary.lhs(), binary.rhs());
+    let (op, lhs, imm) = match (scope.imm(&lhs), scope.imm(&rhs)) {
+        (_, Some(imm)) => (binary.op(), lhs, imm),
+        (Some(imm), None) => {
+            let op = match binary.op() {
+                BinaryOp::Lt => BinaryOp::Gt,
+                BinaryOp::Gt => BinaryOp::Lt,
+                BinaryOp::Le => BinaryOp::Ge,
+                BinaryOp::Ge => BinaryOp::Le,
+                BinaryOp::Sub => return None,
+                op => op,
+            };
+            (op, rhs, imm)
+        }
+        _ => return None,
+    };
+    match op {
+        BinaryOp::Add | BinaryOp::And | BinaryOp::Or | BinaryOp::Xor | BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::Ge => Some((op, lhs, imm)),
+        // `imm + 1` and `-imm` must fit too
+        BinaryOp::Gt | BinaryOp::Le if imm < 2047 => Some((op, lhs, imm)),
+        BinaryOp::Sub if imm > -2048 => Some((op, lhs, imm)),
+        _ => None,
     }
 }
 
 impl Asm for Alloc {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
+    fn asm(&self, _: &Program, _: &mut Scope, w: &mut Writer, _: &mut Info) {
+        // slots of stack objects are given before the function is generated
         w.note("# alloc");
-        let func = scope.cur_func().clone();
-        let value = scope.cur_value().clone();
-        let size = match program.func(func).dfg().value(value.clone()).ty().kind() {
-            TypeKind::Pointer(base) => {
-                match base.kind() {
-                    TypeKind::Int32 => 1,
-                    TypeKind::Array(_, _) => get_size_form_ty(base),
-                    TypeKind::Pointer(_) => 1,
-                    _ => panic!("in my compiler, only allocat int32, array and pointer")
-                }
-            }
-            _ => panic!("we expect allocate value should be pointer type")
-        };
-
-        let slot = scope.new_slots(size);
-        scope.new_value(value, Entry::Slot(slot));
     }
 }
 
 impl Asm for Load {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, _: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# load");
-        // if load the value jsut store, we skip load
-        let entry = scope.value(&self.src()).clone();
-        let idx = info.info(scope.cur_value().clone()).unwrap().birth;
-        if entry == scope.store_pair_mut().1 && idx == scope.store_pair_mut().2 + 1{
-            let value = scope.cur_value().clone();
-            let name = scope.store_pair_mut().0.clone();
-            let register = scope.register_mut(&name);
-            register.used = true;
-            register.stamp = idx;
-            register.value = Some(value.clone());
-
-            let register = register.name().to_string();
-            scope.new_value(value, Entry::Register(register));
-            return;
-        }
+        let dst = scope.target();
 
-        let is_addr = if self.src().is_global() {
-            match program.borrow_value(self.src()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        } else {
-            match program.func(scope.cur_func().clone()).dfg().value(self.src()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        };
-
-        let value = scope.cur_value().clone();
-        let register = new_register!(scope, w);
-        register.used = true;
-        register.stamp = info.info(value.clone()).unwrap().birth;
-        register.value = Some(value.clone());
-
-        let dst = register.name().to_string();
-        scope.new_value(value.clone(), Entry::Register(dst.clone()));
-
-        match scope.value(&self.src()) {
-            Entry::Slot(offset) => {
-                if is_addr {
-                    if (*offset) < 512 {
-                        w.op2("lw", &dst, &format!("{}(sp)", offset * 4));
-                    } else {
-                        w.op2("li", &dst, &format!("{}", offset * 4));
-                        w.op3("add", &dst, &dst, "sp");
-                        w.op2("lw", &dst, &format!("0({})", &dst));
-                    }
-                    w.op2("lw", &dst, &format!("0({})", &dst));
-                }
-                else {
-                    if (*offset) < 512 {
-                        w.op2("lw", &dst, &format!("{}(sp)", offset * 4));
-                    } else {
-                        w.op2("li", &dst, &format!("{}", offset * 4));
-                        w.op3("add", &dst, &dst, "sp");
-                        w.op2("lw", &dst, &format!("0({})", &dst));
-                    }
-                }
-            }
-            Entry::Label(label) => {
-                w.op2("la", &dst, &label);
-                w.op2("lw", &dst, &format!("0({})", &dst));
-            }
-            Entry::Register(name) => {
-                if is_addr {
-                    w.op2("lw", &dst, &format!("0({})", &name));
-                }
-                else {
-                    panic!("load from register")
-                }
+        match *scope.value(&self.src()) {
+            Entry::Frame(slot) => w.load_slot(&dst, slot),
+            _ => {
+                let src = w.operand(scope, self.src(), "t5");
+                w.op2("lw", &dst, &format!("0({})", src));
             }
         }
+
+        w.write_back(scope, &dst);
     }
 }
 
 impl Asm for Store {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, _: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# store");
-        match program.func(scope.cur_func().clone()).dfg().value(self.value()).kind() {
-            ValueKind::FuncArgRef(arg) => {
-                let offset = match scope.value(&self.dest()) {
-                    Entry::Slot(offset) => *offset,
-                    _ => panic!("should store function argument in stack")
-                };
-                if arg.index() < 8 {
-                    w.op2("sw", &format!("a{}", arg.index()), &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    let temporary = new_register!(scope, w).name().to_string();
-                    w.op2("lw", &temporary, &format!("{}(sp)", (arg.index() as usize - 8 + scope.total_slots()) * 4));
-                    w.op2("sw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                return;
-            }
-            _ => {}
-        }
-
-        let is_addr = if self.dest().is_global() {
-            match program.borrow_value(self.dest()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        } else {
-            match program.func(scope.cur_func().clone()).dfg().value(self.dest()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        };
-        
-        let src_entry = scope.value(&self.value()).clone();
-        let mut src_flag = false;
-
-        let src = match src_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                src_flag = true;
-                if offset < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", offset * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                src_flag = true;
-                w.op2("la", &temporary, &label);
-                w.op2("lw", &temporary, &format!("0({})", &temporary));
-                temporary
-            }
-        };
+        let src = w.operand(scope, self.value(), "t4");
 
-        let dst_entry = scope.value(&self.dest()).clone();
-        match dst_entry {
-            Entry::Slot(offset) => {
-                if is_addr {
-                    let temporary = new_register!(scope, w).name().to_string();
-                    if offset < 512 {
-                        w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                    }
-                    else {
-                        w.op2("li", &temporary, &format!("{}", offset * 4));
-                        w.op3("add", &temporary, &temporary, "sp");
-                        w.op2("lw", &temporary, &format!("0({})", temporary));
-                    }
-                    w.op2("sw", &src, &format!("0({})", &temporary));
-                }
-                else {
-                    if offset < 512 {
-                        w.op2("sw", &src, &format!("{}(sp)", offset * 4));
-                    }
-                    else {
-                        let temporary = new_register!(scope, w).name().to_string();
-                        w.op2("li", &temporary, &format!("{}", offset * 4));
-                        w.op3("add", &temporary, &temporary, "sp");
-                        w.op2("sw", &src, &format!("0({})", temporary));
-                    }
-                }
-            }
-            Entry::Label(label) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                w.op2("la", &temporary, &label);
-                w.op2("sw", &src, &format!("0({})", &temporary));
+        match *scope.value(&self.dest()) {
+            Entry::Frame(slot) => w.store_slot(&src, slot),
+            _ => {
+                let dst = w.operand(scope, self.dest(), "t5");
+                w.op2("sw", &src, &format!("0({})", dst));
             }
-            Entry::Register(name) => {
-                if is_addr {
-                    w.op2("sw", &src, &format!("0({})", &name));
-                }
-                else {
-                    panic!("store to register")
-                }
-            } 
-        }
-
-        if src_flag {
-            scope.register_mut(&src).fixed = false;
         }
-
-        let dst_entry = scope.value(&self.dest()).clone();
-        scope.store_pair_mut().0 = src;
-        scope.store_pair_mut().1 = dst_entry;
-        scope.store_pair_mut().2 = info.info(scope.cur_value().clone()).unwrap().birth;
     }
 }
 
 impl Asm for Branch {
     fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# branch");
-        let cond_entry = scope.value(&self.cond()).clone();
-        let cond = match cond_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                if offset < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", offset * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                w.op2("la", &temporary, &label);
-                w.op2("lw", &temporary, &format!("0({})", &temporary));
-                temporary
-            }
+        let func = program.func(*scope.cur_func());
+        // `0 <= x && x < n` goes straight to where the test of `x < n` goes if it holds
+        let (test, then_bb, then_args) = match range(func, scope, self) {
+            Some((x, n, inner)) => (Test::Below(x, n), inner.true_bb(), inner.true_args()),
+            None => (Test::Cond(self.cond()), self.true_bb(), self.true_args()),
         };
+        let then = func.dfg().bb(then_bb).name().as_ref().unwrap()[1..].to_string();
+        let els = func.dfg().bb(self.false_bb()).name().as_ref().unwrap()[1..].to_string();
+        let then_moves = block_moves(func, scope, then_bb, then_args);
+        let els_moves = block_moves(func, scope, self.false_bb(), self.false_args());
+
+        // the condition is tested before moves of either edge, which may overwrite its operands
+        if then_moves.is_empty() {
+            branch_if(func, scope, w, test, true, &then);
+            w.parallel_move(els_moves);
+            w.op1("j", &els);
+        }
+        else if els_moves.is_empty() {
+            branch_if(func, scope, w, test, false, &els);
+            w.parallel_move(then_moves);
+            w.op1("j", &then);
+        }
+        else {
+            // moves of the true edge are done out of the way
+            let edge = scope.label_mut().edge();
+            branch_if(func, scope, w, test, true, &edge);
+            w.parallel_move(els_moves);
+            w.op1("j", &els);
+            w.note(&format!("{}:", edge));
+            w.parallel_move(then_moves);
+            w.op1("j", &then);
+        }
+    }
+}
+
+/// What a branch tests.
+#[derive(Clone, Copy)]
+enum Test {
+    Cond(Value),
+    /// `0 <= x && x < n`, which is `x < n` unsigned as `n` isn't negative.
+    Below(Value, Value),
+}
+
+/// The `x` and `n` of a branch on `0 <= x` to a test of `x < n` with a constant `n` not negative,
+/// and the branch of that test. Both branches must go to the same block if they fail, and the test
+/// must compute nothing else, as it's skipped.
+fn range<'f>(func: &'f FunctionData, scope: &Scope, branch: &Branch) -> Option<(Value, Value, &'f Branch)> {
+    if !scope.is_fused(&branch.cond()) {
+        return None;
+    }
+    let x = match func.dfg().value(branch.cond()).kind() {
+        ValueKind::Binary(binary) => match (binary.op(), constant(func, binary.lhs()), constant(func, binary.rhs())) {
+            (BinaryOp::Le, Some(0), _) | (BinaryOp::Lt, Some(-1), _) => binary.rhs(),
+            (BinaryOp::Ge, _, Some(0)) | (BinaryOp::Gt, _, Some(-1)) => binary.lhs(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+
+    // constants are in the layout too, though they compute nothing
+    let insts: Vec<Value> = func.layout().bbs().node(&branch.true_bb())?.insts().keys().copied()
+        .filter(|inst| !matches!(func.dfg().value(*inst).kind(), ValueKind::Integer(_)))
+        .collect();
+    let (cond, inner) = match (insts.as_slice(), insts.last().map(|inst| func.dfg().value(*inst).kind())) {
+        ([cond, _], Some(ValueKind::Branch(inner))) if inner.cond() == *cond => (*cond, inner),
+        _ => return None,
+    };
+    if func.dfg().value(cond).used_by().len() != 1 || inner.false_bb() != branch.false_bb() || inner.false_args() != branch.false_args() {
+        return None;
+    }
+    let n = match func.dfg().value(cond).kind() {
+        ValueKind::Binary(binary) => match binary.op() {
+            BinaryOp::Lt if binary.lhs() == x => binary.rhs(),
+            BinaryOp::Gt if binary.rhs() == x => binary.lhs(),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    // `x < n` unsigned holds for negative `x` if `n` is negative too
+    constant(func, n).filter(|&n| n >= 0).map(|_| (x, n, inner))
+}
 
-        let then = program.func(scope.cur_func().clone()).dfg().bb(self.true_bb()).name().as_ref().unwrap()[1..].to_string();
-        let els = program.func(scope.cur_func().clone()).dfg().bb(self.false_bb()).name().as_ref().unwrap()[1..].to_string();
+/// The value of a constant in the function.
+fn constant(func: &FunctionData, value: Value) -> Option<i32> {
+    match func.dfg().values().get(&value)?.kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    }
+}
 
-        w.op2("bnez", &cond, &then);
-        w.op1("j", &els);
+/// Jump to the label if the test is `expected`, with the comparison itself if it's fused.
+fn branch_if(func: &FunctionData, scope: &Scope, w: &mut Writer, test: Test, expected: bool, label: &str) {
+    let cond = match test {
+        Test::Cond(cond) => cond,
+        Test::Below(x, n) => {
+            let x = w.operand(scope, x, "t4");
+            let n = w.operand(scope, n, "t5");
+            w.op3(if expected { "bltu" } else { "bgeu" }, &x, &n, label);
+            return;
+        }
+    };
+    if !scope.is_fused(&cond) {
+        let cond = w.operand(scope, cond, "t4");
+        w.op2(if expected { "bnez" } else { "beqz" }, &cond, label);
+        return;
     }
+
+    let binary = match func.dfg().value(cond).kind() {
+        ValueKind::Binary(binary) => binary,
+        _ => unreachable!(),
+    };
+    let op = match (binary.op(), expected) {
+        (op, true) => op,
+        (BinaryOp::Eq, false) => BinaryOp::NotEq,
+        (BinaryOp::NotEq, false) => BinaryOp::Eq,
+        (BinaryOp::Lt, false) => BinaryOp::Ge,
+        (BinaryOp::Ge, false) => BinaryOp::Lt,
+        (BinaryOp::Gt, false) => BinaryOp::Le,
+        (BinaryOp::Le, false) => BinaryOp::Gt,
+        _ => unreachable!(),
+    };
+    let lhs = w.operand(scope, binary.lhs(), "t4");
+    let rhs = w.operand(scope, binary.rhs(), "t5");
+    // `>` and `<=` are `<` and `>=` with operands swapped
+    let (inst, lhs, rhs) = match op {
+        BinaryOp::Eq => ("beq", lhs, rhs),
+        BinaryOp::NotEq => ("bne", lhs, rhs),
+        BinaryOp::Lt => ("blt", lhs, rhs),
+        BinaryOp::Ge => ("bge", lhs, rhs),
+        BinaryOp::Gt => ("blt", rhs, lhs),
+        BinaryOp::Le => ("bge", rhs, lhs),
+        _ => unreachable!(),
+    };
+    w.op3(inst, &lhs, &rhs, label);
+}
+
+/// Comparisons used only by the branch right after them, which test them directly.
+fn fused(func: &FunctionData) -> HashSet<Value> {
+    let mut fused = HashSet::new();
+    for (_, node) in func.layout().bbs() {
+        let insts: Vec<Value> = node.insts().keys().copied().collect();
+        for pair in insts.windows(2) {
+            let cond = match func.dfg().value(pair[1]).kind() {
+                ValueKind::Branch(branch) => branch.cond(),
+                _ => continue,
+            };
+            let compare = matches!(func.dfg().value(cond).kind(), ValueKind::Binary(binary)
+                if matches!(binary.op(), BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::Gt | BinaryOp::Le | BinaryOp::Ge));
+            // operands live until the comparison only, so nothing may come between them
+            if cond == pair[0] && compare && func.dfg().value(cond).used_by().len() == 1 {
+                fused.insert(cond);
+            }
+        }
+    }
+    fused
 }
 
 impl Asm for Jump {
     fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# jump");
-        let dst = program.func(scope.cur_func().clone()).dfg().bb(self.target()).name().as_ref().unwrap()[1..].to_string();
+        let func = program.func(*scope.cur_func());
+        let dst = func.dfg().bb(self.target()).name().as_ref().unwrap()[1..].to_string();
+        w.parallel_move(block_moves(func, scope, self.target(), self.args()));
         w.op1("j", &dst);
     }
 }
 
+/// Moves of arguments into parameters of the target block, except those already in place.
+fn block_moves(func: &FunctionData, scope: &Scope, target: BasicBlock, args: &[Value]) -> Vec<(Entry, Entry)> {
+    func.dfg().bb(target).params().iter()
+        .zip(args)
+        .map(|(param, arg)| (scope.value(param).clone(), scope.value(arg).clone()))
+        .filter(|(dst, src)| dst != src)
+        .collect()
+}
+
 impl Asm for Call {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# call");
-        let mut count = 0;
-        for arg in self.args() {
-            let arg_entry = scope.value(&arg).clone();
+        // values living across the call are in callee-saved registers or stack, nothing to spill here
+        for (count, arg) in self.args().iter().enumerate() {
             if count < 8 {
-                match arg_entry {
-                    Entry::Slot(offset) => {
-                        if offset < 512 {
-                            w.op2("lw", &format!("a{}", count), &format!("{}(sp)", offset * 4));
-                        }
-                        else {
-                            let temporary = new_register!(scope, w).name().to_string();
-                            w.op2("li", &temporary, &format!("{}", offset * 4));
-                            w.op3("add", &temporary, &temporary, "sp");
-                            w.op2("lw", &format!("a{}", count), &format!("0({})", &temporary));
-                        }
-                    }
-                    Entry::Register(name) => w.op2("mv", &format!("a{}", count), &name),
-                    Entry::Label(label) => {
-                        let temporary = new_register!(scope, w).name().to_string();
-                        w.op2("la", &temporary, &label);
-                        w.op2("lw", &format!("a{}", count), &format!("0({})", &temporary));
-                    }
-                }
-            }
-            else {
-                match arg_entry {
-                    Entry::Slot(offset) => {
-                        let temporary = new_register!(scope, w).name().to_string();
-                        if offset < 512 {
-                            w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                        }
-                        else {
-                            w.op2("li", &temporary, &format!("{}", offset * 4));
-                            w.op3("add", &temporary, &temporary, "sp");
-                            w.op2("lw", &temporary, &format!("0({})", temporary));
-                        }
-                        w.op2("sw", &temporary, &format!("{}(sp)", (count - 8) * 4));
-                    }
-                    Entry::Register(name) => w.op2("sw", &name, &format!("{}(sp)", (count - 8) * 4)),
-                    Entry::Label(label) => {
-                        let temporary = new_register!(scope, w).name().to_string();
-                        w.op2("la", &temporary, &label);
-                        w.op2("lw", &temporary, &format!("0({})", &temporary));
-                        w.op2("sw", &temporary, &format!("{}(sp)", (count - 8) * 4));
-                    }
-                }
-            }
-            count += 1;
-        }
-
-        // before call, spill all used registers into stack
-        let current = info.info(scope.cur_value().clone()).unwrap().birth;
-        for register in scope.registers_mut() {
-            match register.value {
-                Some(value) => {
-                    if info.info(value.clone()).unwrap().death <= current {
-                        register.used = false;
-                        register.value = None;
-                    }
-                }
-                None => {}
-            }
-        }
-
-        let mut values: Vec<(String, Value)> = vec![];
-        for register in scope.registers_mut() {
-            match register.value {
-                Some(value) => {
-                    register.used = false;
-                    register.value = None;
-                    values.push((register.name().to_string(), value.clone()));
+                let register = format!("a{}", count);
+                let src = w.operand(scope, *arg, &register);
+                if src != register {
+                    w.op2("mv", &register, &src);
                 }
-                None => {}
-            }
-        }
-
-        for (name, value) in values {
-            let slot = scope.new_slot();
-            if slot < 512 {
-                w.op2("sw", &name, &format!("{}(sp)", slot * 4));
             }
             else {
-                w.op2("li", "t6", &format!("{}", slot * 4));
-                w.op3("add", "t6", "t6", "sp");
-                w.op2("sw", &name, "0(t6)");
+                let src = w.operand(scope, *arg, "t4");
+                w.store_slot(&src, count - 8);
             }
-            scope.new_value(value, Entry::Slot(slot));
         }
 
         let name = program.func(self.callee()).name()[1..].to_string();
+        if scope.is_tail(scope.cur_value()) {
+            w.teardown(scope);
+            w.op1("tail", &name);
+            return;
+        }
         w.op1("call", &name);
 
-        let slot = scope.new_slot();
-        if slot < 512 {
-            w.op2("sw", "a0", &format!("{}(sp)", slot * 4));
+        if !program.func(*scope.cur_func()).dfg().value(*scope.cur_value()).ty().is_unit() {
+            let dst = scope.target();
+            w.op2("mv", &dst, "a0");
+            w.write_back(scope, &dst);
         }
-        else {
-            let temporary = scope.new_register(w).name().to_string();
-            w.op2("li", &temporary, &format!("{}", slot * 4));
-            w.op3("add", &temporary, &temporary, "sp");
-            w.op2("sw", "a0", &format!("0({})", temporary));
-        }
-
-        let value = scope.cur_value().clone();
-        scope.new_value(value, Entry::Slot(slot));
     }
 }
 
@@ -779,80 +618,12 @@ impl Asm for GlobalAlloc {
 }
 
 impl Asm for GetElemPtr {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# get element pointer");
-        let is_addr = if self.src().is_global() {
-            match program.borrow_value(self.src()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        } else {
-            match program.func(scope.cur_func().clone()).dfg().value(self.src()).kind() {
-                ValueKind::GetElemPtr(_) => true,
-                ValueKind::GetPtr(_) => true,
-                _ => false,
-            }
-        };
-        
-        let base_entry = scope.value(&self.src()).clone();
-        let mut base_flag = false;
-        let base = match base_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                base_flag = true;
-                if is_addr {
-                    if offset < 512 {
-                        w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                    }
-                    else {
-                        w.op2("li", &temporary, &format!("{}", offset * 4));
-                        w.op3("add", &temporary, &temporary, "sp");
-                        w.op2("lw", &temporary, &format!("0({})", temporary));
-                    }
-                }
-                else {
-                    if offset < 512 {
-                        w.op3("addi", &temporary, "sp", &format!("{}", offset * 4));
-                    }
-                    else {
-                        w.op2("li", &temporary, &format!("{}", offset * 4));
-                        w.op3("add", &temporary, "sp", &temporary);
-                    }
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                base_flag = true;
-                w.op2("la", &temporary, &label);
-                temporary
-            }
-        };
-
-        let index_entry = scope.value(&self.index()).clone();
-        let mut index_flag = false;
-        let index = match index_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                index_flag = true;
-                if offset < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", offset * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(_) => panic!("index should not be in label"),
-        };
+        // a constant offset of a stack object is an address in the frame itself
+        if let Entry::Frame(_) = scope.value(scope.cur_value()) {
+            return;
+        }
 
         let size = if self.src().is_global() {
             match program.borrow_value(self.src()).ty().kind() {
@@ -880,79 +651,17 @@ impl Asm for GetElemPtr {
             }
         };
 
-        let value = scope.cur_value().clone();
-        let register = new_register!(scope, w);
-        register.used = true;
-        register.stamp = info.info(value.clone()).unwrap().birth;
-        register.value = Some(value.clone());
-
-        let dst = register.name().to_string();
-        scope.new_value(value.clone(), Entry::Register(dst.clone()));
-
-        let temporary = new_register!(scope, w).name().to_string();
-        if base_flag {
-            scope.register_mut(&base).fixed = false;
-        }
-        if index_flag {
-            scope.register_mut(&index).fixed = false;
-        }
-
-        w.op2("li", &temporary, &format!("{}", size));
-        w.op3("mul", &temporary, &index, &temporary);
-        w.op3("add", &dst, &base, &temporary);
+        address(scope, w, self.src(), self.index(), size);
     }
 }
 
 impl Asm for GetPtr {
-    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, info: &mut Info) {
+    fn asm(&self, program: &Program, scope: &mut Scope, w: &mut Writer, _: &mut Info) {
         w.note("# get pointer");
-        let base_entry = scope.value(&self.src()).clone();
-        let mut base_flag = false;
-        let base = match base_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                base_flag = true;
-                if offset < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", offset * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(label) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                base_flag = true;
-                w.op2("la", &temporary, &label);
-                temporary
-            }
-        };
-        
-        let index_entry = scope.value(&self.index()).clone();
-        let mut index_flag = false;
-        let index = match index_entry {
-            Entry::Slot(offset) => {
-                let temporary = new_register!(scope, w).name().to_string();
-                scope.register_mut(&temporary).fixed = true;
-                index_flag = true;
-                if offset < 512 {
-                    w.op2("lw", &temporary, &format!("{}(sp)", offset * 4));
-                }
-                else {
-                    w.op2("li", &temporary, &format!("{}", offset * 4));
-                    w.op3("add", &temporary, &temporary, "sp");
-                    w.op2("lw", &temporary, &format!("0({})", temporary));
-                }
-                temporary
-            }
-            Entry::Register(name) => name.to_string(),
-            Entry::Label(_) => panic!("index should not be in label"),
-        };
+        // a constant offset of a stack object is an address in the frame itself
+        if let Entry::Frame(_) = scope.value(scope.cur_value()) {
+            return;
+        }
 
         let size = if self.src().is_global() {
             match program.borrow_value(self.src()).ty().kind() {
@@ -980,25 +689,6 @@ impl Asm for GetPtr {
             }
         };
 
-        let value = scope.cur_value().clone();
-        let register = new_register!(scope, w);
-        register.used = true;
-        register.stamp = info.info(value.clone()).unwrap().birth;
-        register.value = Some(value.clone());
-
-        let dst = register.name().to_string();
-        scope.new_value(value.clone(), Entry::Register(dst.clone()));
-
-        let temporary = new_register!(scope, w).name().to_string();
-        if base_flag {
-            scope.register_mut(&base).fixed = false;
-        }
-        if index_flag {
-            scope.register_mut(&index).fixed = false;
-        }
-
-        w.op2("li", &temporary, &format!("{}", size));
-        w.op3("mul", &temporary, &index, &temporary);
-        w.op3("add", &dst, &base, &temporary);
+        address(scope, w, self.src(), self.index(), size);
     }
 }
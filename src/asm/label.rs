@@ -5,12 +5,14 @@
 
 pub struct Label {
     var: usize,
+    edge: usize,
 }
 
 impl Label {
     pub fn new() -> Self {
         Self {
             var: 0,
+            edge: 0,
         }
     }
 
@@ -19,4 +21,11 @@ impl Label {
         self.var += 1;
         label
     }
+
+    /// Label of the moves on an edge, taken by a branch when both targets need moves.
+    pub fn edge(&mut self) -> String {
+        let label = format!("edge_{}", self.edge);
+        self.edge += 1;
+        label
+    }
 }
@@ -8,6 +8,8 @@ mod label;
 mod register;
 mod writer;
 mod scope;
+mod reduce;
+pub mod alloc;
 mod asm;
 
 use koopa::ir::Program;
@@ -15,10 +17,17 @@ use crate::mem::info::Info;
 use crate::asm::scope::Scope;
 use crate::asm::asm::Asm;
 use crate::asm::writer::Writer;
+use crate::asm::alloc::Allocator;
 use std::fs::File;
+use std::io::Write;
 
-pub fn generate_asm(program: &Program, info: &mut Info, path: &str) {
-    let mut scope = Scope::new();
+pub fn generate_asm(program: &Program, info: &mut Info, path: &str, allocator: Allocator) {
     let mut f = File::create(path).unwrap();
-    program.asm(program, &mut scope, &mut Writer::new(&mut f), info)
+    write_asm(program, info, &mut f, allocator)
+}
+
+/// Write the assembly into any writer, such as a buffer to be run by the emulator.
+pub fn write_asm(program: &Program, info: &mut Info, f: &mut dyn Write, allocator: Allocator) {
+    let mut scope = Scope::new(allocator);
+    program.asm(program, &mut scope, &mut Writer::new(f), info)
 }
@@ -0,0 +1,284 @@
+//! # Reduce
+//!
+//! In this file, we lower multiplication, division and modulo by a constant to cheaper instructions.
+//!
+//! Multiplying by a power of two is a shift. Dividing by a power of two shifts too, after adding
+//! `2^k - 1` to negative dividends so that the quotient rounds toward zero, and the remainder is
+//! the dividend minus the quotient shifted back. Dividing by other constants multiplies by a magic
+//! number and keeps the high word, as in chapter 10 of Hacker's Delight.
+//!
+//! Results are the same as `mul`, `div` and `rem` for every `i32`, wrapping included,
+//! so `INT_MIN / -1` is still `INT_MIN`. Dividing by zero is left to `div` and `rem`.
+//! Only the last instruction writes the destination, which may be the register of the source.
+//!
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Reg {
+    Dst,
+    Src,
+    /// Scratch registers, not holding any value.
+    T1,
+    T2,
+    Zero,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Inst {
+    /// An instruction on two registers, such as `add` and `mulh`.
+    R(&'static str, Reg, Reg, Reg),
+    /// An instruction on a register and an immediate, such as `srai` and `andi`.
+    I(&'static str, Reg, Reg, i32),
+    Li(Reg, i32),
+}
+
+use Inst::*;
+use Reg::*;
+
+/// Multiply `Src` by `c`, if it can be done without `mul`.
+pub fn mul(c: i32) -> Option<Vec<Inst>> {
+    match c {
+        0 => Some(vec![Li(Dst, 0)]),
+        1 => Some(vec![I("addi", Dst, Src, 0)]),
+        -1 => Some(vec![R("sub", Dst, Zero, Src)]),
+        // `INT_MIN` is a power of two as well, modulo `2^32`
+        _ if c.unsigned_abs().is_power_of_two() => {
+            let k = c.unsigned_abs().trailing_zeros() as i32;
+            if c > 0 || c == i32::MIN {
+                Some(vec![I("slli", Dst, Src, k)])
+            }
+            else {
+                Some(vec![I("slli", T1, Src, k), R("sub", Dst, Zero, T1)])
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Divide `Src` by `c` rounding toward zero, if `c` isn't zero.
+pub fn div(c: i32) -> Option<Vec<Inst>> {
+    match c {
+        0 => None,
+        1 => Some(vec![I("addi", Dst, Src, 0)]),
+        -1 => Some(vec![R("sub", Dst, Zero, Src)]),
+        _ if c.unsigned_abs().is_power_of_two() => {
+            let k = c.unsigned_abs().trailing_zeros() as i32;
+            let mut insts = round(k);
+            if c > 0 {
+                insts.push(I("srai", Dst, T1, k));
+            }
+            else {
+                insts.push(I("srai", T1, T1, k));
+                insts.push(R("sub", Dst, Zero, T1));
+            }
+            Some(insts)
+        }
+        _ => {
+            let (magic, shift) = magic(c);
+            let mut insts = vec![Li(T1, magic), R("mulh", T1, Src, T1)];
+            // the magic number is off by `2^32` when its sign differs from the divisor
+            if c > 0 && magic < 0 {
+                insts.push(R("add", T1, T1, Src));
+            }
+            if c < 0 && magic > 0 {
+                insts.push(R("sub", T1, T1, Src));
+            }
+            if shift > 0 {
+                insts.push(I("srai", T1, T1, shift));
+            }
+            // a negative quotient is rounded down, so it's one less
+            insts.push(I("srli", T2, T1, 31));
+            insts.push(R("add", Dst, T1, T2));
+            Some(insts)
+        }
+    }
+}
+
+/// Remainder of `Src` divided by `c` with the sign of `Src`, if `c` isn't zero.
+pub fn rem(c: i32) -> Option<Vec<Inst>> {
+    match c {
+        0 => None,
+        1 | -1 => Some(vec![Li(Dst, 0)]),
+        // the sign of the divisor doesn't change the remainder
+        _ if c.unsigned_abs().is_power_of_two() => {
+            let k = c.unsigned_abs().trailing_zeros() as i32;
+            let mask = (u32::MAX << k) as i32;
+            let mut insts = round(k);
+            if mask >= -2048 {
+                insts.push(I("andi", T1, T1, mask));
+            }
+            else {
+                insts.push(Li(T2, mask));
+                insts.push(R("and", T1, T1, T2));
+            }
+            insts.push(R("sub", Dst, Src, T1));
+            Some(insts)
+        }
+        _ => {
+            let mut insts = div(c).unwrap();
+            // the quotient goes to `T1` instead, then the remainder is `Src - T1 * c`
+            if let Some(R(_, dst, _, _)) = insts.last_mut() {
+                *dst = T1;
+            }
+            insts.push(Li(T2, c));
+            insts.push(R("mul", T1, T1, T2));
+            insts.push(R("sub", Dst, Src, T1));
+            Some(insts)
+        }
+    }
+}
+
+/// `Src + 2^k - 1` into `T1` if `Src` is negative, else `Src`.
+fn round(k: i32) -> Vec<Inst> {
+    if k == 1 {
+        vec![I("srli", T1, Src, 31), R("add", T1, Src, T1)]
+    }
+    else {
+        vec![I("srai", T1, Src, 31), I("srli", T1, T1, 32 - k), R("add", T1, Src, T1)]
+    }
+}
+
+/// The magic number and the shift dividing by `d`, with `|d|` at least 2 and not a power of two.
+fn magic(d: i32) -> (i32, i32) {
+    let two31: u32 = 1 << 31;
+    let ad = d.unsigned_abs();
+    let t = two31 + ((d as u32) >> 31);
+    // the largest dividend with the remainder `|d| - 1`
+    let anc = t - 1 - t % ad;
+    let mut p = 31;
+    let (mut q1, mut r1) = (two31 / anc, two31 % anc);
+    let (mut q2, mut r2) = (two31 / ad, two31 % ad);
+    loop {
+        p += 1;
+        q1 = q1.wrapping_mul(2);
+        r1 = r1.wrapping_mul(2);
+        if r1 >= anc {
+            q1 = q1.wrapping_add(1);
+            r1 = r1.wrapping_sub(anc);
+        }
+        q2 = q2.wrapping_mul(2);
+        r2 = r2.wrapping_mul(2);
+        if r2 >= ad {
+            q2 = q2.wrapping_add(1);
+            r2 = r2.wrapping_sub(ad);
+        }
+        let delta = ad - r2;
+        if !(q1 < delta || (q1 == delta && r1 == 0)) {
+            break;
+        }
+    }
+    let magic = q2.wrapping_add(1) as i32;
+    (if d < 0 { magic.wrapping_neg() } else { magic }, p - 32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Execute the instructions with RV32 semantics, return `Dst`.
+    /// With `alias`, `Dst` and `Src` are the same register, as they may be.
+    fn eval(insts: &[Inst], src: i32, alias: bool) -> i32 {
+        let mut regs = [0; 5];
+        let index = |reg: Reg| match reg {
+            Dst if alias => 1,
+            Dst => 0,
+            Src => 1,
+            T1 => 2,
+            T2 => 3,
+            Zero => 4,
+        };
+        regs[1] = src;
+        for &inst in insts {
+            let (dst, value) = match inst {
+                R(op, dst, a, b) => {
+                    let (a, b) = (regs[index(a)], regs[index(b)]);
+                    let value = match op {
+                        "add" => a.wrapping_add(b),
+                        "sub" => a.wrapping_sub(b),
+                        "mul" => a.wrapping_mul(b),
+                        "mulh" => ((a as i64 * b as i64) >> 32) as i32,
+                        "and" => a & b,
+                        _ => panic!("unknown instruction `{}`", op),
+                    };
+                    (dst, value)
+                }
+                I(op, dst, a, imm) => {
+                    let a = regs[index(a)];
+                    let value = match op {
+                        "addi" => a.wrapping_add(imm),
+                        "andi" => a & imm,
+                        "slli" => a << imm,
+                        "srai" => a >> imm,
+                        "srli" => ((a as u32) >> imm) as i32,
+                        _ => panic!("unknown instruction `{}`", op),
+                    };
+                    assert!((-2048..2048).contains(&imm), "immediate {} doesn't fit in 12 bits", imm);
+                    (dst, value)
+                }
+                Li(dst, imm) => (dst, imm),
+            };
+            regs[index(dst)] = value;
+            regs[index(Zero)] = 0;
+        }
+        regs[index(Dst)]
+    }
+
+    fn divisors() -> Vec<i32> {
+        let mut divisors = vec![1, -1, i32::MIN, i32::MAX, i32::MIN + 1];
+        for k in 1..31 {
+            divisors.extend([1 << k, -(1 << k)]);
+        }
+        for d in [3, 5, 6, 7, 9, 10, 11, 12, 13, 25, 100, 125, 641, 1000, 7919, 65537, 1_000_000_007, (1 << 30) + 1, (1 << 30) - 1] {
+            divisors.extend([d, -d]);
+        }
+        divisors
+    }
+
+    /// Edge cases and a sweep sampled by a linear congruential generator.
+    fn dividends() -> Vec<i32> {
+        let mut dividends = vec![i32::MIN, i32::MIN + 1, -1, 0, 1, i32::MAX];
+        let mut state: u32 = 1;
+        for _ in 0..4096 {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            dividends.push(state as i32);
+            // small dividends too, where rounding toward zero matters most
+            dividends.push((state as i32) >> 20);
+        }
+        dividends
+    }
+
+    fn check(op: &str, c: i32, insts: &[Inst], expected: impl Fn(i32) -> i32) {
+        for x in dividends() {
+            for alias in [false, true] {
+                assert_eq!(eval(insts, x, alias), expected(x), "{} {} {}, alias {}: {:?}", x, op, c, alias, insts);
+            }
+        }
+    }
+
+    #[test]
+    fn mul_by_constant() {
+        for c in divisors().into_iter().chain([0]) {
+            if let Some(insts) = mul(c) {
+                check("*", c, &insts, |x| x.wrapping_mul(c));
+            }
+            else {
+                assert!(!c.unsigned_abs().is_power_of_two(), "`mul` is needed for {}", c);
+            }
+        }
+    }
+
+    #[test]
+    fn div_by_constant() {
+        assert_eq!(div(0), None);
+        for c in divisors() {
+            check("/", c, &div(c).unwrap(), |x| x.wrapping_div(c));
+        }
+    }
+
+    #[test]
+    fn rem_by_constant() {
+        assert_eq!(rem(0), None);
+        for c in divisors() {
+            check("%", c, &rem(c).unwrap(), |x| x.wrapping_rem(c));
+        }
+    }
+}
@@ -1,42 +1,25 @@
 //! # Register
-//! 
-//! In this file, we define structure to manage temporary registers.
-//! 
-
-use koopa::ir::Value;
-
-pub struct Register {
-    pub name: String,
-    pub used: bool,
-    pub fixed: bool,    // this member may be useless
-    pub stamp: usize,
-    pub value: Option<Value>,
+//!
+//! In this file, we describe the registers of RISC-V that the register allocator can hand out.
+//!
+//! `t4`, `t5` and `t6` are never allocated, they are scratch registers of the generator:
+//! * `t4` and `t5` hold operands loaded from the stack and results to be stored into it.
+//! * `t6` holds addresses which don't fit in an immediate.
+//!
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Class {
+    /// `t` registers, clobbered by calls.
+    Temporary,
+    /// `a` registers, clobbered by calls and used to pass arguments.
+    Argument,
+    /// `s` registers, preserved by calls, a function must save them before using.
+    Saved,
 }
 
-impl Register {
-    pub fn new(name: String) -> Self {
-        Self {
-            name,
-            used: false,
-            fixed: false,
-            stamp: 0,
-            value: None,
-        }
-    }
-
-    pub fn with_fixed(name: String) -> Self {
-        Self {
-            name,
-            used: false,
-            fixed: true,
-            stamp: 0,
-            value: None,
-        }
-    }
-
-    pub fn name(&self) -> &str {
-        &self.name
-    }
+pub struct Register {
+    pub name: &'static str,
+    pub class: Class,
 }
 
 pub struct Registers {
@@ -45,32 +28,17 @@ pub struct Registers {
 
 impl Registers {
     pub fn new() -> Self {
+        let temporaries = ["t0", "t1", "t2", "t3"].map(|name| Register { name, class: Class::Temporary });
+        let arguments = ["a0", "a1", "a2", "a3", "a4", "a5", "a6", "a7"].map(|name| Register { name, class: Class::Argument });
+        let saved = ["s0", "s1", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11"].map(|name| Register { name, class: Class::Saved });
+
+        // prefer registers which are free to use
         Self {
-            registers: vec![
-                Register::with_fixed("x0".to_string()),
-                Register::new("t0".to_string()),
-                Register::new("t1".to_string()),
-                Register::new("t2".to_string()),
-                Register::new("t3".to_string()),
-                Register::new("t4".to_string()),
-                Register::new("t5".to_string()),
-                Register::with_fixed("t6".to_string()),
-            ],
+            registers: temporaries.into_iter().chain(arguments).chain(saved).collect(),
         }
     }
 
-    pub fn register_mut(&mut self, name: &str) -> &mut Register {
-        self.registers
-            .iter_mut()
-            .find(|r| r.name() == name)
-            .expect(&format!("Register {} not found", name))
-    }
-
     pub fn registers(&self) -> &[Register] {
         &self.registers
     }
-
-    pub fn registers_mut(&mut self) -> &mut [Register] {
-        &mut self.registers
-    }
 }
@@ -1,70 +1,60 @@
 //! # Scope
-//! 
+//!
 //! In this file, we define structure manages values and statement of the program.
-//! 
+//!
 
-use crate::asm::register::{ Registers, Register };
 use crate::asm::label::Label;
-use crate::asm::writer::Writer;
-use std::collections::HashMap;
+use crate::asm::alloc::Allocator;
+use crate::analysis::liveness::Liveness;
+use std::collections::{ HashMap, HashSet };
 use koopa::ir::{ Value, Function };
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub enum Entry {
-    Slot(usize),    // slot in stack
+    Slot(usize),    // value spilled to a slot in stack
+    Frame(usize),   // object allocated in stack, the value is its address
     Register(String),   // register
     Label(String),  // label(data in heap)
+    Imm(i32),   // constant, folded into instructions using it
 }
 
-macro_rules! new_register {
-    ($scope: expr, $w: expr) => {
-        {
-            let register = $scope.new_register($w);
-            let name = register.name().to_string();
-            let value = register.value.clone();
-
-            $scope.spill(value);
-
-            let register = $scope.register_mut(&name);
-            register.used = false;
-            register.value = None;
-            register
-        }
-    };
-}
-pub(crate) use new_register;
-
 pub struct Scope {
     values: HashMap<Value, Entry>,
 
     cur_func: Option<Function>,
     cur_value: Option<Value>,
-
-    registers: Registers,
+    liveness: Option<Liveness>,
+    allocator: Allocator,
 
     total_slots: usize,
     used_slots: usize,
 
     caller: bool,
+    // callee-saved registers used by current function, and the slots they are saved in
+    saved: Vec<(&'static str, usize)>,
+    // calls of current function jumping to their callees, and the returns after them
+    tails: HashSet<Value>,
+    // comparisons of current function done by the branches using them
+    fused: HashSet<Value>,
 
     label: Label,
-
-    // beta
-    store_pair: (String, Entry, usize),
 }
 
 impl Scope {
-    pub fn new() -> Self {
+    pub fn new(allocator: Allocator) -> Self {
         Self {
             values: HashMap::new(),
             cur_func: None,
             cur_value: None,
-            registers: Registers::new(),
+            liveness: None,
+            allocator,
             total_slots: 0,
             used_slots: 0,
             caller: false,
+            saved: vec![],
+            tails: HashSet::new(),
+            fused: HashSet::new(),
             label: Label::new(),
-            store_pair: (String::new(), Entry::Register(String::from("not exist")), 0),
         }
     }
 
@@ -72,10 +62,30 @@ impl Scope {
         self.values.get(value).unwrap()
     }
 
+    pub fn get(&self, value: &Value) -> Option<&Entry> {
+        self.values.get(value)
+    }
+
+    /// The constant of the value if it fits in a 12-bit immediate.
+    pub fn imm(&self, value: &Value) -> Option<i32> {
+        match self.values.get(value) {
+            Some(Entry::Imm(imm)) if (-2048..2048).contains(imm) => Some(*imm),
+            _ => None,
+        }
+    }
+
     pub fn new_value(&mut self, value: Value, entry: Entry) {
         self.values.insert(value, entry);
     }
 
+    /// Register to compute the current value into, `t4` if the value lives in stack.
+    pub fn target(&self) -> String {
+        match self.value(self.cur_value()) {
+            Entry::Register(name) => name.clone(),
+            _ => "t4".to_string(),
+        }
+    }
+
     pub fn cur_func(&self) -> &Function {
         self.cur_func.as_ref().unwrap()
     }
@@ -92,6 +102,18 @@ impl Scope {
         self.cur_value = value;
     }
 
+    pub fn liveness(&self) -> &Liveness {
+        self.liveness.as_ref().unwrap()
+    }
+
+    pub fn set_liveness(&mut self, liveness: Option<Liveness>) {
+        self.liveness = liveness;
+    }
+
+    pub fn allocator(&self) -> Allocator {
+        self.allocator
+    }
+
     pub fn caller(&self) -> bool {
         self.caller
     }
@@ -100,6 +122,30 @@ impl Scope {
         self.caller = caller;
     }
 
+    pub fn saved(&self) -> &[(&'static str, usize)] {
+        &self.saved
+    }
+
+    pub fn set_saved(&mut self, saved: Vec<(&'static str, usize)>) {
+        self.saved = saved;
+    }
+
+    pub fn is_tail(&self, value: &Value) -> bool {
+        self.tails.contains(value)
+    }
+
+    pub fn set_tails(&mut self, tails: HashSet<Value>) {
+        self.tails = tails;
+    }
+
+    pub fn is_fused(&self, value: &Value) -> bool {
+        self.fused.contains(value)
+    }
+
+    pub fn set_fused(&mut self, fused: HashSet<Value>) {
+        self.fused = fused;
+    }
+
     pub fn label_mut(&mut self) -> &mut Label {
         &mut self.label
     }
@@ -112,6 +158,10 @@ impl Scope {
         self.total_slots = total_slots;
     }
 
+    pub fn used_slots(&self) -> usize {
+        self.used_slots
+    }
+
     pub fn set_used_slots(&mut self, used_slots: usize) {
         self.used_slots = used_slots;
     }
@@ -127,47 +177,4 @@ impl Scope {
         self.used_slots += slots;
         slot
     }
-
-    pub fn register_mut(&mut self, id: &str) -> &mut Register {
-        self.registers.register_mut(id)
-    }
-
-    pub fn registers_mut(&mut self) -> &mut [Register] {
-        self.registers.registers_mut()
-    }
-
-    pub fn new_register(&mut self, w: &mut Writer) -> &Register {
-        let slot = self.used_slots;
-        let unused = self.registers.registers().iter().find(|r| !r.used && !r.fixed);
-        let oldest = self.registers.registers().iter().filter(|r| !r.fixed).min_by_key(|r| r.stamp);
-
-        match (unused, oldest) {
-            (Some(unused), _) => unused,
-            (_, Some(oldest)) => {
-                if slot < 512 {
-                    w.op2("sw", oldest.name(), &format!("{}(sp)", slot * 4));
-                } else {
-                    w.op2("li", "t6", &format!("{}", slot * 4));
-                    w.op3("add", "t6", "sp", "t6");
-                    w.op2("sw", oldest.name(), "0(t6)");
-                }
-                oldest
-            }
-            _ => panic!("all registers fixed"),
-        }
-    }
-
-    pub fn spill(&mut self, value: Option<Value>) {
-        match value {
-            Some(value) => {
-                self.new_value(value, Entry::Slot(self.used_slots));
-                self.used_slots += 1;
-            }
-            None => {}
-        }
-    }
-
-    pub fn store_pair_mut(&mut self) -> &mut (String, Entry, usize) {
-        &mut self.store_pair
-    }
 }
@@ -1,17 +1,16 @@
 //! # Writer
 //! 
 
-use crate::asm::scope::Scope;
-use std::fs::File;
+use crate::asm::scope::{ Scope, Entry };
 use std::io::Write;
 use koopa::ir::{ Program, Value, ValueKind, Type, TypeKind };
 
 pub struct Writer<'f> {
-    f: &'f mut File,
+    f: &'f mut dyn Write,
 }
 
 impl<'f> Writer<'f> {
-    pub fn new(f: &'f mut File) -> Self {
+    pub fn new(f: &'f mut dyn Write) -> Self {
         Self { f }
     }
 
@@ -49,29 +48,25 @@ impl<'f> Writer<'f> {
         }
 
         if scope.caller() {
-            let offset = (scope.total_slots() - 1) * 4;
-            if offset < 512 {
-                self.op2("sw", "ra", &format!("{}(sp)", offset));
-            }
-            else {
-                self.op2("li", "t0", &format!("{}", offset));
-                self.op3("add", "t0", "sp", "t0");
-                self.op2("sw", "ra", &format!("0(t0)"));
-            }
+            self.store_slot("ra", scope.total_slots() - 1);
+        }
+        for (register, slot) in scope.saved() {
+            self.store_slot(register, *slot);
         }
     }
 
     pub fn epilogue(&mut self, scope: &Scope) {
+        self.teardown(scope);
+        self.op1("ret", "");
+    }
+
+    /// Restore registers and free the frame, before returning or jumping to a tail call.
+    pub fn teardown(&mut self, scope: &Scope) {
         if scope.caller() {
-            let offset = (scope.total_slots() - 1) * 4;
-            if offset < 512 {
-                self.op2("lw", "ra", &format!("{}(sp)", offset));
-            }
-            else {
-                self.op2("li", "t0", &format!("{}", offset));
-                self.op3("add", "t0", "sp", "t0");
-                self.op2("lw", "ra", &format!("0(t0)"));
-            }
+            self.load_slot("ra", scope.total_slots() - 1);
+        }
+        for (register, slot) in scope.saved() {
+            self.load_slot(register, *slot);
         }
         
         let slots = scope.total_slots();
@@ -82,8 +77,121 @@ impl<'f> Writer<'f> {
             self.op2("li", "t0", &format!("{}", slots * 4));
             self.op3("add", "sp", "sp", "t0");
         }
+    }
 
-        self.op1("ret", "");
+    pub fn load_slot(&mut self, register: &str, slot: usize) {
+        if slot < 512 {
+            self.op2("lw", register, &format!("{}(sp)", slot * 4));
+        }
+        else {
+            self.op2("li", "t6", &format!("{}", slot * 4));
+            self.op3("add", "t6", "t6", "sp");
+            self.op2("lw", register, "0(t6)");
+        }
+    }
+
+    pub fn store_slot(&mut self, register: &str, slot: usize) {
+        if slot < 512 {
+            self.op2("sw", register, &format!("{}(sp)", slot * 4));
+        }
+        else {
+            self.op2("li", "t6", &format!("{}", slot * 4));
+            self.op3("add", "t6", "t6", "sp");
+            self.op2("sw", register, "0(t6)");
+        }
+    }
+
+    /// Get a register holding the value, load it into `scratch` if it's not in a register.
+    /// Values of stack objects and global data are their addresses.
+    pub fn operand(&mut self, scope: &Scope, value: Value, scratch: &str) -> String {
+        self.entry(scope.value(&value), scratch)
+    }
+
+    /// Get a register holding what is in the entry, load it into `scratch` if it's not a register.
+    pub fn entry(&mut self, entry: &Entry, scratch: &str) -> String {
+        match entry {
+            Entry::Register(name) => name.clone(),
+            Entry::Slot(slot) => {
+                self.load_slot(scratch, *slot);
+                scratch.to_string()
+            }
+            Entry::Frame(slot) => {
+                if *slot < 512 {
+                    self.op3("addi", scratch, "sp", &format!("{}", slot * 4));
+                }
+                else {
+                    self.op2("li", scratch, &format!("{}", slot * 4));
+                    self.op3("add", scratch, "sp", scratch);
+                }
+                scratch.to_string()
+            }
+            Entry::Label(label) => {
+                self.op2("la", scratch, label);
+                scratch.to_string()
+            }
+            Entry::Imm(imm) => {
+                self.op2("li", scratch, &format!("{}", imm));
+                scratch.to_string()
+            }
+        }
+    }
+
+    /// Move all sources to their destinations at once, as if every source is read before any destination is written.
+    /// Destinations are registers or slots, `t4` and `t5` are used when needed.
+    pub fn parallel_move(&mut self, moves: Vec<(Entry, Entry)>) {
+        // constants and addresses read no register but `sp`, they can be moved last
+        let (mut pending, late): (Vec<_>, Vec<_>) = moves.into_iter()
+            .filter(|(dst, src)| dst != src)
+            .partition(|(_, src)| matches!(src, Entry::Register(_) | Entry::Slot(_)));
+
+        while !pending.is_empty() {
+            // a move can be done when no other move still reads its destination
+            let ready = (0..pending.len()).find(|&i| !pending.iter().any(|(_, src)| *src == pending[i].0));
+            match ready {
+                Some(i) => {
+                    let (dst, src) = pending.remove(i);
+                    self.move_entry(&dst, &src);
+                }
+                None => {
+                    // every destination is read by another move, break the cycle by saving one in `t5`
+                    let saved = pending[0].0.clone();
+                    let temp = Entry::Register("t5".to_string());
+                    self.move_entry(&temp, &saved);
+                    for (_, src) in pending.iter_mut() {
+                        if *src == saved {
+                            *src = temp.clone();
+                        }
+                    }
+                }
+            }
+        }
+
+        for (dst, src) in late {
+            self.move_entry(&dst, &src);
+        }
+    }
+
+    fn move_entry(&mut self, dst: &Entry, src: &Entry) {
+        match dst {
+            Entry::Register(name) => {
+                let src = self.entry(src, name);
+                if src != *name {
+                    self.op2("mv", name, &src);
+                }
+            }
+            Entry::Slot(slot) => {
+                let src = self.entry(src, "t4");
+                self.store_slot(&src, *slot);
+            }
+            _ => panic!("values can only be moved to registers and slots"),
+        }
+    }
+
+    /// Store the current value computed in `register` if it lives in stack.
+    pub fn write_back(&mut self, scope: &Scope, register: &str) {
+        if let Entry::Slot(slot) = scope.value(scope.cur_value()) {
+            self.store_slot(register, *slot);
+        }
     }
 
     pub fn aggregate(&mut self, program: &Program, value: Value) {
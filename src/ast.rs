@@ -1,12 +1,19 @@
+use crate::diagnostic::Span;
+
 #[derive(Debug)]
 pub struct CompUnit {
     pub items: Vec<CompItem>,
+    // spans are kept for diagnostics and tools on every node, even where no pass reads them yet
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum CompItem {
     Func(FuncDef),
     Decl(Decl),
+    /// An item with a syntax error, which has been reported by the parser.
+    Error { span: Span },
 }
 
 #[derive(Debug)]
@@ -18,6 +25,8 @@ pub enum Decl {
 #[derive(Debug)]
 pub struct ConstDecl {
     pub defs: Vec<ConstDef>,
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -25,17 +34,20 @@ pub struct ConstDef {
     pub id: String,
     pub dims: Vec<ConstExp>,
     pub init: ConstInitVal,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum ConstInitVal {
     Exp(ConstExp),
-    List(Box<Vec<ConstInitVal>>),
+    List { list: Box<Vec<ConstInitVal>>, span: Span },
 }
 
 #[derive(Debug)]
 pub struct VarDecl {
     pub defs: Vec<VarDef>,
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -43,12 +55,13 @@ pub struct VarDef {
     pub id: String,
     pub dims: Vec<ConstExp>,
     pub init: Option<InitVal>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum InitVal {
     Exp(Exp),
-    List(Box<Vec<InitVal>>),
+    List { list: Box<Vec<InitVal>>, span: Span },
 }
 
 #[derive(Debug)]
@@ -57,12 +70,14 @@ pub struct FuncDef {
     pub id: String,
     pub params: Vec<FuncParam>,
     pub body: Block,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct FuncParam {
     pub id: String,
     pub dims: Option<Vec<ConstExp>>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -74,35 +89,48 @@ pub enum FuncType {
 #[derive(Debug)]
 pub struct Block {
     pub items: Vec<BlockItem>,
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum BlockItem {
     Stmt(Stmt),
     Decl(Decl),
+    /// An item with a syntax error, which has been reported by the parser.
+    Error { span: Span },
 }
 
 #[derive(Debug)]
 pub enum Stmt {
     Return(Return),
     Assign(Assign),
-    Exp(Option<Exp>),
+    Exp {
+        exp: Option<Exp>,
+        #[allow(dead_code)]
+        span: Span,
+    },
     Block(Box<Block>),
     If(Box<If>),
     While(Box<While>),
     Break(Break),
     Continue(Continue),
+    /// A statement with a syntax error, which has been reported by the parser.
+    Error { span: Span },
 }
 
 #[derive(Debug)]
 pub struct Return {
     pub exp: Option<Exp>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct Assign {
     pub lval: LVal,
     pub exp: Exp,
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -110,19 +138,29 @@ pub struct If {
     pub cond: Exp,
     pub then: Stmt,
     pub els: Option<Stmt>,
+    /// Up to the condition, as the body has spans of its own.
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub struct While {
     pub cond: Exp,
     pub body: Stmt,
+    /// Up to the condition, as the body has spans of its own.
+    #[allow(dead_code)]
+    pub span: Span,
 }
 
 #[derive(Debug)]
-pub struct Break;
+pub struct Break {
+    pub span: Span,
+}
 
 #[derive(Debug)]
-pub struct Continue;
+pub struct Continue {
+    pub span: Span,
+}
 
 #[derive(Debug)]
 pub struct Exp {
@@ -133,26 +171,28 @@ pub struct Exp {
 pub struct LVal {
     pub id: String,
     pub dims: Vec<Exp>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
 pub enum PrimaryExp {
-    Exp(Box<Exp>),
+    Exp { exp: Box<Exp>, span: Span },
     LVal(LVal),
-    Num(i32),
+    Num { num: i32, span: Span },
 }
 
 #[derive(Debug)]
 pub enum UnaryExp {
     Primary(PrimaryExp),
     Call(Box<Call>),
-    Unary(UnaryOp, Box<UnaryExp>),
+    Unary { op: UnaryOp, unary: Box<UnaryExp>, span: Span },
 }
 
 #[derive(Debug)]
 pub struct Call {
     pub id: String,
     pub args: Vec<Exp>,
+    pub span: Span,
 }
 
 #[derive(Debug)]
@@ -229,3 +269,88 @@ pub enum LOrExp {
 pub struct ConstExp {
     pub exp: Exp,
 }
+
+impl Exp {
+    pub fn span(&self) -> Span {
+        self.lor.span()
+    }
+}
+
+impl PrimaryExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Exp { span, .. } | Self::Num { span, .. } => *span,
+            Self::LVal(lval) => lval.span,
+        }
+    }
+}
+
+impl UnaryExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Primary(primary) => primary.span(),
+            Self::Call(call) => call.span,
+            Self::Unary { span, .. } => *span,
+        }
+    }
+}
+
+impl MulExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Unary(unary) => unary.span(),
+            Self::Mul(mul, _, unary) => mul.span().to(unary.span()),
+        }
+    }
+}
+
+impl AddExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Mul(mul) => mul.span(),
+            Self::Add(add, _, mul) => add.span().to(mul.span()),
+        }
+    }
+}
+
+impl RelExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Add(add) => add.span(),
+            Self::Rel(rel, _, add) => rel.span().to(add.span()),
+        }
+    }
+}
+
+impl EqExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Rel(rel) => rel.span(),
+            Self::Eq(eq, _, rel) => eq.span().to(rel.span()),
+        }
+    }
+}
+
+impl LAndExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Eq(eq) => eq.span(),
+            Self::LAnd(land, eq) => land.span().to(eq.span()),
+        }
+    }
+}
+
+impl LOrExp {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::LAnd(land) => land.span(),
+            Self::LOr(lor, land) => lor.span().to(land.span()),
+        }
+    }
+}
+
+impl ConstExp {
+    pub fn span(&self) -> Span {
+        self.exp.span()
+    }
+}
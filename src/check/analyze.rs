@@ -0,0 +1,593 @@
+//! # Check
+//!
+//! In this file, we define a trait for checking the AST and implement it for each node.
+//! Expressions are checked into their types, `None` means an error has been reported for it.
+//!
+
+use crate::ast::*;
+use crate::check::scope::{ Scope, Symbol, Signature };
+use crate::check::ty::Ty;
+use crate::mem::eval::Eval;
+use crate::diagnostic::{ Diagnostic, Span };
+
+pub trait Check<'ast> {
+    type Out;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out;
+}
+
+/// Report an expression which is used as an `int` but has another type.
+fn expect_int(scope: &mut Scope, ty: Option<Ty>, span: Span) {
+    if let Some(ty) = ty {
+        if ty != Ty::Int {
+            scope.report(Diagnostic::error(format!("expected `int`, found `{}`", ty)).with_primary(span));
+        }
+    }
+}
+
+/// Check the dimensions of an array, which must be positive constants.
+fn check_dims<'ast>(dims: &'ast [ConstExp], scope: &mut Scope<'ast>) -> Option<Vec<usize>> {
+    let mut result = Some(vec![]);
+    for dim in dims {
+        let ty = dim.exp.check(scope);
+        expect_int(scope, ty, dim.span());
+        match dim.exp.evaluate(scope) {
+            Some(len) if len > 0 => {
+                if let Some(result) = &mut result {
+                    result.push(len as usize);
+                }
+            }
+            Some(_) => {
+                scope.report(Diagnostic::error("array dimension must be positive").with_primary(dim.span()));
+                result = None;
+            }
+            None => {
+                scope.report(Diagnostic::error("array dimension must be a constant expression").with_primary(dim.span()));
+                result = None;
+            }
+        }
+    }
+    result
+}
+
+/// An initializer seen by the checker, both `ConstInitVal` and `InitVal` turn into it.
+enum Init<'ast> {
+    Exp(&'ast Exp),
+    List(Vec<Init<'ast>>, Span),
+}
+
+impl<'ast> From<&'ast ConstInitVal> for Init<'ast> {
+    fn from(init: &'ast ConstInitVal) -> Self {
+        match init {
+            ConstInitVal::Exp(exp) => Self::Exp(&exp.exp),
+            ConstInitVal::List { list, span } => Self::List(list.iter().map(Self::from).collect(), *span),
+        }
+    }
+}
+
+impl<'ast> From<&'ast InitVal> for Init<'ast> {
+    fn from(init: &'ast InitVal) -> Self {
+        match init {
+            InitVal::Exp(exp) => Self::Exp(exp),
+            InitVal::List { list, span } => Self::List(list.iter().map(Self::from).collect(), *span),
+        }
+    }
+}
+
+impl<'ast> Init<'ast> {
+    /// Check an element, which must be an `int`, and a constant if `constant` is set.
+    fn check_exp(exp: &'ast Exp, scope: &mut Scope<'ast>, constant: bool, message: &str) {
+        let ty = exp.check(scope);
+        expect_int(scope, ty, exp.span());
+        if constant && exp.evaluate(scope).is_none() {
+            scope.report(Diagnostic::error(message).with_primary(exp.span()));
+        }
+    }
+
+    /// Check the elements only, when the shape of the array is unknown.
+    fn check_elems(&self, scope: &mut Scope<'ast>, constant: bool, message: &str) {
+        match self {
+            Self::Exp(exp) => Self::check_exp(exp, scope, constant, message),
+            Self::List(list, _) => list.iter().for_each(|init| init.check_elems(scope, constant, message)),
+        }
+    }
+
+    /// Check an initializer list of an array.
+    /// Sub-lists are placed the same way as `mem` flattens them, so excess elements are found.
+    fn check_list(&self, dims: &[usize], scope: &mut Scope<'ast>, constant: bool, message: &str) {
+        let list = match self {
+            Self::Exp(exp) => {
+                scope.report(Diagnostic::error("can't initialize an array with a single expression").with_primary(exp.span()));
+                return;
+            }
+            Self::List(list, _) => list,
+        };
+
+        let base = *dims.last().unwrap();
+        let total = dims.iter().product::<usize>();
+        let mut count: usize = 0;
+
+        for elem in list {
+            let span = match elem {
+                Self::Exp(exp) => {
+                    Self::check_exp(exp, scope, constant, message);
+                    count += 1;
+                    exp.span()
+                }
+                Self::List(_, span) if dims.len() == 1 => {
+                    scope.report(Diagnostic::error("initializer list is nested deeper than the array").with_primary(*span));
+                    return;
+                }
+                Self::List(_, span) => {
+                    count = count.div_ceil(base) * base;
+
+                    let mut begin = dims.len() - 1;
+                    let mut len = base;
+                    for num in dims.iter().rev().skip(1) {
+                        if !count.is_multiple_of(len * num) || begin <= 1 {
+                            break;
+                        }
+                        begin -= 1;
+                        len *= num;
+                    }
+
+                    elem.check_list(&dims[begin..], scope, constant, message);
+                    count += len;
+                    *span
+                }
+            };
+            if count > total {
+                scope.report(Diagnostic::error("excess elements in array initializer").with_primary(span));
+                return;
+            }
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for CompUnit {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let mut decl = |id: &'ast str, params: Vec<Ty>, ret: Ty| {
+            let params = params.into_iter().map(Some).collect();
+            scope.new_func(id, Signature { ret, params, span: None });
+        };
+        let array = || Ty::Array(vec![None]);
+        decl("getint", vec![], Ty::Int);
+        decl("getch", vec![], Ty::Int);
+        decl("getarray", vec![array()], Ty::Int);
+        decl("putint", vec![Ty::Int], Ty::Void);
+        decl("putch", vec![Ty::Int], Ty::Void);
+        decl("putarray", vec![Ty::Int, array()], Ty::Void);
+        decl("starttime", vec![], Ty::Void);
+        decl("stoptime", vec![], Ty::Void);
+
+        for item in &self.items {
+            item.check(scope);
+        }
+
+        if !matches!(scope.func("main"), Some(Signature { span: Some(_), .. })) {
+            scope.report(Diagnostic::error("function `main` is not defined"));
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for CompItem {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Func(func) => func.check(scope),
+            Self::Decl(decl) => decl.check(scope),
+            Self::Error { .. } => {}
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Decl {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Const(const_decl) => const_decl.defs.iter().for_each(|def| def.check(scope)),
+            Self::Var(var_decl) => var_decl.defs.iter().for_each(|def| def.check(scope)),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for ConstDef {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let message = "initializer of a constant must be a constant expression";
+        let init = Init::from(&self.init);
+        let (ty, num) = if self.dims.is_empty() {
+            match init {
+                Init::Exp(exp) => {
+                    Init::check_exp(exp, scope, true, message);
+                    (Some(Ty::Int), exp.evaluate(scope))
+                }
+                Init::List(_, span) => {
+                    scope.report(Diagnostic::error("can't initialize a single variable with a list").with_primary(span));
+                    (Some(Ty::Int), None)
+                }
+            }
+        }
+        else {
+            match check_dims(&self.dims, scope) {
+                Some(dims) => {
+                    init.check_list(&dims, scope, true, message);
+                    (Some(Ty::Array(dims.into_iter().map(Some).collect())), None)
+                }
+                None => {
+                    init.check_elems(scope, true, message);
+                    (None, None)
+                }
+            }
+        };
+        // a constant with a wrong initializer is still a constant, it is treated as 0 to avoid more errors
+        let num = if ty == Some(Ty::Int) { Some(num.unwrap_or(0)) } else { None };
+        scope.new_value(&self.id, Symbol { ty, is_const: true, num, span: self.span });
+    }
+}
+
+impl<'ast> Check<'ast> for VarDef {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let constant = scope.is_global();
+        let message = "initializer of a global variable must be a constant expression";
+        let init = self.init.as_ref().map(Init::from);
+        let ty = if self.dims.is_empty() {
+            match init {
+                Some(Init::Exp(exp)) => Init::check_exp(exp, scope, constant, message),
+                Some(Init::List(_, span)) => {
+                    scope.report(Diagnostic::error("can't initialize a single variable with a list").with_primary(span));
+                }
+                None => {}
+            }
+            Some(Ty::Int)
+        }
+        else {
+            match check_dims(&self.dims, scope) {
+                Some(dims) => {
+                    if let Some(init) = init {
+                        init.check_list(&dims, scope, constant, message);
+                    }
+                    Some(Ty::Array(dims.into_iter().map(Some).collect()))
+                }
+                None => {
+                    if let Some(init) = init {
+                        init.check_elems(scope, constant, message);
+                    }
+                    None
+                }
+            }
+        };
+        scope.new_value(&self.id, Symbol { ty, is_const: false, num: None, span: self.span });
+    }
+}
+
+impl<'ast> Check<'ast> for FuncDef {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let params: Vec<_> = self.params.iter().map(|param| param.check(scope)).collect();
+        let ret = match self.ty {
+            FuncType::Int => Ty::Int,
+            FuncType::Void => Ty::Void,
+        };
+        // the runtime calls `main` with no arguments and exits with what it returns
+        if self.id == "main" && (ret != Ty::Int || !self.params.is_empty()) {
+            scope.report(Diagnostic::error("function `main` must be declared as `int main()`").with_primary(self.span));
+        }
+        scope.new_func(&self.id, Signature { ret, params: params.clone(), span: Some(self.span) });
+
+        // parameters live in the same block as the outermost items of the body
+        scope.set_cur_func(Some(self));
+        scope.enter();
+        for (param, ty) in self.params.iter().zip(params) {
+            scope.new_value(&param.id, Symbol { ty, is_const: false, num: None, span: param.span });
+        }
+        for item in &self.body.items {
+            item.check(scope);
+        }
+        scope.exit();
+        scope.set_cur_func(None);
+    }
+}
+
+impl<'ast> Check<'ast> for FuncParam {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match &self.dims {
+            Some(dims) => {
+                let dims = check_dims(dims, scope)?;
+                Some(Ty::Array(std::iter::once(None).chain(dims.into_iter().map(Some)).collect()))
+            }
+            None => Some(Ty::Int),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Block {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        scope.enter();
+        for item in &self.items {
+            item.check(scope);
+        }
+        scope.exit();
+    }
+}
+
+impl<'ast> Check<'ast> for BlockItem {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Decl(decl) => decl.check(scope),
+            Self::Stmt(stmt) => stmt.check(scope),
+            Self::Error { .. } => {}
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Stmt {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Return(ret) => ret.check(scope),
+            Self::Assign(asg) => asg.check(scope),
+            Self::Exp { exp, .. } => {
+                if let Some(exp) = exp {
+                    exp.check(scope);
+                }
+            }
+            Self::Block(blk) => blk.check(scope),
+            Self::If(f) => f.check(scope),
+            Self::While(whl) => whl.check(scope),
+            Self::Break(brk) => {
+                if !scope.in_loop() {
+                    scope.report(Diagnostic::error("`break` outside of a loop").with_primary(brk.span));
+                }
+            }
+            Self::Continue(ctn) => {
+                if !scope.in_loop() {
+                    scope.report(Diagnostic::error("`continue` outside of a loop").with_primary(ctn.span));
+                }
+            }
+            Self::Error { .. } => {}
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Return {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let func = scope.cur_func().unwrap();
+        match (&func.ty, &self.exp) {
+            (FuncType::Int, Some(exp)) => {
+                let ty = exp.check(scope);
+                expect_int(scope, ty, exp.span());
+            }
+            (FuncType::Int, None) => {
+                scope.report(Diagnostic::error(format!("non-void function `{}` should return a value", func.id))
+                    .with_primary(self.span)
+                    .with_secondary(func.span, "function is declared to return `int` here"));
+            }
+            (FuncType::Void, Some(exp)) => {
+                exp.check(scope);
+                scope.report(Diagnostic::error(format!("void function `{}` should not return a value", func.id))
+                    .with_primary(exp.span())
+                    .with_secondary(func.span, "function is declared to return `void` here"));
+            }
+            (FuncType::Void, None) => {}
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Assign {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        if let Some(ty) = self.lval.check(scope) {
+            let symbol = scope.value(&self.lval.id).unwrap();
+            if symbol.is_const {
+                let diagnostic = Diagnostic::error(format!("can't assign to constant `{}`", self.lval.id))
+                    .with_primary(self.lval.span)
+                    .with_secondary(symbol.span, "constant is defined here");
+                scope.report(diagnostic);
+            }
+            else if ty != Ty::Int {
+                scope.report(Diagnostic::error(format!("can't assign to `{}` of type `{}`", self.lval.id, ty)).with_primary(self.lval.span));
+            }
+        }
+        let ty = self.exp.check(scope);
+        expect_int(scope, ty, self.exp.span());
+    }
+}
+
+impl<'ast> Check<'ast> for If {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let ty = self.cond.check(scope);
+        expect_int(scope, ty, self.cond.span());
+        self.then.check(scope);
+        if let Some(els) = &self.els {
+            els.check(scope);
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for While {
+    type Out = ();
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let ty = self.cond.check(scope);
+        expect_int(scope, ty, self.cond.span());
+        scope.enter_loop();
+        self.body.check(scope);
+        scope.exit_loop();
+    }
+}
+
+impl<'ast> Check<'ast> for Exp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        self.lor.check(scope)
+    }
+}
+
+impl<'ast> Check<'ast> for LVal {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        for dim in &self.dims {
+            let ty = dim.check(scope);
+            expect_int(scope, ty, dim.span());
+        }
+
+        let ty = match scope.value(&self.id) {
+            Some(symbol) => symbol.ty.clone()?,
+            None => {
+                scope.report(Diagnostic::error(format!("use of undeclared identifier `{}`", self.id)).with_primary(self.span));
+                return None;
+            }
+        };
+        match ty.index(self.dims.len()) {
+            Some(ty) => Some(ty),
+            None => {
+                scope.report(Diagnostic::error(format!("too many subscripts for `{}` of type `{}`", self.id, ty)).with_primary(self.span));
+                None
+            }
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for PrimaryExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Exp { exp, .. } => exp.check(scope),
+            Self::LVal(lval) => lval.check(scope),
+            Self::Num { num, span } => {
+                if *num < 0 {
+                    scope.report(Diagnostic::error("integer literal out of range").with_primary(*span));
+                }
+                Some(Ty::Int)
+            }
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for UnaryExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Primary(primary) => primary.check(scope),
+            Self::Call(call) => call.check(scope),
+            // `2147483648` is only in range right after a minus, which makes it INT_MIN
+            Self::Unary { op: UnaryOp::Neg, unary, .. } if matches!(**unary, Self::Primary(PrimaryExp::Num { num: i32::MIN, .. })) => Some(Ty::Int),
+            Self::Unary { unary, .. } => {
+                let ty = unary.check(scope);
+                expect_int(scope, ty, unary.span());
+                Some(Ty::Int)
+            }
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for Call {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        let args: Vec<_> = self.args.iter().map(|arg| arg.check(scope)).collect();
+        let (ret, params, span) = match scope.func(&self.id) {
+            Some(func) => (func.ret.clone(), func.params.clone(), func.span),
+            None => {
+                scope.report(Diagnostic::error(format!("call to undeclared function `{}`", self.id)).with_primary(self.span));
+                return None;
+            }
+        };
+
+        if params.len() != args.len() {
+            let diagnostic = Diagnostic::error(format!("function `{}` takes {} argument(s) but {} were given", self.id, params.len(), args.len()))
+                .with_primary(self.span);
+            scope.report(match span {
+                Some(span) => diagnostic.with_secondary(span, "function is defined here"),
+                None => diagnostic,
+            });
+        }
+        else {
+            for ((arg, ty), param) in self.args.iter().zip(args).zip(params) {
+                if let (Some(ty), Some(param)) = (ty, param) {
+                    if !param.accepts(&ty) {
+                        let message = format!("mismatched argument of `{}`: expected `{}`, found `{}`", self.id, param, ty);
+                        scope.report(Diagnostic::error(message).with_primary(arg.span()));
+                    }
+                }
+            }
+        }
+        Some(ret)
+    }
+}
+
+/// Check both operands of a binary expression, which must be `int`s.
+macro_rules! check_binary {
+    ($scope: expr, $lhs: expr, $rhs: expr) => {{
+        let ty = $lhs.check($scope);
+        expect_int($scope, ty, $lhs.span());
+        let ty = $rhs.check($scope);
+        expect_int($scope, ty, $rhs.span());
+        Some(Ty::Int)
+    }};
+}
+
+impl<'ast> Check<'ast> for MulExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Unary(unary) => unary.check(scope),
+            Self::Mul(mul, _, unary) => check_binary!(scope, mul, unary),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for AddExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Mul(mul) => mul.check(scope),
+            Self::Add(add, _, mul) => check_binary!(scope, add, mul),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for RelExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Add(add) => add.check(scope),
+            Self::Rel(rel, _, add) => check_binary!(scope, rel, add),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for EqExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Rel(rel) => rel.check(scope),
+            Self::Eq(eq, _, rel) => check_binary!(scope, eq, rel),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for LAndExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::Eq(eq) => eq.check(scope),
+            Self::LAnd(land, eq) => check_binary!(scope, land, eq),
+        }
+    }
+}
+
+impl<'ast> Check<'ast> for LOrExp {
+    type Out = Option<Ty>;
+    fn check(&'ast self, scope: &mut Scope<'ast>) -> Self::Out {
+        match self {
+            Self::LAnd(land) => land.check(scope),
+            Self::LOr(lor, land) => check_binary!(scope, lor, land),
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! # Check
+//!
+//! This module checks the AST before any IR is built, and reports every error found at once.
+//!
+//! The checker:
+//! * resolves every identifier, in the same scopes as `mem` does.
+//! * checks types of expressions, including the number and shapes of arguments of calls.
+//! * rejects assignment to constants, `break`/`continue` outside of loops and mismatched `return`s.
+//! * requires array dimensions and initializers of constants and globals to be constant.
+//!
+
+mod analyze;
+mod scope;
+mod ty;
+
+use crate::ast::CompUnit;
+use crate::check::analyze::Check;
+use crate::check::scope::Scope;
+use crate::diagnostic::Diagnostic;
+
+pub fn check(ast: &CompUnit) -> Vec<Diagnostic> {
+    let mut scope = Scope::new();
+    ast.check(&mut scope);
+    scope.into_diagnostics()
+}
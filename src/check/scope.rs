@@ -0,0 +1,147 @@
+//! # Scope
+//!
+//! This scope contains everything the checker knows at a point of the program.
+//! * project from string to symbol, one table for each block.
+//! * signatures of functions.
+//! * the function being checked and the depth of loops.
+//! * diagnostics reported so far.
+//!
+
+use crate::ast::FuncDef;
+use crate::check::ty::Ty;
+use crate::mem::eval::Constants;
+use crate::diagnostic::{ Diagnostic, Span };
+use std::collections::HashMap;
+
+pub struct Symbol {
+    /// `None` if the type can't be decided because of an error reported before.
+    pub ty: Option<Ty>,
+    pub is_const: bool,
+    /// Value of a constant scalar, which can be used in constant expressions.
+    pub num: Option<i32>,
+    pub span: Span,
+}
+
+pub struct Signature {
+    pub ret: Ty,
+    /// `None` for a parameter whose type can't be decided, it accepts any argument.
+    pub params: Vec<Option<Ty>>,
+    /// Library functions have no span.
+    pub span: Option<Span>,
+}
+
+pub struct Scope<'ast> {
+    values: Vec<HashMap<&'ast str, Symbol>>,
+    funcs: HashMap<&'ast str, Signature>,
+
+    cur_func: Option<&'ast FuncDef>,
+    loop_depth: usize,
+
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'ast> Scope<'ast> {
+    pub fn new() -> Self {
+        Self {
+            values: vec![HashMap::new()],
+            funcs: HashMap::new(),
+
+            cur_func: None,
+            loop_depth: 0,
+
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn value(&self, id: &str) -> Option<&Symbol> {
+        self.values.iter().rev().find_map(|scope| scope.get(id))
+    }
+
+    /// Define a value in the innermost block, report it if the name is taken.
+    pub fn new_value(&mut self, id: &'ast str, symbol: Symbol) {
+        let previous = match self.values.last().unwrap().get(id) {
+            Some(previous) => Some(Some(previous.span)),
+            // global variables share names with functions
+            None if self.is_global() => self.funcs.get(id).map(|func| func.span),
+            None => None,
+        };
+        match previous {
+            Some(previous) => self.redefinition(id, symbol.span, previous),
+            None => {
+                self.values.last_mut().unwrap().insert(id, symbol);
+            }
+        }
+    }
+
+    pub fn enter(&mut self) {
+        self.values.push(HashMap::new());
+    }
+
+    pub fn exit(&mut self) {
+        self.values.pop();
+    }
+
+    pub fn is_global(&self) -> bool {
+        self.values.len() == 1
+    }
+
+    pub fn func(&self, id: &str) -> Option<&Signature> {
+        self.funcs.get(id)
+    }
+
+    /// Define a function, report it if the name is taken.
+    pub fn new_func(&mut self, id: &'ast str, signature: Signature) {
+        let previous = match self.funcs.get(id) {
+            Some(previous) => Some(previous.span),
+            None => self.values[0].get(id).map(|value| Some(value.span)),
+        };
+        match (previous, signature.span) {
+            (Some(previous), Some(span)) => self.redefinition(id, span, previous),
+            _ => {
+                self.funcs.insert(id, signature);
+            }
+        }
+    }
+
+    fn redefinition(&mut self, id: &str, span: Span, previous: Option<Span>) {
+        let diagnostic = Diagnostic::error(format!("redefinition of `{}`", id)).with_primary(span);
+        self.report(match previous {
+            Some(previous) => diagnostic.with_secondary(previous, "previous definition is here"),
+            None => Diagnostic::error(format!("redefinition of library function `{}`", id)).with_primary(span),
+        });
+    }
+
+    pub fn cur_func(&self) -> Option<&'ast FuncDef> {
+        self.cur_func
+    }
+
+    pub fn set_cur_func(&mut self, func: Option<&'ast FuncDef>) {
+        self.cur_func = func;
+    }
+
+    pub fn enter_loop(&mut self) {
+        self.loop_depth += 1;
+    }
+
+    pub fn exit_loop(&mut self) {
+        self.loop_depth -= 1;
+    }
+
+    pub fn in_loop(&self) -> bool {
+        self.loop_depth > 0
+    }
+
+    pub fn report(&mut self, diagnostic: Diagnostic) {
+        self.diagnostics.push(diagnostic);
+    }
+
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.diagnostics
+    }
+}
+
+impl<'ast> Constants for Scope<'ast> {
+    fn constant(&self, id: &str) -> Option<i32> {
+        self.value(id).and_then(|symbol| symbol.num)
+    }
+}
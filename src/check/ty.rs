@@ -0,0 +1,55 @@
+//! # Ty
+//!
+//! In this file, we define types of SysY expressions seen by the checker.
+//!
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Ty {
+    Int,
+    Void,
+    /// Lengths of each dimension, only the first one of an array parameter is unknown.
+    Array(Vec<Option<usize>>),
+}
+
+impl Ty {
+    /// Type after `count` subscripts, `None` if there are too many of them.
+    pub fn index(&self, count: usize) -> Option<Ty> {
+        match self {
+            Self::Array(dims) if count < dims.len() => Some(Self::Array(dims[count..].to_vec())),
+            Self::Array(dims) if count == dims.len() => Some(Self::Int),
+            Self::Int if count == 0 => Some(Self::Int),
+            _ => None,
+        }
+    }
+
+    /// Whether an argument of type `arg` can be passed to a parameter of this type.
+    /// The first dimension of an array decays, the others must be the same.
+    pub fn accepts(&self, arg: &Ty) -> bool {
+        match (self, arg) {
+            (Self::Int, Self::Int) => true,
+            (Self::Array(param), Self::Array(arg)) => param.len() == arg.len() && param[1..] == arg[1..],
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Ty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int => write!(f, "int"),
+            Self::Void => write!(f, "void"),
+            Self::Array(dims) => {
+                write!(f, "int")?;
+                for dim in dims {
+                    match dim {
+                        Some(len) => write!(f, "[{}]", len)?,
+                        None => write!(f, "[]")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+//! # Index
+//!
+//! In this file, we define an index over the source, which maps byte offsets to lines and columns.
+//!
+
+pub struct LineIndex<'s> {
+    source: &'s str,
+    /// Byte offset where each line begins.
+    starts: Vec<usize>,
+}
+
+impl<'s> LineIndex<'s> {
+    pub fn new(source: &'s str) -> Self {
+        let mut starts = vec![0];
+        starts.extend(source.match_indices('\n').map(|(pos, _)| pos + 1));
+        Self { source, starts }
+    }
+
+    pub fn source(&self) -> &'s str {
+        self.source
+    }
+
+    /// Line number and column number of the offset, both start from 1.
+    /// Columns are counted in characters.
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = self.starts.partition_point(|&start| start <= offset);
+        let column = self.source[self.starts[line - 1]..offset].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Byte offset where the line begins.
+    pub fn line_start(&self, line: usize) -> usize {
+        self.starts[line - 1]
+    }
+
+    /// Text of the line without the line break.
+    pub fn line(&self, line: usize) -> &'s str {
+        let start = self.starts[line - 1];
+        let end = self.starts.get(line).map_or(self.source.len(), |&next| next - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+}
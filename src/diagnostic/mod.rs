@@ -0,0 +1,81 @@
+//! # Diagnostic
+//!
+//! In this module, we define diagnostics, which are reported to the user instead of panicking.
+//! A diagnostic has a severity, a message, a primary span and some secondary spans with labels.
+//!
+//! Note:
+//! * spans are byte offsets into the source, the same as locations reported by lalrpop.
+//! * `LineIndex` turns them into line and column numbers.
+//!
+
+mod render;
+mod parse;
+mod index;
+
+pub use crate::diagnostic::parse::from_parse_error;
+pub use crate::diagnostic::index::LineIndex;
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    /// The smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Option<Span>,
+    pub secondary: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: String) -> Self {
+        Self {
+            severity,
+            message,
+            primary: None,
+            secondary: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message.into())
+    }
+
+    pub fn with_primary(mut self, span: Span) -> Self {
+        self.primary = Some(span);
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, label: impl Into<String>) -> Self {
+        self.secondary.push((span, label.into()));
+        self
+    }
+}
@@ -0,0 +1,53 @@
+//! # Parse
+//!
+//! In this file, we turn errors reported by the lalrpop parser into diagnostics.
+//!
+
+use crate::diagnostic::{ Diagnostic, Span };
+use lalrpop_util::ParseError;
+use lalrpop_util::lexer::Token;
+
+/// Describe a terminal expected by the grammar in the user's words.
+fn describe(expected: &str) -> String {
+    if expected.starts_with("r#") {
+        if expected.contains("_a-zA-Z") {
+            "identifier".to_string()
+        }
+        else {
+            "integer literal".to_string()
+        }
+    }
+    else {
+        format!("`{}`", expected.trim_matches('"'))
+    }
+}
+
+fn expected_list(expected: &[String]) -> String {
+    let mut names: Vec<_> = expected.iter().map(|e| describe(e)).collect();
+    names.dedup();
+    match names.len() {
+        0 => String::new(),
+        1 => format!(", expected {}", names[0]),
+        _ => format!(", expected one of {}", names.join(", ")),
+    }
+}
+
+pub fn from_parse_error(error: ParseError<usize, Token<'_>, Diagnostic>) -> Diagnostic {
+    match error {
+        ParseError::InvalidToken { location } => {
+            Diagnostic::error("invalid token").with_primary(Span::new(location, location + 1))
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            Diagnostic::error(format!("unexpected end of file{}", expected_list(&expected)))
+                .with_primary(Span::new(location, location))
+        }
+        ParseError::UnrecognizedToken { token: (start, token, end), expected } => {
+            Diagnostic::error(format!("unexpected `{}`{}", token.1, expected_list(&expected)))
+                .with_primary(Span::new(start, end))
+        }
+        ParseError::ExtraToken { token: (start, token, end) } => {
+            Diagnostic::error(format!("extra token `{}`", token.1)).with_primary(Span::new(start, end))
+        }
+        ParseError::User { error } => error,
+    }
+}
@@ -0,0 +1,98 @@
+//! # Render
+//!
+//! In this file, we render a diagnostic with caret-underlined excerpts of the source.
+//!
+
+use crate::diagnostic::{ Diagnostic, Span, LineIndex };
+
+impl Diagnostic {
+    /// Render the diagnostic as `path:line:col: severity: message` followed by excerpts.
+    pub fn render(&self, path: &str, index: &LineIndex) -> String {
+        let mut result = match self.primary {
+            Some(span) => {
+                let (line, column) = index.line_col(span.start);
+                format!("{}:{}:{}: {}: {}\n", path, line, column, self.severity, self.message)
+            }
+            None => format!("{}: {}: {}\n", path, self.severity, self.message),
+        };
+
+        let mut marks: Vec<(Span, char, &str)> = vec![];
+        if let Some(span) = self.primary {
+            marks.push((span, '^', ""));
+        }
+        for (span, label) in &self.secondary {
+            marks.push((*span, '-', label));
+        }
+        if marks.is_empty() {
+            return result;
+        }
+        marks.sort_by_key(|(span, _, _)| span.start);
+
+        let last = marks.iter().map(|(span, _, _)| index.line_col(span.start).0).max().unwrap();
+        let width = last.to_string().len();
+        result.push_str(&format!("{} |\n", " ".repeat(width)));
+        let mut rest = &marks[..];
+        while let Some((first, _, _)) = rest.first() {
+            // marks on the same line share one excerpt
+            let line = index.line_col(first.start).0;
+            let count = rest.iter().take_while(|(span, _, _)| index.line_col(span.start).0 == line).count();
+            let text = index.line(line);
+            result.push_str(&format!("{:>width$} | {}\n", line, text, width = width));
+            for row in underline(&rest[..count], index.line_start(line), text, index.source().len()) {
+                result.push_str(&format!("{} | {}\n", " ".repeat(width), row.trim_end()));
+            }
+            rest = &rest[count..];
+        }
+        result
+    }
+}
+
+/// Rows under a line of the source, marking the spans on it. The label of the last mark follows it,
+/// and other labels are below their marks, connected to them by `|`.
+fn underline(marks: &[(Span, char, &str)], begin: usize, text: &str, len: usize) -> Vec<String> {
+    // columns are counted in chars, and tabs are kept in the padding, so the marks line up with the excerpt
+    let chars: Vec<char> = text.chars().collect();
+    let column = |offset: usize| text[..(offset.min(len) - begin).min(text.len())].chars().count();
+    let blank = |width: usize| -> Vec<char> {
+        (0..width).map(|i| if chars.get(i) == Some(&'\t') { '\t' } else { ' ' }).collect()
+    };
+
+    let spans: Vec<(usize, usize)> = marks.iter().map(|(span, _, _)| {
+        let start = column(span.start);
+        let end = column(span.end.min(begin + text.len()).max(span.start));
+        (start, end.max(start + 1))
+    }).collect();
+    let mut row = blank(spans.iter().map(|&(_, end)| end).max().unwrap());
+    // the primary mark is drawn last, so it's seen where marks overlap
+    for (&(start, end), (_, mark, _)) in spans.iter().zip(marks).filter(|(_, (_, mark, _))| *mark != '^') {
+        row[start..end].iter_mut().for_each(|c| *c = *mark);
+    }
+    for (&(start, end), (_, mark, _)) in spans.iter().zip(marks).filter(|(_, (_, mark, _))| *mark == '^') {
+        row[start..end].iter_mut().for_each(|c| *c = *mark);
+    }
+    let mut rows = vec![row.into_iter().collect::<String>()];
+
+    let mut labels: Vec<(usize, &str)> = spans.iter().zip(marks)
+        .filter(|(_, (_, _, label))| !label.is_empty())
+        .map(|(&(start, _), (_, _, label))| (start, *label))
+        .collect();
+    labels.sort_by_key(|&(start, _)| start);
+    let rightmost = spans.iter().map(|&(start, _)| start).max().unwrap();
+    if labels.last().is_some_and(|&(start, _)| start == rightmost) {
+        let (_, label) = labels.pop().unwrap();
+        rows[0] = format!("{} {}", rows[0], label);
+    }
+    while let Some((start, label)) = labels.pop() {
+        let mut connector = blank(start + 1);
+        for &(column, _) in labels.iter().chain([(start, label)].iter()) {
+            connector[column] = '|';
+        }
+        let mut row = blank(start);
+        for &(column, _) in &labels {
+            row[column] = '|';
+        }
+        rows.push(connector.into_iter().collect());
+        rows.push(row.into_iter().collect::<String>() + label);
+    }
+    rows
+}
@@ -0,0 +1,447 @@
+//! # Assembler
+//!
+//! In this file, we define an assembler for the subset of RISC-V assembly emitted by `asm::Writer`.
+//! Instructions are not encoded into machine words. Instead, they are decoded into `Inst` directly,
+//! pseudo instructions are expanded and labels are resolved to addresses.
+//!
+
+use std::collections::HashMap;
+
+/// Base address of the data section.
+pub const DATA_BASE: u32 = 0x0001_0000;
+/// Base address of the text section.
+pub const TEXT_BASE: u32 = 0x4000_0000;
+/// Base address of the SysY runtime functions, each function takes 4 bytes.
+pub const RUNTIME_BASE: u32 = 0x7000_0000;
+/// Functions of the SysY runtime library, in the order of their addresses.
+pub const RUNTIME: [&str; 8] = ["getint", "getch", "getarray", "putint", "putch", "putarray", "starttime", "stoptime"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImmOp {
+    Addi,
+    Slti,
+    Sltiu,
+    Xori,
+    Ori,
+    Andi,
+    Slli,
+    Srli,
+    Srai,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegOp {
+    Add,
+    Sub,
+    Sll,
+    Slt,
+    Sltu,
+    Xor,
+    Srl,
+    Sra,
+    Or,
+    And,
+    Mul,
+    Mulh,
+    Mulhsu,
+    Mulhu,
+    Div,
+    Divu,
+    Rem,
+    Remu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BranchOp {
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Bltu,
+    Bgeu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Inst {
+    /// Load an immediate or an address, expanded from `li`, `la` and `lui`.
+    Li(u8, i32),
+    Imm(ImmOp, u8, u8, i32),
+    Reg(RegOp, u8, u8, u8),
+    Lw(u8, u8, i32),
+    Sw(u8, u8, i32),
+    Branch(BranchOp, u8, u8, u32),
+    Jal(u8, u32),
+    Jalr(u8, u8, i32),
+}
+
+/// Assembled program, which can be loaded into a machine.
+pub struct Image {
+    pub data: Vec<u8>,
+    pub text: Vec<Inst>,
+    pub symbols: HashMap<String, u32>,
+}
+
+impl Image {
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+}
+
+#[derive(Debug)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Data,
+    Text,
+}
+
+/// Assemble the source text into an image.
+pub fn assemble(source: &str) -> Result<Image, AsmError> {
+    let lines: Vec<_> = source.lines().enumerate().map(|(no, line)| {
+        let line = match line.find('#') {
+            Some(pos) => &line[..pos],
+            None => line,
+        };
+        (no + 1, line.trim())
+    }).filter(|(_, line)| !line.is_empty()).collect();
+
+    // first pass: document addresses of all labels
+    let mut symbols = HashMap::new();
+    for (i, name) in RUNTIME.iter().enumerate() {
+        symbols.insert(name.to_string(), RUNTIME_BASE + 4 * i as u32);
+    }
+    let mut section = Section::Text;
+    let mut data_size = 0u32;
+    let mut text_size = 0u32;
+    for &(no, line) in &lines {
+        let (label, rest) = split_label(line);
+        if let Some(label) = label {
+            let address = match section {
+                Section::Data => DATA_BASE + data_size,
+                Section::Text => TEXT_BASE + text_size * 4,
+            };
+            symbols.insert(label.to_string(), address);
+        }
+        if rest.is_empty() {
+            continue;
+        }
+        let (op, args) = split_op(rest);
+        match op {
+            ".data" => section = Section::Data,
+            ".text" => section = Section::Text,
+            ".globl" | ".global" | ".align" | ".p2align" | ".section" | ".type" | ".size" => {}
+            ".word" => data_size += 4 * args.len() as u32,
+            ".zero" | ".space" => data_size += parse_int(args.first().copied().unwrap_or(""), no)? as u32,
+            _ => {
+                if section == Section::Data {
+                    return Err(AsmError { line: no, message: format!("instruction `{}` in data section", op) });
+                }
+                text_size += 1;
+            }
+        }
+        if section == Section::Data && !data_size.is_multiple_of(4) && op != ".zero" && op != ".space" {
+            return Err(AsmError { line: no, message: "misaligned data".to_string() });
+        }
+    }
+
+    // second pass: fill data and decode instructions, the first pass made sure they are in the text section
+    let mut data = Vec::with_capacity(data_size as usize);
+    let mut text = Vec::with_capacity(text_size as usize);
+    for &(no, line) in &lines {
+        let (_, rest) = split_label(line);
+        if rest.is_empty() {
+            continue;
+        }
+        let (op, args) = split_op(rest);
+        match op {
+            ".data" | ".text" | ".globl" | ".global" | ".align" | ".p2align" | ".section" | ".type" | ".size" => {}
+            ".word" => {
+                for arg in args {
+                    let word = match symbols.get(arg) {
+                        Some(address) => *address as i32,
+                        None => parse_int(arg, no)?,
+                    };
+                    data.extend_from_slice(&word.to_le_bytes());
+                }
+            }
+            ".zero" | ".space" => {
+                let size = parse_int(args.first().copied().unwrap_or(""), no)? as usize;
+                data.resize(data.len() + size, 0);
+            }
+            _ => text.push(decode(op, &args, &symbols, no)?),
+        }
+    }
+
+    Ok(Image { data, text, symbols })
+}
+
+fn split_label(line: &str) -> (Option<&str>, &str) {
+    match line.find(':') {
+        Some(pos) if line[..pos].chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '$') => {
+            (Some(&line[..pos]), line[pos + 1..].trim())
+        }
+        _ => (None, line),
+    }
+}
+
+fn split_op(line: &str) -> (&str, Vec<&str>) {
+    match line.find(char::is_whitespace) {
+        Some(pos) => {
+            let args = line[pos..].split(',').map(|arg| arg.trim()).filter(|arg| !arg.is_empty()).collect();
+            (&line[..pos], args)
+        }
+        None => (line, vec![]),
+    }
+}
+
+fn parse_int(s: &str, line: usize) -> Result<i32, AsmError> {
+    let (neg, digits) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16)
+    } else {
+        digits.parse::<i64>()
+    };
+    match value {
+        Ok(value) if value <= u32::MAX as i64 => Ok(if neg { (value as i32).wrapping_neg() } else { value as i32 }),
+        _ => Err(AsmError { line, message: format!("invalid integer `{}`", s) }),
+    }
+}
+
+fn parse_reg(s: &str) -> Option<u8> {
+    let reg = match s {
+        "zero" => 0,
+        "ra" => 1,
+        "sp" => 2,
+        "gp" => 3,
+        "tp" => 4,
+        "t0" => 5,
+        "t1" => 6,
+        "t2" => 7,
+        "s0" | "fp" => 8,
+        "s1" => 9,
+        "t3" => 28,
+        "t4" => 29,
+        "t5" => 30,
+        "t6" => 31,
+        _ => {
+            if let Some(n) = s.strip_prefix('a') {
+                let n: u8 = n.parse().ok()?;
+                if n > 7 {
+                    return None;
+                }
+                10 + n
+            }
+            else if let Some(n) = s.strip_prefix('s') {
+                let n: u8 = n.parse().ok()?;
+                if !(2..=11).contains(&n) {
+                    return None;
+                }
+                16 + n
+            }
+            else if let Some(n) = s.strip_prefix('x') {
+                let n: u8 = n.parse().ok()?;
+                if n > 31 {
+                    return None;
+                }
+                n
+            }
+            else {
+                return None;
+            }
+        }
+    };
+    Some(reg)
+}
+
+fn decode(op: &str, args: &[&str], symbols: &HashMap<String, u32>, line: usize) -> Result<Inst, AsmError> {
+    let error = |message: String| AsmError { line, message };
+    let expect = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(AsmError { line, message: format!("`{}` expects {} operands, found {}", op, n, args.len()) })
+        }
+    };
+    let reg = |i: usize| parse_reg(args[i]).ok_or_else(|| AsmError { line, message: format!("invalid register `{}`", args[i]) });
+    let imm = |i: usize| parse_int(args[i], line);
+    let label = |i: usize| symbols.get(args[i]).copied().ok_or_else(|| AsmError { line, message: format!("undefined label `{}`", args[i]) });
+    let mem = |i: usize| -> Result<(i32, u8), AsmError> {
+        let arg = args[i];
+        let open = arg.find('(').ok_or_else(|| AsmError { line, message: format!("invalid memory operand `{}`", arg) })?;
+        let close = arg.rfind(')').ok_or_else(|| AsmError { line, message: format!("invalid memory operand `{}`", arg) })?;
+        let offset = if open == 0 { 0 } else { parse_int(&arg[..open], line)? };
+        let base = parse_reg(arg[open + 1..close].trim()).ok_or_else(|| AsmError { line, message: format!("invalid register in `{}`", arg) })?;
+        Ok((offset, base))
+    };
+
+    let imm_op = |op: ImmOp| -> Result<Inst, AsmError> {
+        expect(3)?;
+        Ok(Inst::Imm(op, reg(0)?, reg(1)?, imm(2)?))
+    };
+    let reg_op = |op: RegOp| -> Result<Inst, AsmError> {
+        expect(3)?;
+        Ok(Inst::Reg(op, reg(0)?, reg(1)?, reg(2)?))
+    };
+    let branch = |op: BranchOp, swap: bool| -> Result<Inst, AsmError> {
+        expect(3)?;
+        let (lhs, rhs) = if swap { (reg(1)?, reg(0)?) } else { (reg(0)?, reg(1)?) };
+        Ok(Inst::Branch(op, lhs, rhs, label(2)?))
+    };
+    let branch_zero = |op: BranchOp, swap: bool| -> Result<Inst, AsmError> {
+        expect(2)?;
+        let (lhs, rhs) = if swap { (0, reg(0)?) } else { (reg(0)?, 0) };
+        Ok(Inst::Branch(op, lhs, rhs, label(1)?))
+    };
+
+    let inst = match op {
+        "addi" => imm_op(ImmOp::Addi)?,
+        "slti" => imm_op(ImmOp::Slti)?,
+        "sltiu" => imm_op(ImmOp::Sltiu)?,
+        "xori" => imm_op(ImmOp::Xori)?,
+        "ori" => imm_op(ImmOp::Ori)?,
+        "andi" => imm_op(ImmOp::Andi)?,
+        "slli" => imm_op(ImmOp::Slli)?,
+        "srli" => imm_op(ImmOp::Srli)?,
+        "srai" => imm_op(ImmOp::Srai)?,
+        "add" => reg_op(RegOp::Add)?,
+        "sub" => reg_op(RegOp::Sub)?,
+        "sll" => reg_op(RegOp::Sll)?,
+        "slt" => reg_op(RegOp::Slt)?,
+        "sltu" => reg_op(RegOp::Sltu)?,
+        "xor" => reg_op(RegOp::Xor)?,
+        "srl" => reg_op(RegOp::Srl)?,
+        "sra" => reg_op(RegOp::Sra)?,
+        "or" => reg_op(RegOp::Or)?,
+        "and" => reg_op(RegOp::And)?,
+        "mul" => reg_op(RegOp::Mul)?,
+        "mulh" => reg_op(RegOp::Mulh)?,
+        "mulhsu" => reg_op(RegOp::Mulhsu)?,
+        "mulhu" => reg_op(RegOp::Mulhu)?,
+        "div" => reg_op(RegOp::Div)?,
+        "divu" => reg_op(RegOp::Divu)?,
+        "rem" => reg_op(RegOp::Rem)?,
+        "remu" => reg_op(RegOp::Remu)?,
+        "sgt" => {
+            expect(3)?;
+            Inst::Reg(RegOp::Slt, reg(0)?, reg(2)?, reg(1)?)
+        }
+        "sgtu" => {
+            expect(3)?;
+            Inst::Reg(RegOp::Sltu, reg(0)?, reg(2)?, reg(1)?)
+        }
+        "lui" => {
+            expect(2)?;
+            Inst::Li(reg(0)?, imm(1)? << 12)
+        }
+        "li" => {
+            expect(2)?;
+            Inst::Li(reg(0)?, imm(1)?)
+        }
+        "la" => {
+            expect(2)?;
+            Inst::Li(reg(0)?, label(1)? as i32)
+        }
+        "mv" => {
+            expect(2)?;
+            Inst::Imm(ImmOp::Addi, reg(0)?, reg(1)?, 0)
+        }
+        "not" => {
+            expect(2)?;
+            Inst::Imm(ImmOp::Xori, reg(0)?, reg(1)?, -1)
+        }
+        "neg" => {
+            expect(2)?;
+            Inst::Reg(RegOp::Sub, reg(0)?, 0, reg(1)?)
+        }
+        "seqz" => {
+            expect(2)?;
+            Inst::Imm(ImmOp::Sltiu, reg(0)?, reg(1)?, 1)
+        }
+        "snez" => {
+            expect(2)?;
+            Inst::Reg(RegOp::Sltu, reg(0)?, 0, reg(1)?)
+        }
+        "sltz" => {
+            expect(2)?;
+            Inst::Reg(RegOp::Slt, reg(0)?, reg(1)?, 0)
+        }
+        "sgtz" => {
+            expect(2)?;
+            Inst::Reg(RegOp::Slt, reg(0)?, 0, reg(1)?)
+        }
+        "lw" => {
+            expect(2)?;
+            let (offset, base) = mem(1)?;
+            Inst::Lw(reg(0)?, base, offset)
+        }
+        "sw" => {
+            expect(2)?;
+            let (offset, base) = mem(1)?;
+            Inst::Sw(reg(0)?, base, offset)
+        }
+        "beq" => branch(BranchOp::Beq, false)?,
+        "bne" => branch(BranchOp::Bne, false)?,
+        "blt" => branch(BranchOp::Blt, false)?,
+        "bge" => branch(BranchOp::Bge, false)?,
+        "bltu" => branch(BranchOp::Bltu, false)?,
+        "bgeu" => branch(BranchOp::Bgeu, false)?,
+        "bgt" => branch(BranchOp::Blt, true)?,
+        "ble" => branch(BranchOp::Bge, true)?,
+        "bgtu" => branch(BranchOp::Bltu, true)?,
+        "bleu" => branch(BranchOp::Bgeu, true)?,
+        "beqz" => branch_zero(BranchOp::Beq, false)?,
+        "bnez" => branch_zero(BranchOp::Bne, false)?,
+        "bltz" => branch_zero(BranchOp::Blt, false)?,
+        "bgez" => branch_zero(BranchOp::Bge, false)?,
+        "blez" => branch_zero(BranchOp::Bge, true)?,
+        "bgtz" => branch_zero(BranchOp::Blt, true)?,
+        "j" => {
+            expect(1)?;
+            Inst::Jal(0, label(0)?)
+        }
+        "jal" => match args.len() {
+            1 => Inst::Jal(1, label(0)?),
+            _ => {
+                expect(2)?;
+                Inst::Jal(reg(0)?, label(1)?)
+            }
+        },
+        "call" => {
+            expect(1)?;
+            Inst::Jal(1, label(0)?)
+        }
+        "tail" => {
+            expect(1)?;
+            Inst::Jal(0, label(0)?)
+        }
+        "jr" => {
+            expect(1)?;
+            Inst::Jalr(0, reg(0)?, 0)
+        }
+        "jalr" => match args.len() {
+            1 => Inst::Jalr(1, reg(0)?, 0),
+            _ => {
+                expect(2)?;
+                let (offset, base) = mem(1)?;
+                Inst::Jalr(reg(0)?, base, offset)
+            }
+        },
+        "ret" => Inst::Jalr(0, 1, 0),
+        "nop" => Inst::Imm(ImmOp::Addi, 0, 0, 0),
+        _ => return Err(error(format!("unknown instruction `{}`", op))),
+    };
+    Ok(inst)
+}
@@ -0,0 +1,230 @@
+//! # Machine
+//!
+//! In this file, we define an RV32IM machine which executes an assembled image.
+//!
+
+use crate::emu::assembler::{ Image, Inst, ImmOp, RegOp, BranchOp, DATA_BASE, TEXT_BASE, RUNTIME_BASE, RUNTIME };
+use crate::emu::runtime::Runtime;
+use std::io::{ BufRead, Write };
+
+/// Size of the memory, the stack grows down from its top.
+const MEMORY_SIZE: u32 = 256 << 20;
+/// Return address of `main`, jumping to it stops the machine.
+const EXIT: u32 = 0xffff_fff0;
+
+#[derive(Debug)]
+pub enum Trap {
+    NoMain,
+    InvalidPc(u32),
+    InvalidAccess(u32),
+    StepLimit,
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for Trap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoMain => write!(f, "no `main` function"),
+            Self::InvalidPc(pc) => write!(f, "jump to invalid address {:#010x}", pc),
+            Self::InvalidAccess(address) => write!(f, "invalid memory access at {:#010x}", address),
+            Self::StepLimit => write!(f, "step limit exceeded"),
+            Self::Io(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+pub struct Machine<'i> {
+    image: &'i Image,
+    regs: [i32; 32],
+    pc: u32,
+    memory: Vec<u8>,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl<'i> Machine<'i> {
+    pub fn new(image: &'i Image) -> Self {
+        let mut memory = vec![0; MEMORY_SIZE as usize];
+        let begin = DATA_BASE as usize;
+        memory[begin..begin + image.data.len()].copy_from_slice(&image.data);
+        Self {
+            image,
+            regs: [0; 32],
+            pc: 0,
+            memory,
+            steps: 0,
+            step_limit: None,
+        }
+    }
+
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Run `main` until it returns. Return the exit code of the program.
+    pub fn run<R: BufRead, W: Write>(&mut self, runtime: &mut Runtime<R, W>) -> Result<i32, Trap> {
+        self.pc = self.image.symbol("main").ok_or(Trap::NoMain)?;
+        self.regs[1] = EXIT as i32;
+        self.regs[2] = MEMORY_SIZE as i32;
+
+        loop {
+            if self.pc == EXIT {
+                return Ok(self.regs[10]);
+            }
+            if let Some(limit) = self.step_limit {
+                if self.steps >= limit {
+                    return Err(Trap::StepLimit);
+                }
+            }
+            self.steps += 1;
+
+            if self.pc >= RUNTIME_BASE && self.pc < RUNTIME_BASE + 4 * RUNTIME.len() as u32 {
+                let index = ((self.pc - RUNTIME_BASE) / 4) as usize;
+                self.call_runtime(index, runtime)?;
+                self.pc = self.regs[1] as u32;
+                continue;
+            }
+
+            let index = self.pc.wrapping_sub(TEXT_BASE) / 4;
+            if !self.pc.is_multiple_of(4) || self.pc < TEXT_BASE || index as usize >= self.image.text.len() {
+                return Err(Trap::InvalidPc(self.pc));
+            }
+            let inst = self.image.text[index as usize];
+            let mut next = self.pc + 4;
+            match inst {
+                Inst::Li(rd, imm) => self.set(rd, imm),
+                Inst::Imm(op, rd, rs1, imm) => {
+                    let lhs = self.regs[rs1 as usize];
+                    let value = match op {
+                        ImmOp::Addi => lhs.wrapping_add(imm),
+                        ImmOp::Slti => (lhs < imm) as i32,
+                        ImmOp::Sltiu => ((lhs as u32) < (imm as u32)) as i32,
+                        ImmOp::Xori => lhs ^ imm,
+                        ImmOp::Ori => lhs | imm,
+                        ImmOp::Andi => lhs & imm,
+                        ImmOp::Slli => ((lhs as u32) << (imm & 31)) as i32,
+                        ImmOp::Srli => ((lhs as u32) >> (imm & 31)) as i32,
+                        ImmOp::Srai => lhs >> (imm & 31),
+                    };
+                    self.set(rd, value);
+                }
+                Inst::Reg(op, rd, rs1, rs2) => {
+                    let lhs = self.regs[rs1 as usize];
+                    let rhs = self.regs[rs2 as usize];
+                    let value = match op {
+                        RegOp::Add => lhs.wrapping_add(rhs),
+                        RegOp::Sub => lhs.wrapping_sub(rhs),
+                        RegOp::Sll => ((lhs as u32) << (rhs & 31)) as i32,
+                        RegOp::Slt => (lhs < rhs) as i32,
+                        RegOp::Sltu => ((lhs as u32) < (rhs as u32)) as i32,
+                        RegOp::Xor => lhs ^ rhs,
+                        RegOp::Srl => ((lhs as u32) >> (rhs & 31)) as i32,
+                        RegOp::Sra => lhs >> (rhs & 31),
+                        RegOp::Or => lhs | rhs,
+                        RegOp::And => lhs & rhs,
+                        RegOp::Mul => lhs.wrapping_mul(rhs),
+                        RegOp::Mulh => ((lhs as i64 * rhs as i64) >> 32) as i32,
+                        RegOp::Mulhsu => ((lhs as i64 * rhs as u32 as i64) >> 32) as i32,
+                        RegOp::Mulhu => ((lhs as u32 as u64 * rhs as u32 as u64) >> 32) as i32,
+                        RegOp::Div => if rhs == 0 { -1 } else { lhs.wrapping_div(rhs) },
+                        RegOp::Divu => if rhs == 0 { -1 } else { ((lhs as u32) / (rhs as u32)) as i32 },
+                        RegOp::Rem => if rhs == 0 { lhs } else { lhs.wrapping_rem(rhs) },
+                        RegOp::Remu => if rhs == 0 { lhs } else { ((lhs as u32) % (rhs as u32)) as i32 },
+                    };
+                    self.set(rd, value);
+                }
+                Inst::Lw(rd, base, offset) => {
+                    let address = (self.regs[base as usize] as u32).wrapping_add(offset as u32);
+                    let value = self.load(address)?;
+                    self.set(rd, value);
+                }
+                Inst::Sw(rs, base, offset) => {
+                    let address = (self.regs[base as usize] as u32).wrapping_add(offset as u32);
+                    self.store(address, self.regs[rs as usize])?;
+                }
+                Inst::Branch(op, rs1, rs2, target) => {
+                    let lhs = self.regs[rs1 as usize];
+                    let rhs = self.regs[rs2 as usize];
+                    let taken = match op {
+                        BranchOp::Beq => lhs == rhs,
+                        BranchOp::Bne => lhs != rhs,
+                        BranchOp::Blt => lhs < rhs,
+                        BranchOp::Bge => lhs >= rhs,
+                        BranchOp::Bltu => (lhs as u32) < (rhs as u32),
+                        BranchOp::Bgeu => (lhs as u32) >= (rhs as u32),
+                    };
+                    if taken {
+                        next = target;
+                    }
+                }
+                Inst::Jal(rd, target) => {
+                    self.set(rd, next as i32);
+                    next = target;
+                }
+                Inst::Jalr(rd, rs1, offset) => {
+                    let target = (self.regs[rs1 as usize] as u32).wrapping_add(offset as u32) & !1;
+                    self.set(rd, next as i32);
+                    next = target;
+                }
+            }
+            self.pc = next;
+        }
+    }
+
+    fn set(&mut self, rd: u8, value: i32) {
+        if rd != 0 {
+            self.regs[rd as usize] = value;
+        }
+    }
+
+    pub fn load(&self, address: u32) -> Result<i32, Trap> {
+        if !address.is_multiple_of(4) || !(DATA_BASE..=MEMORY_SIZE - 4).contains(&address) {
+            return Err(Trap::InvalidAccess(address));
+        }
+        let address = address as usize;
+        let bytes = [self.memory[address], self.memory[address + 1], self.memory[address + 2], self.memory[address + 3]];
+        Ok(i32::from_le_bytes(bytes))
+    }
+
+    pub fn store(&mut self, address: u32, value: i32) -> Result<(), Trap> {
+        if !address.is_multiple_of(4) || !(DATA_BASE..=MEMORY_SIZE - 4).contains(&address) {
+            return Err(Trap::InvalidAccess(address));
+        }
+        let address = address as usize;
+        self.memory[address..address + 4].copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn call_runtime<R: BufRead, W: Write>(&mut self, index: usize, runtime: &mut Runtime<R, W>) -> Result<(), Trap> {
+        let a0 = self.regs[10];
+        let a1 = self.regs[11];
+        match index {
+            0 => self.regs[10] = runtime.getint().map_err(Trap::Io)?,
+            1 => self.regs[10] = runtime.getch().map_err(Trap::Io)?,
+            2 => {
+                let values = runtime.getarray().map_err(Trap::Io)?;
+                for (i, value) in values.iter().enumerate() {
+                    self.store((a0 as u32).wrapping_add(4 * i as u32), *value)?;
+                }
+                self.regs[10] = values.len() as i32;
+            }
+            3 => runtime.putint(a0).map_err(Trap::Io)?,
+            4 => runtime.putch(a0).map_err(Trap::Io)?,
+            5 => {
+                let mut values = vec![];
+                for i in 0..a0.max(0) as u32 {
+                    values.push(self.load((a1 as u32).wrapping_add(4 * i))?);
+                }
+                runtime.putarray(&values).map_err(Trap::Io)?;
+            }
+            6 => runtime.starttime(),
+            7 => runtime.stoptime().map_err(Trap::Io)?,
+            _ => return Err(Trap::InvalidPc(self.pc)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! # Emu
+//!
+//! In this module, we run the generated assembly without a RISC-V toolchain.
+//! The assembly is assembled into an image, and executed by an RV32IM machine,
+//! with the SysY runtime library reading stdin and writing stdout.
+//!
+
+mod assembler;
+mod machine;
+pub mod runtime;
+
+use crate::emu::assembler::assemble;
+use crate::emu::machine::Machine;
+use crate::emu::runtime::Runtime;
+use std::io::{ stdin, stdout, BufWriter, Write };
+
+/// Run the assembly, return the exit code of the program, or the error stopping it.
+/// `steps` limits the number of instructions executed, and the number executed is reported with `stats`.
+pub fn run(source: &str, steps: Option<u64>, stats: bool) -> Result<i32, String> {
+    let image = assemble(source).map_err(|error| format!("invalid assembly, {}", error))?;
+    let mut machine = Machine::new(&image);
+    machine.set_step_limit(steps);
+
+    let mut runtime = Runtime::new(stdin().lock(), BufWriter::new(stdout().lock()));
+    let result = machine.run(&mut runtime);
+    // output before a trap is still written
+    runtime.output_mut().flush().map_err(|error| error.to_string())?;
+    if stats {
+        eprintln!("steps: {}", machine.steps());
+    }
+    if let Some(total) = runtime.total() {
+        eprintln!("{}", total);
+    }
+    result.map(|code| code & 0xff).map_err(|trap| trap.to_string())
+}
@@ -0,0 +1,111 @@
+//! # Runtime
+//!
+//! In this file, we define the SysY runtime library over a reader and a writer.
+//!
+
+use std::io::{ BufRead, Write, Result };
+use std::time::{ Duration, Instant };
+
+pub struct Runtime<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    timer: Option<Instant>,
+    /// Total time between all pairs of `starttime` and `stoptime`, if there's any.
+    elapsed: Option<Duration>,
+}
+
+impl<R: BufRead, W: Write> Runtime<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            input,
+            output,
+            timer: None,
+            elapsed: None,
+        }
+    }
+
+    /// The time between `starttime` and `stoptime` in the format of the runtime library, reported at exit.
+    pub fn total(&self) -> Option<String> {
+        let us = self.elapsed?.as_micros();
+        Some(format!("TOTAL: {}H-{}M-{}S-{}us", us / 3_600_000_000, us / 60_000_000 % 60, us / 1_000_000 % 60, us % 1_000_000))
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    fn peek(&mut self) -> Result<Option<u8>> {
+        Ok(self.input.fill_buf()?.first().copied())
+    }
+
+    pub fn getch(&mut self) -> Result<i32> {
+        match self.peek()? {
+            Some(c) => {
+                self.input.consume(1);
+                Ok(c as i32)
+            }
+            None => Ok(-1),
+        }
+    }
+
+    pub fn getint(&mut self) -> Result<i32> {
+        while let Some(c) = self.peek()? {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.input.consume(1);
+        }
+        let mut negative = false;
+        if let Some(c) = self.peek()? {
+            if c == b'-' || c == b'+' {
+                negative = c == b'-';
+                self.input.consume(1);
+            }
+        }
+        let mut value: i32 = 0;
+        while let Some(c) = self.peek()? {
+            if !c.is_ascii_digit() {
+                break;
+            }
+            value = value.wrapping_mul(10).wrapping_add((c - b'0') as i32);
+            self.input.consume(1);
+        }
+        Ok(if negative { value.wrapping_neg() } else { value })
+    }
+
+    pub fn getarray(&mut self) -> Result<Vec<i32>> {
+        let len = self.getint()?;
+        let mut values = vec![];
+        for _ in 0..len.max(0) {
+            values.push(self.getint()?);
+        }
+        Ok(values)
+    }
+
+    pub fn putint(&mut self, value: i32) -> Result<()> {
+        write!(self.output, "{}", value)
+    }
+
+    pub fn putch(&mut self, value: i32) -> Result<()> {
+        self.output.write_all(&[value as u8])
+    }
+
+    pub fn putarray(&mut self, values: &[i32]) -> Result<()> {
+        write!(self.output, "{}:", values.len())?;
+        for value in values {
+            write!(self.output, " {}", value)?;
+        }
+        writeln!(self.output)
+    }
+
+    pub fn starttime(&mut self) {
+        self.timer = Some(Instant::now());
+    }
+
+    pub fn stoptime(&mut self) -> Result<()> {
+        if let Some(timer) = self.timer.take() {
+            self.elapsed = Some(self.elapsed.unwrap_or_default() + timer.elapsed());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,922 @@
+//! # Generator
+//!
+//! In this file, we define a generator of random SysY programs, which pass the checker and mean the same thing
+//! in every correct implementation:
+//! * every variable is initialized before it's read, and every index is in the bounds of its dimension,
+//!   by a constant, a loop variable known to fit, or `(e % n + n) % n`.
+//! * nothing is divided by zero, and `INT_MIN` is never divided by -1, which SysY leaves to the implementation.
+//!   Divisors are constants other than 0 and -1, or checked by `safe_div` and `safe_mod`.
+//! * arithmetic wraps, as it does in every mode of the compiler.
+//! * every loop counts up to a constant, and recursion counts a depth down to zero, so programs terminate.
+//!   The statements executed are estimated, and kept in a budget.
+//! * expressions have no side effects, so their order of evaluation doesn't matter. Only pure functions,
+//!   which write nothing but their locals, are called in them. Other functions are called as statements.
+//!
+//! `main` ends by printing a checksum of the globals, and returns it.
+//!
+
+use crate::fuzz::random::Random;
+use std::collections::HashSet;
+
+/// Statements a call of a function may execute at most.
+const FUNC_BUDGET: u64 = 20_000;
+/// Statements `main` may execute at most, including calls.
+const MAIN_BUDGET: u64 = 400_000;
+
+const INTERESTING: [i64; 22] = [
+    0, 1, 2, 3, 7, 8, 15, 16, 31, 255, 256, 1023, 1024, 2047, 2048, 2049, 4095, 4096, 65535, 65536, 1 << 30, 2147483647,
+];
+
+#[derive(Clone, PartialEq)]
+enum Kind {
+    Scalar,
+    /// Lengths of each dimension, the first one of an array parameter is the least any caller passes.
+    Array(Vec<usize>),
+}
+
+#[derive(Clone)]
+struct Var {
+    name: String,
+    kind: Kind,
+    is_const: bool,
+    /// Value of a constant initialized with a number, which can be a dimension.
+    value: Option<i32>,
+    /// Whether the variable can be assigned at all, pure functions don't assign shared ones either.
+    writable: bool,
+    /// Globals and array parameters are shared with the callers.
+    shared: bool,
+    /// A loop variable is `1..=trip` in the body of its loop.
+    trip: Option<usize>,
+}
+
+struct Func {
+    name: String,
+    /// Dimensions of each parameter, empty for a scalar.
+    params: Vec<Vec<usize>>,
+    returns: bool,
+    /// Pure functions write nothing but their locals, so they can be called in expressions.
+    pure: bool,
+    /// Statements a call executes at most.
+    cost: u64,
+    /// Greatest depth given to a recursive function as its first argument.
+    depth: Option<usize>,
+}
+
+/// The function being generated.
+struct Context {
+    pure: bool,
+    returns: bool,
+    /// Times a statement here is executed by one call of the function.
+    mult: u64,
+    cost: u64,
+    budget: u64,
+    loops: usize,
+}
+
+pub struct Generator {
+    rng: Random,
+    out: String,
+    indent: usize,
+    ids: usize,
+    globals: Vec<Var>,
+    /// Blocks of the function being generated, the innermost last.
+    scopes: Vec<Vec<Var>>,
+    /// A variable whose name is being shadowed, which its initializer can't read.
+    hidden: Option<String>,
+    funcs: Vec<Func>,
+    /// Shapes of arrays declared so far, so arrays of the same shape can be passed around.
+    shapes: Vec<Vec<usize>>,
+    ctx: Context,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Random::new(seed),
+            out: String::new(),
+            indent: 0,
+            ids: 0,
+            globals: vec![],
+            scopes: vec![],
+            hidden: None,
+            funcs: vec![],
+            shapes: vec![],
+            ctx: Context { pure: false, returns: true, mult: 1, cost: 0, budget: MAIN_BUDGET, loops: 0 },
+        }
+    }
+
+    /// Generate the whole program, return its source.
+    pub fn program(mut self, seed: u64) -> String {
+        self.line(&format!("// generated from seed {}", seed));
+        self.helpers();
+        let count = self.rng.range(2, 7);
+        for _ in 0..count {
+            for _ in 0..self.rng.range(1, 3) {
+                self.global();
+            }
+            self.func();
+        }
+        for _ in 0..self.rng.range(0, 2) {
+            self.global();
+        }
+        self.main();
+        self.out
+    }
+
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
+    fn open(&mut self, text: &str) {
+        if text.is_empty() {
+            self.line("{");
+        }
+        else {
+            self.line(&format!("{} {{", text));
+        }
+        self.indent += 1;
+        self.scopes.push(vec![]);
+    }
+
+    fn close(&mut self) {
+        self.scopes.pop();
+        self.indent -= 1;
+        self.line("}");
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.ids += 1;
+        format!("{}{}", prefix, self.ids)
+    }
+
+    fn define(&mut self, var: Var) {
+        match self.scopes.last_mut() {
+            Some(scope) => scope.push(var),
+            None => self.globals.push(var),
+        }
+    }
+
+    /// Variables in scope, the innermost of those with the same name only.
+    fn visible(&self) -> Vec<Var> {
+        let mut names = HashSet::new();
+        let mut vars = vec![];
+        for var in self.scopes.iter().rev().flat_map(|scope| scope.iter().rev()).chain(self.globals.iter().rev()) {
+            if names.insert(var.name.clone()) && self.hidden.as_ref() != Some(&var.name) {
+                vars.push(var.clone());
+            }
+        }
+        vars
+    }
+
+    fn can_write(&self, var: &Var) -> bool {
+        var.writable && !(var.shared && self.ctx.pure)
+    }
+
+    fn room(&self, cost: u64) -> bool {
+        self.ctx.cost + cost <= self.ctx.budget
+    }
+
+    /// Division and modulo of any operands, which pure functions and expressions call.
+    fn helpers(&mut self) {
+        for (name, op) in [("safe_div", "/"), ("safe_mod", "%")] {
+            self.line(&format!("int {}(int a, int b) {{", name));
+            self.line("    if (b == 0 || a == -2147483647 - 1 && b == -1) return a;");
+            self.line(&format!("    return a {} b;", op));
+            self.line("}");
+            self.funcs.push(Func { name: name.to_string(), params: vec![vec![], vec![]], returns: true, pure: true, cost: 2, depth: None });
+        }
+    }
+
+    /// A shape seen before, or a new one, of at most `size` elements.
+    fn shape(&mut self, size: usize) -> Vec<usize> {
+        if !self.shapes.is_empty() && self.rng.chance(50) {
+            let shape = self.rng.pick(&self.shapes).clone();
+            if shape.iter().product::<usize>() <= size {
+                return shape;
+            }
+        }
+        let mut shape = vec![];
+        for _ in 0..self.rng.range(1, 3) {
+            shape.push(self.rng.range(1, 6) as usize);
+        }
+        if shape.iter().product::<usize>() > size {
+            shape = vec![size.min(8)];
+        }
+        self.shapes.push(shape.clone());
+        shape
+    }
+
+    /// Dimensions of a declaration, by constants of the same value sometimes.
+    fn dims(&mut self, shape: &[usize]) -> String {
+        let consts: Vec<Var> = self.visible().into_iter().filter(|var| var.value.is_some()).collect();
+        let mut text = String::new();
+        for &len in shape {
+            let named: Vec<&Var> = consts.iter().filter(|var| var.value == Some(len as i32)).collect();
+            if !named.is_empty() && self.rng.chance(50) {
+                text += &format!("[{}]", self.rng.pick(&named).name);
+            }
+            else if len > 1 && self.rng.chance(10) {
+                text += &format!("[{} + 1]", len - 1);
+            }
+            else {
+                text += &format!("[{}]", len);
+            }
+        }
+        text
+    }
+
+    fn global(&mut self) {
+        match self.rng.below(5) {
+            0 => {
+                let name = self.fresh("c");
+                let (init, value) = if self.rng.chance(60) {
+                    let value = self.rng.range(1, 8) as i32;
+                    (value.to_string(), Some(value))
+                }
+                else {
+                    (self.const_exp(2), None)
+                };
+                self.line(&format!("const int {} = {};", name, init));
+                self.define(Var { name, kind: Kind::Scalar, is_const: true, value, writable: false, shared: true, trip: None });
+            }
+            1 => {
+                let name = self.fresh("g");
+                if self.rng.chance(70) {
+                    let init = self.const_exp(2);
+                    self.line(&format!("int {} = {};", name, init));
+                }
+                else {
+                    self.line(&format!("int {};", name));
+                }
+                self.define(Var { name, kind: Kind::Scalar, is_const: false, value: None, writable: true, shared: true, trip: None });
+            }
+            _ => self.array(true),
+        }
+    }
+
+    /// Declare an array, constant or not, with an initializer list.
+    fn array(&mut self, global: bool) {
+        let is_const = self.rng.chance(30);
+        let name = self.fresh("a");
+        // big arrays take offsets out of the range of immediates
+        let shape = if self.rng.chance(8) {
+            vec![self.rng.range(300, 1200) as usize]
+        }
+        else {
+            self.shape(60)
+        };
+        let dims = self.dims(&shape);
+        let size: usize = shape.iter().product();
+        let init = if size > 100 {
+            let len = self.rng.range(0, 6) as usize;
+            let items: Vec<String> = (0..len).map(|_| self.init_exp(is_const || global)).collect();
+            format!("{{{}}}", items.join(", "))
+        }
+        else {
+            self.init_list(&shape, is_const || global)
+        };
+        if is_const {
+            self.line(&format!("const int {}{} = {};", name, dims, init));
+        }
+        else if global && self.rng.chance(20) {
+            self.line(&format!("int {}{};", name, dims));
+        }
+        else {
+            self.line(&format!("int {}{} = {};", name, dims, init));
+        }
+        self.define(Var { name, kind: Kind::Array(shape), is_const, value: None, writable: !is_const, shared: global, trip: None });
+    }
+
+    fn init_exp(&mut self, constant: bool) -> String {
+        if constant {
+            self.const_exp(2)
+        }
+        else {
+            self.exp(2)
+        }
+    }
+
+    /// An initializer list of an array of shape `dims`. A nested list starts at a row,
+    /// and initializes the largest subarray aligned there, but never the whole array.
+    fn init_list(&mut self, dims: &[usize], constant: bool) -> String {
+        let size: usize = dims.iter().product();
+        let row = *dims.last().unwrap();
+        let target = if self.rng.chance(50) { size } else { self.rng.below(size + 1) };
+        let mut count = 0;
+        let mut items = vec![];
+        while count < target {
+            if dims.len() > 1 && count % row == 0 && self.rng.chance(40) {
+                let mut first = dims.len() - 1;
+                let mut len = row;
+                while first > 1 && count % (len * dims[first - 1]) == 0 {
+                    first -= 1;
+                    len *= dims[first];
+                }
+                items.push(self.init_list(&dims[first..], constant));
+                count += len;
+            }
+            else {
+                items.push(self.init_exp(constant));
+                count += 1;
+            }
+        }
+        format!("{{{}}}", items.join(", "))
+    }
+
+    fn func(&mut self) {
+        let pure = self.rng.chance(50);
+        let returns = pure || self.rng.chance(50);
+        let name = self.fresh("f");
+        // a recursive function gets the depth left, and calls itself once or twice
+        let recursion = if self.rng.chance(25) {
+            let depth = self.rng.range(2, 5) as usize;
+            let branches = if depth <= 4 && self.rng.chance(50) { 2 } else { 1 };
+            Some((depth, branches))
+        }
+        else {
+            None
+        };
+        let activations: u64 = match recursion {
+            Some((depth, branches)) => (0..=depth as u32).map(|k| (branches as u64).pow(k)).sum(),
+            None => 1,
+        };
+
+        let mut params: Vec<Vec<usize>> = vec![];
+        let mut texts = vec![];
+        let mut vars = vec![];
+        if recursion.is_some() {
+            let name = self.fresh("d");
+            texts.push(format!("int {}", name));
+            vars.push(Var { name, kind: Kind::Scalar, is_const: false, value: None, writable: false, shared: false, trip: None });
+            params.push(vec![]);
+        }
+        // many parameters are passed on the stack
+        let count = if self.rng.chance(10) { self.rng.range(8, 11) } else { self.rng.range(0, 4) };
+        for _ in 0..count {
+            let name = self.fresh("p");
+            if self.shapes.is_empty() || self.rng.chance(65) {
+                texts.push(format!("int {}", name));
+                vars.push(Var { name, kind: Kind::Scalar, is_const: false, value: None, writable: true, shared: false, trip: None });
+                params.push(vec![]);
+            }
+            else {
+                let mut dims = self.rng.pick(&self.shapes).clone();
+                dims[0] = self.rng.range(1, dims[0] as i64) as usize;
+                let rest: String = dims[1..].iter().map(|len| format!("[{}]", len)).collect();
+                texts.push(format!("int {}[]{}", name, rest));
+                vars.push(Var { name, kind: Kind::Array(dims.clone()), is_const: false, value: None, writable: true, shared: true, trip: None });
+                params.push(dims);
+            }
+        }
+
+        let ty = if returns { "int" } else { "void" };
+        self.line("");
+        self.open(&format!("{} {}({})", ty, name, texts.join(", ")));
+        for var in vars {
+            self.define(var);
+        }
+        let budget = self.rng.range(200, FUNC_BUDGET as i64) as u64 / activations;
+        self.ctx = Context { pure, returns, mult: 1, cost: 0, budget: budget.max(20), loops: 0 };
+
+        if recursion.is_some() {
+            let depth = self.scopes[0][0].name.clone();
+            let value = if returns { format!(" {}", self.exp(2)) } else { String::new() };
+            self.line(&format!("if ({} <= 0) return{};", depth, value));
+        }
+        self.pressure();
+        let count = self.rng.range(2, 10) as usize;
+        self.stmts(count, 2);
+
+        if let Some((_, branches)) = recursion {
+            let params: Vec<Var> = self.scopes[0][..params.len()].to_vec();
+            let args: Vec<String> = params.iter().enumerate().map(|(i, param)| {
+                if i == 0 {
+                    format!("{} - 1", param.name)
+                }
+                else if param.kind == Kind::Scalar {
+                    self.exp(2)
+                }
+                else {
+                    param.name.clone()
+                }
+            }).collect();
+            let call = format!("{}({})", name, args.join(", "));
+            if returns {
+                let result = self.fresh("r");
+                self.line(&format!("int {} = {};", result, call));
+                if branches == 2 {
+                    self.line(&format!("{} = {} + {};", result, result, call));
+                }
+                self.define(Var { name: result, kind: Kind::Scalar, is_const: false, value: None, writable: true, shared: false, trip: None });
+            }
+            else {
+                for _ in 0..branches {
+                    self.line(&format!("{};", call));
+                }
+            }
+            let count = self.rng.range(0, 3) as usize;
+            self.stmts(count, 1);
+        }
+
+        if returns {
+            let value = self.chain();
+            self.line(&format!("return {};", value));
+        }
+        self.close();
+        let cost = (self.ctx.cost + 2) * activations;
+        self.funcs.push(Func { name, params, returns, pure, cost, depth: recursion.map(|(depth, _)| depth) });
+    }
+
+    fn main(&mut self) {
+        self.line("");
+        self.open("int main()");
+        self.ctx = Context { pure: false, returns: true, mult: 1, cost: 0, budget: MAIN_BUDGET, loops: 0 };
+        self.pressure();
+        // an array of every shape, so every function can be called
+        for shape in self.shapes.clone() {
+            let name = self.fresh("a");
+            let dims = self.dims(&shape);
+            let init = self.init_list(&shape, false);
+            self.line(&format!("int {}{} = {};", name, dims, init));
+            self.define(Var { name, kind: Kind::Array(shape), is_const: false, value: None, writable: true, shared: false, trip: None });
+        }
+        // every function is called at least once, if the budget allows
+        for i in 2..self.funcs.len() {
+            if self.room(self.funcs[i].cost) {
+                self.call(i);
+            }
+        }
+        let count = self.rng.range(8, 30) as usize;
+        self.stmts(count, 3);
+
+        let sum = self.fresh("sum");
+        self.line(&format!("int {} = 0;", sum));
+        for var in self.globals.clone() {
+            match &var.kind {
+                Kind::Scalar if !var.is_const => self.line(&format!("{} = {} * 31 + {};", sum, sum, var.name)),
+                Kind::Array(dims) if !var.is_const => {
+                    let mut loops = vec![];
+                    for &len in dims {
+                        let i = self.fresh("i");
+                        self.line(&format!("int {} = 0;", i));
+                        self.open(&format!("while ({} < {})", i, len));
+                        loops.push(i);
+                    }
+                    let index: String = loops.iter().map(|i| format!("[{}]", i)).collect();
+                    self.line(&format!("{} = {} * 31 + {}{};", sum, sum, var.name, index));
+                    for i in loops.iter().rev() {
+                        self.line(&format!("{} = {} + 1;", i, i));
+                        self.close();
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.line(&format!("putint({});", sum));
+        self.line("putch(10);");
+        self.line(&format!("return {};", sum));
+        self.close();
+    }
+
+    /// Many scalars live at once, so registers run out.
+    fn pressure(&mut self) {
+        let count = if self.rng.chance(30) { self.rng.range(8, 24) } else { self.rng.range(0, 4) };
+        for _ in 0..count {
+            self.scalar(None);
+        }
+    }
+
+    /// An expression over every local scalar, which keeps them alive until it.
+    fn chain(&mut self) -> String {
+        let scalars: Vec<Var> = self.visible().into_iter()
+            .filter(|var| var.kind == Kind::Scalar && !var.shared)
+            .collect();
+        let mut text = self.exp(1);
+        for var in scalars.iter().rev() {
+            let op = *self.rng.pick(&["+", "-", "*"]);
+            text = format!("({} {} {})", text, op, var.name);
+        }
+        text
+    }
+
+    fn stmts(&mut self, count: usize, depth: usize) {
+        for _ in 0..count {
+            if !self.room(self.ctx.mult) {
+                break;
+            }
+            self.stmt(depth);
+        }
+    }
+
+    fn stmt(&mut self, depth: usize) {
+        self.ctx.cost += self.ctx.mult;
+        match self.rng.below(100) {
+            0..=24 => self.assign(),
+            25..=32 => self.scalar(None),
+            33..=37 => self.array(false),
+            38..=49 if depth > 0 => self.if_stmt(depth),
+            50..=61 if depth > 0 => self.while_stmt(depth),
+            62..=65 if depth > 0 => {
+                self.open("");
+                let count = self.rng.range(1, 4) as usize;
+                self.stmts(count, depth - 1);
+                self.close();
+            }
+            66..=73 if self.ctx.loops > 0 => {
+                let cond = self.cond(2);
+                let jump = if self.rng.chance(50) { "break" } else { "continue" };
+                self.line(&format!("if ({}) {};", cond, jump));
+            }
+            74..=83 => {
+                let funcs: Vec<usize> = (0..self.funcs.len())
+                    .filter(|&i| (self.funcs[i].pure || !self.ctx.pure) && self.room(self.ctx.mult * self.funcs[i].cost))
+                    .collect();
+                if funcs.is_empty() {
+                    self.assign();
+                }
+                else {
+                    let i = *self.rng.pick(&funcs);
+                    self.call(i);
+                }
+            }
+            84..=92 if !self.ctx.pure && self.ctx.mult <= 64 => self.print(),
+            93..=95 => {
+                let cond = self.cond(2);
+                let value = if self.ctx.returns { format!(" {}", self.exp(2)) } else { String::new() };
+                self.line(&format!("if ({}) return{};", cond, value));
+            }
+            96..=97 => self.line(";"),
+            _ => self.assign(),
+        }
+    }
+
+    /// Declare a scalar, which shadows a local of an outer block sometimes.
+    fn scalar(&mut self, name: Option<String>) {
+        let outer: Vec<String> = self.scopes.iter().rev().skip(1).flatten()
+            .filter(|var| var.kind == Kind::Scalar && var.writable && !var.shared)
+            .map(|var| var.name.clone())
+            .collect();
+        let name = match name {
+            Some(name) => name,
+            None if !outer.is_empty() && self.rng.chance(10) => self.rng.pick(&outer).clone(),
+            None => self.fresh("l"),
+        };
+        if self.rng.chance(15) {
+            let (init, value) = if self.rng.chance(50) {
+                let value = self.rng.range(1, 8) as i32;
+                (value.to_string(), Some(value))
+            }
+            else {
+                self.hidden = Some(name.clone());
+                let init = self.const_exp(2);
+                self.hidden = None;
+                (init, None)
+            };
+            self.line(&format!("const int {} = {};", name, init));
+            self.define(Var { name, kind: Kind::Scalar, is_const: true, value, writable: false, shared: false, trip: None });
+        }
+        else {
+            self.hidden = Some(name.clone());
+            let init = self.exp(3);
+            self.hidden = None;
+            self.line(&format!("int {} = {};", name, init));
+            self.define(Var { name, kind: Kind::Scalar, is_const: false, value: None, writable: true, shared: false, trip: None });
+        }
+    }
+
+    fn assign(&mut self) {
+        let targets: Vec<Var> = self.visible().into_iter().filter(|var| self.can_write(var)).collect();
+        if targets.is_empty() {
+            return self.scalar(None);
+        }
+        let target = self.rng.pick(&targets).clone();
+        let value = self.exp(3);
+        let lval = self.element(&target, 1);
+        self.line(&format!("{} = {};", lval, value));
+    }
+
+    fn if_stmt(&mut self, depth: usize) {
+        let cond = self.cond(3);
+        // a single statement without braces sometimes
+        if self.rng.chance(20) {
+            let targets: Vec<Var> = self.visible().into_iter().filter(|var| self.can_write(var)).collect();
+            if !targets.is_empty() {
+                let target = self.rng.pick(&targets).clone();
+                let lval = self.element(&target, 1);
+                let value = self.exp(2);
+                self.line(&format!("if ({}) {} = {};", cond, lval, value));
+                return;
+            }
+        }
+        self.open(&format!("if ({})", cond));
+        let count = self.rng.range(1, 4) as usize;
+        self.stmts(count, depth - 1);
+        if self.rng.chance(50) {
+            self.close();
+            self.open("else");
+            let count = self.rng.range(1, 4) as usize;
+            self.stmts(count, depth - 1);
+        }
+        self.close();
+    }
+
+    /// A loop counting its variable up to a constant, incremented first so `continue` can't skip it.
+    fn while_stmt(&mut self, depth: usize) {
+        let trip = if self.rng.chance(10) { self.rng.range(9, 30) as u64 } else { self.rng.range(1, 8) as u64 };
+        if !self.room(self.ctx.mult * trip * 4) {
+            return self.assign();
+        }
+        let i = self.fresh("i");
+        self.open("");
+        self.line(&format!("int {} = 0;", i));
+        // the condition is evaluated once more than the body
+        let mult = self.ctx.mult;
+        self.ctx.mult *= trip + 1;
+        let cond = match self.rng.below(4) {
+            0 => format!("{} < {} && {}", i, trip, self.cond(2)),
+            1 => format!("{} < {} && ({} || {})", i, trip, self.cond(1), self.cond(1)),
+            _ => format!("{} < {}", i, trip),
+        };
+        self.open(&format!("while ({})", cond));
+        self.line(&format!("{} = {} + 1;", i, i));
+        self.define(Var { name: i, kind: Kind::Scalar, is_const: false, value: None, writable: false, shared: false, trip: Some(trip as usize) });
+        let loops = self.ctx.loops;
+        self.ctx.mult = mult * trip;
+        self.ctx.loops += 1;
+        let count = self.rng.range(1, 5) as usize;
+        self.stmts(count, depth - 1);
+        self.ctx.mult = mult;
+        self.ctx.loops = loops;
+        self.close();
+        self.close();
+    }
+
+    fn print(&mut self) {
+        match self.rng.below(6) {
+            0 => {
+                let ch = self.rng.range(65, 90);
+                self.line(&format!("putch({});", ch));
+            }
+            1 => {
+                // `putarray` takes a row of an array
+                let rows: Vec<(Var, usize)> = self.visible().into_iter().filter_map(|var| match &var.kind {
+                    Kind::Array(dims) if !var.is_const => Some((var.clone(), *dims.last().unwrap())),
+                    _ => None,
+                }).collect();
+                if rows.is_empty() {
+                    return self.line("putch(10);");
+                }
+                let (var, len) = self.rng.pick(&rows).clone();
+                let dims = match &var.kind {
+                    Kind::Array(dims) => dims.clone(),
+                    Kind::Scalar => unreachable!(),
+                };
+                let mut row = var.name.clone();
+                for &dim in &dims[..dims.len() - 1] {
+                    row += &format!("[{}]", self.index(dim, 1));
+                }
+                let count = self.rng.range(0, len as i64);
+                self.line(&format!("putarray({}, {});", count, row));
+            }
+            _ => {
+                let value = self.exp(3);
+                self.line(&format!("putint({});", value));
+                self.line("putch(32);");
+            }
+        }
+    }
+
+    /// Call a function as a statement, assigning its value sometimes.
+    fn call(&mut self, i: usize) {
+        let call = match self.call_exp(i, 2) {
+            Some(call) => call,
+            None => return,
+        };
+        self.ctx.cost += self.ctx.mult * self.funcs[i].cost;
+        let targets: Vec<Var> = self.visible().into_iter()
+            .filter(|var| var.kind == Kind::Scalar && self.can_write(var))
+            .collect();
+        if self.funcs[i].returns && !targets.is_empty() && self.rng.chance(60) {
+            let target = self.rng.pick(&targets).name.clone();
+            self.line(&format!("{} = {};", target, call));
+        }
+        else {
+            self.line(&format!("{};", call));
+        }
+    }
+
+    /// A call of the function with arguments of at most `depth` levels, `None` if no array in scope can be passed to it.
+    fn call_exp(&mut self, i: usize, depth: usize) -> Option<String> {
+        let mut args = vec![];
+        if let Some(depth) = self.funcs[i].depth {
+            args.push(self.rng.range(0, depth as i64).to_string());
+        }
+        let skip = args.len();
+        let visible = self.visible();
+        for param in self.funcs[i].params.clone().iter().skip(skip) {
+            if param.is_empty() {
+                args.push(self.exp(depth));
+                continue;
+            }
+            // an array whose dimensions after some subscripts are those of the parameter
+            let mut candidates = vec![];
+            for var in &visible {
+                if let Kind::Array(dims) = &var.kind {
+                    if var.is_const || dims.len() < param.len() {
+                        continue;
+                    }
+                    let k = dims.len() - param.len();
+                    if dims[k + 1..] == param[1..] && dims[k] >= param[0] {
+                        candidates.push((var.name.clone(), dims[..k].to_vec()));
+                    }
+                }
+            }
+            if candidates.is_empty() {
+                return None;
+            }
+            let (name, dims) = self.rng.pick(&candidates).clone();
+            let mut arg = name;
+            for dim in dims {
+                arg += &format!("[{}]", self.index(dim, depth));
+            }
+            args.push(arg);
+        }
+        Some(format!("{}({})", self.funcs[i].name, args.join(", ")))
+    }
+
+    /// A scalar, or an element of an array with indices in bounds.
+    fn element(&mut self, var: &Var, depth: usize) -> String {
+        let mut text = var.name.clone();
+        if let Kind::Array(dims) = &var.kind {
+            for &len in dims {
+                text += &format!("[{}]", self.index(len, depth));
+            }
+        }
+        text
+    }
+
+    /// An index in `0..len`, of an expression of at most `depth` levels.
+    fn index(&mut self, len: usize, depth: usize) -> String {
+        let visible = self.visible();
+        let loops: Vec<&Var> = visible.iter().filter(|var| var.trip.is_some_and(|trip| trip <= len)).collect();
+        let consts: Vec<&Var> = visible.iter().filter(|var| var.value.is_some_and(|value| (value as usize) < len)).collect();
+        match self.rng.below(5) {
+            0 | 1 if !loops.is_empty() => format!("{} - 1", self.rng.pick(&loops).name),
+            2 if !consts.is_empty() => self.rng.pick(&consts).name.clone(),
+            3 if depth > 0 => {
+                let exp = self.exp(depth - 1);
+                format!("({} % {} + {}) % {}", exp, len, len, len)
+            }
+            _ => self.rng.below(len).to_string(),
+        }
+    }
+
+    fn literal(&mut self) -> String {
+        match self.rng.below(10) {
+            0..=5 => self.rng.range(0, 20).to_string(),
+            6 => self.rng.pick(&INTERESTING).to_string(),
+            7 => self.rng.range(0, i32::MAX as i64).to_string(),
+            8 => {
+                let value = *self.rng.pick(&INTERESTING[1..]);
+                if self.rng.chance(50) { format!("{:#x}", value) } else { format!("0{:o}", value) }
+            }
+            _ if self.rng.chance(10) => "(-2147483647 - 1)".to_string(),
+            _ => format!("(-{})", self.rng.pick(&INTERESTING[1..])),
+        }
+    }
+
+    fn divisor(&mut self) -> String {
+        let value = match self.rng.below(4) {
+            0 => self.rng.range(1, 20),
+            1 => *self.rng.pick(&INTERESTING[1..]),
+            2 => self.rng.range(1, i32::MAX as i64),
+            // -1 could divide `INT_MIN`
+            _ => -*self.rng.pick(&INTERESTING[2..]),
+        };
+        if value < 0 { format!("({})", value) } else { value.to_string() }
+    }
+
+    fn leaf(&mut self, depth: usize) -> String {
+        let vars: Vec<Var> = self.visible();
+        if vars.is_empty() || self.rng.chance(30) {
+            return self.literal();
+        }
+        let var = self.rng.pick(&vars).clone();
+        self.element(&var, depth)
+    }
+
+    /// An expression without side effects.
+    fn exp(&mut self, depth: usize) -> String {
+        if depth == 0 || self.rng.chance(25) {
+            return self.leaf(depth.saturating_sub(1));
+        }
+        match self.rng.below(12) {
+            0..=4 => {
+                let op = *self.rng.pick(&["+", "-", "*", "+", "-"]);
+                format!("({} {} {})", self.exp(depth - 1), op, self.exp(depth - 1))
+            }
+            5 => {
+                let op = *self.rng.pick(&["/", "%"]);
+                format!("({} {} {})", self.exp(depth - 1), op, self.divisor())
+            }
+            6 => {
+                let op = *self.rng.pick(&["-", "!", "+"]);
+                format!("({}{})", op, self.exp(depth - 1))
+            }
+            7 | 8 => self.cond(depth - 1),
+            _ => {
+                let funcs: Vec<usize> = (0..self.funcs.len())
+                    .filter(|&i| self.funcs[i].pure && self.room(self.ctx.mult * self.funcs[i].cost))
+                    .collect();
+                if funcs.is_empty() {
+                    return self.leaf(depth - 1);
+                }
+                let i = *self.rng.pick(&funcs);
+                match self.call_exp(i, depth - 1) {
+                    Some(call) => {
+                        self.ctx.cost += self.ctx.mult * self.funcs[i].cost;
+                        call
+                    }
+                    None => self.leaf(depth - 1),
+                }
+            }
+        }
+    }
+
+    /// An expression used as a condition, which is a comparison or a logical one mostly.
+    fn cond(&mut self, depth: usize) -> String {
+        match self.rng.below(7) {
+            0 | 1 => {
+                let op = *self.rng.pick(&["<", ">", "<=", ">=", "==", "!="]);
+                format!("({} {} {})", self.exp(depth), op, self.exp(depth))
+            }
+            2 if depth > 0 => format!("({} && {})", self.cond(depth - 1), self.cond(depth - 1)),
+            3 if depth > 0 => format!("({} || {})", self.cond(depth - 1), self.cond(depth - 1)),
+            4 if depth > 0 => format!("(!{})", self.cond(depth - 1)),
+            5 if depth > 0 => self.guarded(depth - 1),
+            _ => self.exp(depth),
+        }
+    }
+
+    /// An element indexed by any scalar, read only if `&&` or `||` has checked the index.
+    fn guarded(&mut self, depth: usize) -> String {
+        let visible = self.visible();
+        let arrays: Vec<&Var> = visible.iter().filter(|var| matches!(var.kind, Kind::Array(_))).collect();
+        let scalars: Vec<&Var> = visible.iter().filter(|var| var.kind == Kind::Scalar).collect();
+        if arrays.is_empty() || scalars.is_empty() {
+            return self.exp(depth);
+        }
+        let array = (*self.rng.pick(&arrays)).clone();
+        let index = self.rng.pick(&scalars).name.clone();
+        let dims = match &array.kind {
+            Kind::Array(dims) => dims.clone(),
+            Kind::Scalar => unreachable!(),
+        };
+        let len = *dims.last().unwrap();
+        let mut element = array.name.clone();
+        for &dim in &dims[..dims.len() - 1] {
+            element += &format!("[{}]", self.index(dim, depth));
+        }
+        element += &format!("[{}]", index);
+        let value = self.exp(depth);
+        if self.rng.chance(50) {
+            format!("({} >= 0 && {} < {} && {} > {})", index, index, len, element, value)
+        }
+        else {
+            format!("({} < 0 || {} >= {} || {} != {})", index, index, len, element, value)
+        }
+    }
+
+    /// A constant expression, over numbers and constant scalars.
+    fn const_exp(&mut self, depth: usize) -> String {
+        let consts: Vec<String> = self.visible().into_iter()
+            .filter(|var| var.is_const && var.kind == Kind::Scalar)
+            .map(|var| var.name)
+            .collect();
+        if depth == 0 || self.rng.chance(35) {
+            if !consts.is_empty() && self.rng.chance(40) {
+                return self.rng.pick(&consts).clone();
+            }
+            return self.literal();
+        }
+        match self.rng.below(6) {
+            0..=2 => {
+                let op = *self.rng.pick(&["+", "-", "*"]);
+                format!("({} {} {})", self.const_exp(depth - 1), op, self.const_exp(depth - 1))
+            }
+            3 => {
+                let op = *self.rng.pick(&["/", "%"]);
+                format!("({} {} {})", self.const_exp(depth - 1), op, self.divisor())
+            }
+            4 => {
+                let op = *self.rng.pick(&["<", "==", "&&", "||"]);
+                format!("({} {} {})", self.const_exp(depth - 1), op, self.const_exp(depth - 1))
+            }
+            _ => format!("(-{})", self.const_exp(depth - 1)),
+        }
+    }
+}
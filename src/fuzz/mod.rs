@@ -0,0 +1,21 @@
+//! # Fuzz
+//!
+//! In this module, we generate random SysY programs for fuzzing the compiler. A program is well formed
+//! and means the same thing in every correct implementation, so running it in every mode and comparing
+//! the outputs finds miscompiles, with `-interp-ast` as the oracle. A program showing a bug,
+//! generated or not, is then reduced to a small one still showing it.
+//!
+
+mod generator;
+mod print;
+mod random;
+mod reduce;
+
+use crate::fuzz::generator::Generator;
+
+pub use crate::fuzz::reduce::reduce;
+
+/// Generate the program of a seed, the same one every time.
+pub fn generate(seed: u64) -> String {
+    Generator::new(seed).program(seed)
+}
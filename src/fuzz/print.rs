@@ -0,0 +1,525 @@
+//! # Print
+//!
+//! In this file, we print the AST back to SysY, with at most one edit making it smaller.
+//!
+//! Every place an edit can be made is a site, numbered in the order it's printed, so the same tree
+//! has the same sites every time. Printing without an edit counts the sites of each kind,
+//! and printing with an edit at a site gives the program where only that site has changed.
+//! The tree itself never changes: an edited program is parsed again.
+//!
+//! Local variables without an initializer are initialized with zero. A program reading them
+//! before writing them means nothing, and deleting a statement must not make it one.
+//!
+
+use crate::ast::*;
+use crate::mem::eval::{ Constants, Eval };
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Edit {
+    /// Delete a function or a global declaration.
+    Item,
+    /// Delete a statement or a declaration of a block, or a definition of a declaration defining several.
+    Stmt,
+    /// Replace an `if` with one of its branches, or drop its `else`, and replace a `while` with its body.
+    Lift,
+    /// Delete an element of an initializer list.
+    Element,
+    /// Halve a dimension of an array.
+    Shrink,
+    /// Replace the name of a scalar constant with its value.
+    Inline,
+    /// Replace a binary expression with one of its operands, a unary expression with its operand,
+    /// and drop parentheses around a unary expression.
+    Operand,
+}
+
+impl Edit {
+    /// Every kind of edit, those deleting the most first.
+    pub const ALL: [Edit; 7] = [Edit::Item, Edit::Stmt, Edit::Lift, Edit::Element, Edit::Shrink, Edit::Inline, Edit::Operand];
+}
+
+pub struct Printer {
+    out: String,
+    indent: usize,
+    target: Option<(Edit, usize)>,
+    sites: HashMap<Edit, usize>,
+    /// Names in scope, with the values of scalar constants.
+    scopes: Vec<HashMap<String, Option<i32>>>,
+}
+
+impl Constants for Printer {
+    fn constant(&self, id: &str) -> Option<i32> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(id)).copied().flatten()
+    }
+}
+
+impl Printer {
+    pub fn new(target: Option<(Edit, usize)>) -> Self {
+        Self { out: String::new(), indent: 0, target, sites: HashMap::new(), scopes: vec![HashMap::new()] }
+    }
+
+    /// Number of sites of a kind printed.
+    pub fn sites(&self, edit: Edit) -> usize {
+        self.sites.get(&edit).copied().unwrap_or(0)
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    /// Count a site of a kind, return whether the edit is made there.
+    fn site(&mut self, edit: Edit) -> bool {
+        let count = self.sites.entry(edit).or_insert(0);
+        *count += 1;
+        self.target == Some((edit, *count - 1))
+    }
+
+    fn write(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    /// Start a new line at the current indentation.
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("    ");
+        }
+    }
+
+    fn declare(&mut self, id: &str, value: Option<i32>) {
+        self.scopes.last_mut().unwrap().insert(id.to_string(), value);
+    }
+
+    /// Print the dimensions of a definition, each can be halved.
+    fn dims(&mut self, dims: &[ConstExp]) {
+        for dim in dims {
+            self.write("[");
+            match dim.exp.evaluate(self) {
+                Some(len) if len >= 2 && self.site(Edit::Shrink) => write!(self.out, "{}", len / 2).unwrap(),
+                _ => dim.exp.print(self),
+            }
+            self.write("]");
+        }
+    }
+}
+
+pub trait Print {
+    fn print(&self, printer: &mut Printer);
+}
+
+/// Print a list of items separated by `sep`, skipping those where an edit of the kind is made.
+fn separated<T: Print>(printer: &mut Printer, items: &[T], sep: &str, edit: Option<Edit>) {
+    let mut first = true;
+    for item in items {
+        if edit.is_some_and(|edit| printer.site(edit)) {
+            continue;
+        }
+        if !first {
+            printer.write(sep);
+        }
+        first = false;
+        item.print(printer);
+    }
+}
+
+impl Print for CompUnit {
+    fn print(&self, printer: &mut Printer) {
+        for item in &self.items {
+            if printer.site(Edit::Item) {
+                continue;
+            }
+            match item {
+                CompItem::Func(func) => func.print(printer),
+                CompItem::Decl(decl) => decl.print(printer),
+                CompItem::Error { .. } => continue,
+            }
+            printer.write("\n");
+        }
+    }
+}
+
+impl Print for Decl {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Const(decl) => {
+                printer.write("const int ");
+                let edit = (decl.defs.len() > 1).then_some(Edit::Stmt);
+                separated(printer, &decl.defs, ", ", edit);
+            }
+            Self::Var(decl) => {
+                printer.write("int ");
+                let edit = (decl.defs.len() > 1).then_some(Edit::Stmt);
+                separated(printer, &decl.defs, ", ", edit);
+            }
+        }
+        printer.write(";");
+    }
+}
+
+impl Print for ConstDef {
+    fn print(&self, printer: &mut Printer) {
+        printer.write(&self.id);
+        printer.dims(&self.dims);
+        printer.write(" = ");
+        self.init.print(printer);
+        let value = match &self.init {
+            ConstInitVal::Exp(exp) if self.dims.is_empty() => exp.exp.evaluate(printer),
+            _ => None,
+        };
+        printer.declare(&self.id, value);
+    }
+}
+
+impl Print for ConstInitVal {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Exp(exp) => exp.exp.print(printer),
+            Self::List { list, .. } => {
+                printer.write("{");
+                separated(printer, list, ", ", Some(Edit::Element));
+                printer.write("}");
+            }
+        }
+    }
+}
+
+impl Print for VarDef {
+    fn print(&self, printer: &mut Printer) {
+        printer.write(&self.id);
+        printer.dims(&self.dims);
+        match &self.init {
+            Some(init) => {
+                printer.write(" = ");
+                init.print(printer);
+            }
+            // globals are zero already
+            None if printer.scopes.len() > 1 => printer.write(if self.dims.is_empty() { " = 0" } else { " = {}" }),
+            None => {}
+        }
+        printer.declare(&self.id, None);
+    }
+}
+
+impl Print for InitVal {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Exp(exp) => exp.print(printer),
+            Self::List { list, .. } => {
+                printer.write("{");
+                separated(printer, list, ", ", Some(Edit::Element));
+                printer.write("}");
+            }
+        }
+    }
+}
+
+impl Print for FuncDef {
+    fn print(&self, printer: &mut Printer) {
+        printer.write(match self.ty {
+            FuncType::Void => "void ",
+            FuncType::Int => "int ",
+        });
+        printer.write(&self.id);
+        printer.declare(&self.id, None);
+        printer.write("(");
+        printer.scopes.push(HashMap::new());
+        separated(printer, &self.params, ", ", None);
+        printer.write(") ");
+        self.body.print(printer);
+        printer.scopes.pop();
+    }
+}
+
+impl Print for FuncParam {
+    fn print(&self, printer: &mut Printer) {
+        printer.write("int ");
+        printer.write(&self.id);
+        if let Some(dims) = &self.dims {
+            printer.write("[]");
+            for dim in dims {
+                printer.write("[");
+                dim.exp.print(printer);
+                printer.write("]");
+            }
+        }
+        printer.declare(&self.id, None);
+    }
+}
+
+impl Print for Block {
+    fn print(&self, printer: &mut Printer) {
+        printer.write("{");
+        printer.indent += 1;
+        printer.scopes.push(HashMap::new());
+        for item in &self.items {
+            if printer.site(Edit::Stmt) {
+                continue;
+            }
+            match item {
+                BlockItem::Stmt(stmt) => {
+                    printer.newline();
+                    stmt.print(printer);
+                }
+                BlockItem::Decl(decl) => {
+                    printer.newline();
+                    decl.print(printer);
+                }
+                BlockItem::Error { .. } => {}
+            }
+        }
+        printer.scopes.pop();
+        printer.indent -= 1;
+        printer.newline();
+        printer.write("}");
+    }
+}
+
+impl Print for Stmt {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Return(ret) => {
+                printer.write("return");
+                if let Some(exp) = &ret.exp {
+                    printer.write(" ");
+                    exp.print(printer);
+                }
+                printer.write(";");
+            }
+            Self::Assign(assign) => {
+                assign.lval.print(printer);
+                printer.write(" = ");
+                assign.exp.print(printer);
+                printer.write(";");
+            }
+            Self::Exp { exp, .. } => {
+                if let Some(exp) = exp {
+                    exp.print(printer);
+                }
+                printer.write(";");
+            }
+            Self::Block(block) => block.print(printer),
+            Self::If(stmt) => {
+                if printer.site(Edit::Lift) {
+                    return stmt.then.print(printer);
+                }
+                if let Some(els) = &stmt.els {
+                    if printer.site(Edit::Lift) {
+                        return els.print(printer);
+                    }
+                }
+                let drop_else = stmt.els.is_some() && printer.site(Edit::Lift);
+                printer.write("if (");
+                stmt.cond.print(printer);
+                printer.write(") ");
+                match &stmt.els {
+                    Some(els) if !drop_else => {
+                        // a branch not in braces could take the `else` of an `if` inside it
+                        if matches!(stmt.then, Stmt::Block(_)) {
+                            stmt.then.print(printer);
+                        }
+                        else {
+                            printer.write("{");
+                            printer.indent += 1;
+                            printer.newline();
+                            stmt.then.print(printer);
+                            printer.indent -= 1;
+                            printer.newline();
+                            printer.write("}");
+                        }
+                        printer.newline();
+                        printer.write("else ");
+                        els.print(printer);
+                    }
+                    _ => stmt.then.print(printer),
+                }
+            }
+            Self::While(stmt) => {
+                if printer.site(Edit::Lift) {
+                    return stmt.body.print(printer);
+                }
+                printer.write("while (");
+                stmt.cond.print(printer);
+                printer.write(") ");
+                stmt.body.print(printer);
+            }
+            Self::Break(_) => printer.write("break;"),
+            Self::Continue(_) => printer.write("continue;"),
+            Self::Error { .. } => {}
+        }
+    }
+}
+
+impl Print for Exp {
+    fn print(&self, printer: &mut Printer) {
+        self.lor.print(printer);
+    }
+}
+
+impl Print for LVal {
+    fn print(&self, printer: &mut Printer) {
+        printer.write(&self.id);
+        for dim in &self.dims {
+            printer.write("[");
+            dim.print(printer);
+            printer.write("]");
+        }
+    }
+}
+
+impl Print for PrimaryExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Exp { exp, .. } => match bare(exp) {
+                Some(unary) if printer.site(Edit::Operand) => unary.print(printer),
+                _ => {
+                    printer.write("(");
+                    exp.print(printer);
+                    printer.write(")");
+                }
+            },
+            Self::LVal(lval) => match printer.constant(&lval.id) {
+                Some(value) if lval.dims.is_empty() && printer.site(Edit::Inline) => number(printer, value),
+                _ => lval.print(printer),
+            },
+            Self::Num { num, .. } => number(printer, *num),
+        }
+    }
+}
+
+/// The unary expression an expression is made of, which needs no parentheses.
+fn bare(exp: &Exp) -> Option<&UnaryExp> {
+    match &exp.lor {
+        LOrExp::LAnd(LAndExp::Eq(EqExp::Rel(RelExp::Add(AddExp::Mul(MulExp::Unary(unary)))))) => Some(unary),
+        _ => None,
+    }
+}
+
+/// Print a number, literals are never negative.
+fn number(printer: &mut Printer, value: i32) {
+    if value < 0 {
+        write!(printer.out, "(-{})", value.unsigned_abs()).unwrap();
+    }
+    else {
+        write!(printer.out, "{}", value).unwrap();
+    }
+}
+
+impl Print for UnaryExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Primary(primary) => primary.print(printer),
+            Self::Call(call) => {
+                printer.write(&call.id);
+                printer.write("(");
+                separated(printer, &call.args, ", ", None);
+                printer.write(")");
+            }
+            Self::Unary { op, unary, .. } => {
+                if !printer.site(Edit::Operand) {
+                    printer.write(match op {
+                        UnaryOp::Pos => "+",
+                        UnaryOp::Neg => "-",
+                        UnaryOp::Not => "!",
+                    });
+                }
+                unary.print(printer);
+            }
+        }
+    }
+}
+
+/// Print a binary expression, or one of its operands.
+fn binary<L: Print, R: Print>(printer: &mut Printer, lhs: &L, op: &str, rhs: &R) {
+    if printer.site(Edit::Operand) {
+        return lhs.print(printer);
+    }
+    if printer.site(Edit::Operand) {
+        return rhs.print(printer);
+    }
+    lhs.print(printer);
+    printer.write(op);
+    rhs.print(printer);
+}
+
+impl Print for MulExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Unary(unary) => unary.print(printer),
+            Self::Mul(mul, op, unary) => {
+                let op = match op {
+                    MulOp::Mul => " * ",
+                    MulOp::Div => " / ",
+                    MulOp::Mod => " % ",
+                };
+                binary(printer, mul.as_ref(), op, unary);
+            }
+        }
+    }
+}
+
+impl Print for AddExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Mul(mul) => mul.print(printer),
+            Self::Add(add, op, mul) => {
+                let op = match op {
+                    AddOp::Add => " + ",
+                    AddOp::Sub => " - ",
+                };
+                binary(printer, add.as_ref(), op, mul);
+            }
+        }
+    }
+}
+
+impl Print for RelExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Add(add) => add.print(printer),
+            Self::Rel(rel, op, add) => {
+                let op = match op {
+                    RelOp::Lt => " < ",
+                    RelOp::Gt => " > ",
+                    RelOp::Le => " <= ",
+                    RelOp::Ge => " >= ",
+                };
+                binary(printer, rel.as_ref(), op, add);
+            }
+        }
+    }
+}
+
+impl Print for EqExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Rel(rel) => rel.print(printer),
+            Self::Eq(eq, op, rel) => {
+                let op = match op {
+                    EqOp::Eq => " == ",
+                    EqOp::Ne => " != ",
+                };
+                binary(printer, eq.as_ref(), op, rel);
+            }
+        }
+    }
+}
+
+impl Print for LAndExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::Eq(eq) => eq.print(printer),
+            Self::LAnd(land, eq) => binary(printer, land.as_ref(), " && ", eq),
+        }
+    }
+}
+
+impl Print for LOrExp {
+    fn print(&self, printer: &mut Printer) {
+        match self {
+            Self::LAnd(land) => land.print(printer),
+            Self::LOr(lor, land) => binary(printer, lor.as_ref(), " || ", land),
+        }
+    }
+}
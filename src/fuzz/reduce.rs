@@ -0,0 +1,228 @@
+//! # Reduce
+//!
+//! In this file, we reduce a program while it stays interesting, to find the smallest one showing a bug.
+//!
+//! A candidate is the program printed with one edit, see `print`. It's kept if it still compiles
+//! and the predicate holds, and edits are tried until none of them is kept in a whole round.
+//! The predicate is a command given the candidate file, interesting if it succeeds, or by default:
+//!
+//! * `-interp-ast` runs the candidate without an error, so it means something.
+//! * The first mode and level differing from `-interp-ast` on the original program still differ on it.
+//! * The compiler crashes on it if it crashed on the original program, and doesn't otherwise.
+//!
+//! Every program runs in another process of the compiler, with the `.in` file of the same name as
+//! the original program as stdin if there's one, so crashes and endless loops are only results.
+//!
+
+use crate::ast::CompUnit;
+use crate::check::check;
+use crate::fuzz::print::{ Edit, Print, Printer };
+use crate::mem::generate_mem;
+use crate::sysy::CompUnitParser;
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+use std::process::{ self, Command, Output, Stdio };
+
+/// Steps of the compiled program in every mode, unless `--max-steps` is given.
+const MAX_STEPS: u64 = 1_000_000_000;
+
+const MODES: [&str; 2] = ["-interp-koopa", "run"];
+const LEVELS: [&str; 3] = ["-O0", "-O1", "-O2"];
+
+/// The program printed with an edit at a site, and the number of sites of the kind.
+fn print(ast: &CompUnit, target: Option<(Edit, usize)>) -> (String, usize) {
+    let mut printer = Printer::new(target);
+    ast.print(&mut printer);
+    let sites = target.map_or(0, |(edit, _)| printer.sites(edit));
+    (printer.finish(), sites)
+}
+
+/// The AST of a program, if it compiles.
+fn compile(source: &str) -> Option<CompUnit> {
+    let mut errors = vec![];
+    let ast = CompUnitParser::new().parse(&mut errors, source).ok()?;
+    if !errors.is_empty() || !check(&ast).is_empty() || generate_mem(&ast).is_err() {
+        return None;
+    }
+    Some(ast)
+}
+
+/// Run a command with the input as stdin.
+fn execute(command: &mut Command, input: &[u8]) -> Result<Output, String> {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()
+        .map_err(|error| format!("can't run `{:?}`: {}", command, error))?;
+    // a program may exit without reading all of its input
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().map_err(|error| error.to_string())
+}
+
+/// What a program did: its stdout and exit code, and whether it stopped with an error or crashed the compiler.
+struct Outcome {
+    stdout: Vec<u8>,
+    code: Option<i32>,
+    error: bool,
+    crash: bool,
+}
+
+impl Outcome {
+    fn new(output: &Output) -> Self {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Self {
+            stdout: output.stdout.clone(),
+            code: output.status.code(),
+            error: stderr.lines().any(|line| line.starts_with("error: ")),
+            crash: stderr.contains("internal compiler error"),
+        }
+    }
+
+    /// Whether a compiled program did something else than the oracle.
+    fn differs(&self, oracle: &Outcome) -> bool {
+        self.stdout != oracle.stdout || self.code != oracle.code || self.error
+    }
+}
+
+enum Predicate {
+    Command(Vec<String>),
+    /// The mode and level to compare with `-interp-ast`, and whether the compiler crashed on the original program.
+    Differs { mode: &'static str, level: &'static str, crash: bool },
+}
+
+/// How candidates are run.
+struct Test {
+    compiler: PathBuf,
+    /// Options given to the compiler after the level.
+    options: Vec<String>,
+    /// File the candidates are written to.
+    file: PathBuf,
+    input: Vec<u8>,
+    oracle_steps: u64,
+    steps: u64,
+}
+
+impl Test {
+    /// Run the compiler on the candidate file in a mode.
+    fn run(&self, mode: &str, args: &[&str], steps: u64) -> Result<Output, String> {
+        let mut command = Command::new(&self.compiler);
+        command.arg(mode).arg(&self.file).args(args).args(&self.options).arg(format!("--max-steps={}", steps));
+        execute(&mut command, &self.input)
+    }
+
+    /// Find the first mode and level differing from `-interp-ast` on the program, fail if there's none.
+    fn differing(&mut self, source: &str, limited: bool) -> Result<Predicate, String> {
+        fs::write(&self.file, source).map_err(|error| error.to_string())?;
+        let output = self.run("-interp-ast", &["--stats"], self.oracle_steps)?;
+        let oracle = Outcome::new(&output);
+        if oracle.error || oracle.crash {
+            return Err("`-interp-ast` fails on the program, so it means nothing".to_string());
+        }
+        // a candidate may loop forever, it's stopped after twice the steps of the original
+        if !limited {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if let Some(steps) = stderr.lines().find_map(|line| line.strip_prefix("steps: ")?.parse::<u64>().ok()) {
+                self.oracle_steps = steps * 2 + 1000;
+            }
+        }
+
+        for level in LEVELS {
+            for mode in MODES {
+                let outcome = Outcome::new(&self.run(mode, &[level], self.steps)?);
+                if outcome.differs(&oracle) {
+                    eprintln!("`{} {}` differs from `-interp-ast`{}", mode, level, if outcome.crash { " by a crash" } else { "" });
+                    return Ok(Predicate::Differs { mode, level, crash: outcome.crash });
+                }
+            }
+        }
+        Err("every mode agrees with `-interp-ast` on the program".to_string())
+    }
+
+    fn interesting(&self, predicate: &Predicate, source: &str) -> Result<bool, String> {
+        fs::write(&self.file, source).map_err(|error| error.to_string())?;
+        match predicate {
+            Predicate::Command(words) => {
+                let mut command = Command::new(&words[0]);
+                command.args(&words[1..]).arg(&self.file);
+                Ok(execute(&mut command, &self.input)?.status.success())
+            }
+            Predicate::Differs { mode, level, crash } => {
+                let oracle = Outcome::new(&self.run("-interp-ast", &[], self.oracle_steps)?);
+                if oracle.error || oracle.crash {
+                    return Ok(false);
+                }
+                let outcome = Outcome::new(&self.run(mode, &[level], self.steps)?);
+                Ok(outcome.differs(&oracle) && outcome.crash == *crash)
+            }
+        }
+    }
+}
+
+/// Reduce the program at `path` while it's interesting, and write the smallest one found to `output`.
+/// `command` is the predicate instead of comparing modes, `steps` limits every run, and `options` are
+/// given to the compiler in every mode tried.
+pub fn reduce(path: &str, output: &str, command: Option<&str>, steps: Option<u64>, options: Vec<String>) -> Result<(), String> {
+    let source = fs::read_to_string(path).map_err(|error| format!("can't read {}: {}", path, error))?;
+    let ast = compile(&source).ok_or("the program doesn't compile")?;
+    // the program printed back is where reducing starts, it must be as interesting as the original
+    let (mut text, _) = print(&ast, None);
+    let mut ast = compile(&text).ok_or("the program printed back doesn't compile")?;
+
+    let mut test = Test {
+        compiler: env::current_exe().map_err(|error| error.to_string())?,
+        options,
+        file: env::temp_dir().join(format!("sysy-reduce-{}.sy", process::id())),
+        input: fs::read(Path::new(path).with_extension("in")).unwrap_or_default(),
+        oracle_steps: steps.unwrap_or(MAX_STEPS),
+        steps: steps.unwrap_or(MAX_STEPS),
+    };
+    let result = (|| {
+        let predicate = match command {
+            Some(command) => {
+                let predicate = Predicate::Command(command.split_whitespace().map(|word| word.to_string()).collect());
+                if !test.interesting(&predicate, &text)? {
+                    return Err("the command fails on the program".to_string());
+                }
+                predicate
+            }
+            None => test.differing(&text, steps.is_some())?,
+        };
+
+        let lines = text.lines().count();
+        let mut round = 0;
+        while reduce_round(&test, &predicate, &mut ast, &mut text, output)? {
+            round += 1;
+            eprintln!("round {}: {} lines", round, text.lines().count());
+        }
+        fs::write(output, &text).map_err(|error| error.to_string())?;
+        eprintln!("reduced {} lines to {}", lines, text.lines().count());
+        Ok(())
+    })();
+    let _ = fs::remove_file(&test.file);
+    result
+}
+
+/// Try every edit at every site once, keep those leaving the program interesting.
+/// Return whether any has been kept.
+fn reduce_round(test: &Test, predicate: &Predicate, ast: &mut CompUnit, text: &mut String, output: &str) -> Result<bool, String> {
+    let mut reduced = false;
+    for edit in Edit::ALL {
+        let mut site = 0;
+        loop {
+            let (candidate, sites) = print(ast, Some((edit, site)));
+            if site >= sites {
+                break;
+            }
+            match compile(&candidate) {
+                Some(next) if candidate != *text && test.interesting(predicate, &candidate)? => {
+                    // the sites after this one are numbered again in the new program
+                    *ast = next;
+                    *text = candidate;
+                    fs::write(output, &*text).map_err(|error| error.to_string())?;
+                    reduced = true;
+                }
+                _ => site += 1,
+            }
+        }
+    }
+    Ok(reduced)
+}
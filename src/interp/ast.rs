@@ -0,0 +1,613 @@
+//! # Ast
+//!
+//! In this file, we define a tree-walking interpreter of the AST, which runs a checked program by the meaning
+//! of SysY itself, without `mem`, the passes or the backend. Its output is an oracle for the compiled program.
+//!
+//! Memory is a vector of words like in the interpreter of Koopa IR, with the first word never used.
+//! Every variable, scalar or array, takes words of it, and an array decays to the address of its first element
+//! when passed to a function. Objects of a block are freed when it's left.
+//!
+//! The meaning of a program is made as strict as the interpreter can check:
+//! * arithmetic wraps, and dividing by zero stops the program with an error.
+//! * `&&` and `||` don't evaluate their right operands once the result is known.
+//! * an index out of the bounds of its dimension stops the program, except the first one of an array parameter.
+//! * operands and arguments are evaluated from left to right, and the value of an assignment before its indices,
+//!   the same order as `mem` creates them in.
+//! * local variables without an initializer start as zero, programs shouldn't depend on it.
+//!
+
+use crate::ast::*;
+use crate::emu::runtime::Runtime;
+use std::collections::HashMap;
+use std::io::{ BufRead, Write };
+
+/// Size of the stack of the thread running the interpreter, which recurses as deep as the program.
+pub const STACK_SIZE: usize = 1 << 30;
+/// Stack kept free by a call, enough for the body of any function until it calls again.
+const STACK_MARGIN: usize = 16 << 20;
+
+#[derive(Clone)]
+struct Symbol {
+    address: i32,
+    /// Lengths of each dimension, only the first one of an array parameter is unknown.
+    dims: Vec<Option<usize>>,
+}
+
+struct Func<'ast> {
+    def: &'ast FuncDef,
+    /// Dimensions of each parameter, empty for a scalar.
+    params: Vec<Vec<Option<usize>>>,
+}
+
+/// How a statement is left.
+enum Flow {
+    Next,
+    Break,
+    Continue,
+    Return(i32),
+}
+
+pub struct Interpreter<'ast, R: BufRead, W: Write> {
+    runtime: Runtime<R, W>,
+    memory: Vec<i32>,
+    globals: HashMap<&'ast str, Symbol>,
+    /// Blocks of the function being run, the innermost last.
+    locals: Vec<HashMap<&'ast str, Symbol>>,
+    funcs: HashMap<&'ast str, Func<'ast>>,
+    /// Address on the stack when the interpreter starts, deeper calls take the stack below it.
+    stack_top: usize,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl<'ast, R: BufRead, W: Write> Interpreter<'ast, R, W> {
+    pub fn new(runtime: Runtime<R, W>) -> Self {
+        Self {
+            runtime,
+            memory: vec![0],
+            globals: HashMap::new(),
+            locals: vec![],
+            funcs: HashMap::new(),
+            stack_top: 0,
+            steps: 0,
+            step_limit: None,
+        }
+    }
+
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Number of statements executed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn runtime_mut(&mut self) -> &mut Runtime<R, W> {
+        &mut self.runtime
+    }
+
+    /// Define the globals and functions, and call `main`. Return the exit code of the program.
+    pub fn run(&mut self, ast: &'ast CompUnit) -> Result<i32, String> {
+        let top = 0u8;
+        self.stack_top = &top as *const u8 as usize;
+        ast.interpret(self)?;
+        if !self.funcs.contains_key("main") {
+            return Err("no `main` function".to_string());
+        }
+        self.call("main", vec![])
+    }
+
+    fn step(&mut self) -> Result<(), String> {
+        if let Some(limit) = self.step_limit {
+            if self.steps >= limit {
+                return Err("step limit exceeded".to_string());
+            }
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    fn symbol(&self, id: &str) -> Result<Symbol, String> {
+        self.locals.iter().rev().find_map(|block| block.get(id))
+            .or_else(|| self.globals.get(id))
+            .cloned()
+            .ok_or_else(|| format!("use of undeclared identifier `{}`", id))
+    }
+
+    /// Define a symbol in the innermost block, or as a global out of functions.
+    fn define(&mut self, id: &'ast str, symbol: Symbol) {
+        match self.locals.last_mut() {
+            Some(block) => block.insert(id, symbol),
+            None => self.globals.insert(id, symbol),
+        };
+    }
+
+    /// Allocate zeroed words, return the address of the first one.
+    fn alloc(&mut self, size: usize) -> i32 {
+        let address = self.memory.len() as i32;
+        self.memory.resize(self.memory.len() + size, 0);
+        address
+    }
+
+    fn load(&self, address: i32) -> Result<i32, String> {
+        match usize::try_from(address) {
+            Ok(index) if index > 0 && index < self.memory.len() => Ok(self.memory[index]),
+            _ => Err(format!("invalid memory access at word {}", address)),
+        }
+    }
+
+    fn store(&mut self, address: i32, value: i32) -> Result<(), String> {
+        match usize::try_from(address) {
+            Ok(index) if index > 0 && index < self.memory.len() => {
+                self.memory[index] = value;
+                Ok(())
+            }
+            _ => Err(format!("invalid memory access at word {}", address)),
+        }
+    }
+
+    /// Lengths of array dimensions, which are constant expressions.
+    fn dims(&mut self, dims: &'ast [ConstExp]) -> Result<Vec<usize>, String> {
+        dims.iter().map(|dim| {
+            let len = dim.exp.interpret(self)?;
+            usize::try_from(len).map_err(|_| format!("array dimension {} is negative", len))
+        }).collect()
+    }
+
+    /// Define a constant or a variable, with the expressions of its initializer at their positions.
+    fn declare<I: Init>(&mut self, id: &'ast str, dims: &'ast [ConstExp], init: Option<&'ast I>) -> Result<(), String> {
+        let dims = self.dims(dims)?;
+        let address = self.alloc(dims.iter().product());
+        let mut exps = vec![];
+        match (init, dims.is_empty()) {
+            (None, _) => {}
+            (Some(init), true) => match init.exp() {
+                Some(exp) => exps.push((0, exp)),
+                None => return Err(format!("`{}` is a single variable initialized with a list", id)),
+            },
+            (Some(init), false) => match init.list() {
+                Some(list) => flatten(list, &dims, 0, &mut exps).map_err(|error| format!("{} of `{}`", error, id))?,
+                None => return Err(format!("array `{}` is initialized with a single expression", id)),
+            },
+        }
+        for (offset, exp) in exps {
+            let value = exp.interpret(self)?;
+            self.store(address + offset as i32, value)?;
+        }
+        // the name is visible after its initializer
+        self.define(id, Symbol { address, dims: dims.into_iter().map(Some).collect() });
+        Ok(())
+    }
+
+    /// Address of the element or the subarray, and the number of dimensions left.
+    fn address(&mut self, lval: &'ast LVal) -> Result<(i32, usize), String> {
+        let symbol = self.symbol(&lval.id)?;
+        if lval.dims.len() > symbol.dims.len() {
+            return Err(format!("too many subscripts of `{}`", lval.id));
+        }
+        let mut address = symbol.address;
+        for (i, exp) in lval.dims.iter().enumerate() {
+            let index = exp.interpret(self)?;
+            if let Some(len) = symbol.dims[i] {
+                if index < 0 || index as usize >= len {
+                    return Err(format!("index {} out of bounds of `{}`, whose dimension {} is {}", index, lval.id, i, len));
+                }
+            }
+            let stride: usize = symbol.dims[i + 1..].iter().map(|dim| dim.unwrap()).product();
+            address = address.wrapping_add(index.wrapping_mul(stride as i32));
+        }
+        Ok((address, symbol.dims.len() - lval.dims.len()))
+    }
+
+    fn call(&mut self, id: &str, args: Vec<i32>) -> Result<i32, String> {
+        let (def, params) = match self.funcs.get(id) {
+            Some(func) => (func.def, func.params.clone()),
+            None => return self.call_runtime(id, &args),
+        };
+        let here = 0u8;
+        if self.stack_top.abs_diff(&here as *const u8 as usize) > STACK_SIZE - STACK_MARGIN {
+            return Err(format!("calls nested too deep, in `{}`", id));
+        }
+
+        let caller = std::mem::replace(&mut self.locals, vec![HashMap::new()]);
+        let base = self.memory.len();
+        for ((param, dims), arg) in def.params.iter().zip(params).zip(args) {
+            // an array is passed by the address of its first element
+            let address = if dims.is_empty() {
+                let address = self.alloc(1);
+                self.store(address, arg)?;
+                address
+            }
+            else {
+                arg
+            };
+            self.define(&param.id, Symbol { address, dims });
+        }
+        let flow = def.body.interpret(self)?;
+        self.memory.truncate(base);
+        self.locals = caller;
+        match flow {
+            Flow::Return(value) => Ok(value),
+            // falling off the end of a function returns nothing, which reads as zero
+            _ => Ok(0),
+        }
+    }
+
+    fn call_runtime(&mut self, id: &str, args: &[i32]) -> Result<i32, String> {
+        let io = |error: std::io::Error| error.to_string();
+        match id {
+            "getint" => self.runtime.getint().map_err(io),
+            "getch" => self.runtime.getch().map_err(io),
+            "getarray" => {
+                let values = self.runtime.getarray().map_err(io)?;
+                for (i, &value) in values.iter().enumerate() {
+                    self.store(args[0].wrapping_add(i as i32), value)?;
+                }
+                Ok(values.len() as i32)
+            }
+            "putint" => self.runtime.putint(args[0]).map(|_| 0).map_err(io),
+            "putch" => self.runtime.putch(args[0]).map(|_| 0).map_err(io),
+            "putarray" => {
+                let values = (0..args[0].max(0)).map(|i| self.load(args[1].wrapping_add(i))).collect::<Result<Vec<_>, _>>()?;
+                self.runtime.putarray(&values).map(|_| 0).map_err(io)
+            }
+            "starttime" => {
+                self.runtime.starttime();
+                Ok(0)
+            }
+            "stoptime" => self.runtime.stoptime().map(|_| 0).map_err(io),
+            _ => Err(format!("call to undeclared function `{}`", id)),
+        }
+    }
+}
+
+/// Initializers of constants and variables, which are laid out the same way.
+trait Init: Sized {
+    fn exp(&self) -> Option<&Exp>;
+    fn list(&self) -> Option<&[Self]>;
+}
+
+impl Init for ConstInitVal {
+    fn exp(&self) -> Option<&Exp> {
+        match self {
+            Self::Exp(exp) => Some(&exp.exp),
+            Self::List { .. } => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Self]> {
+        match self {
+            Self::Exp(_) => None,
+            Self::List { list, .. } => Some(list),
+        }
+    }
+}
+
+impl Init for InitVal {
+    fn exp(&self) -> Option<&Exp> {
+        match self {
+            Self::Exp(exp) => Some(exp),
+            Self::List { .. } => None,
+        }
+    }
+
+    fn list(&self) -> Option<&[Self]> {
+        match self {
+            Self::Exp(_) => None,
+            Self::List { list, .. } => Some(list),
+        }
+    }
+}
+
+/// Push the expressions of a list initializing an array of shape `dims` from `begin`, with their positions.
+/// A nested list starts at the next row, and initializes the largest subarray aligned there, but never the whole array.
+fn flatten<'ast, I: Init>(list: &'ast [I], dims: &[usize], begin: usize, exps: &mut Vec<(usize, &'ast Exp)>) -> Result<(), String> {
+    let size: usize = dims.iter().product();
+    let row = *dims.last().unwrap();
+    let mut count = 0;
+    for elem in list {
+        match elem.exp() {
+            Some(exp) => {
+                if count >= size {
+                    return Err("too many initializers".to_string());
+                }
+                exps.push((begin + count, exp));
+                count += 1;
+            }
+            None => {
+                if count % row != 0 {
+                    count += row - count % row;
+                }
+                let mut first = dims.len() - 1;
+                let mut len = row;
+                while first > 1 && count % (len * dims[first - 1]) == 0 {
+                    first -= 1;
+                    len *= dims[first];
+                }
+                if count + len > size {
+                    return Err("too many initializers".to_string());
+                }
+                flatten(elem.list().unwrap(), &dims[first..], begin + count, exps)?;
+                count += len;
+            }
+        }
+    }
+    Ok(())
+}
+
+trait Interpret<'ast> {
+    type Out;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String>;
+}
+
+impl<'ast> Interpret<'ast> for CompUnit {
+    type Out = ();
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        for item in &self.items {
+            match item {
+                CompItem::Func(def) => {
+                    let params = def.params.iter().map(|param| match &param.dims {
+                        Some(dims) => interpreter.dims(dims).map(|dims| {
+                            std::iter::once(None).chain(dims.into_iter().map(Some)).collect()
+                        }),
+                        None => Ok(vec![]),
+                    }).collect::<Result<Vec<_>, _>>()?;
+                    interpreter.funcs.insert(&def.id, Func { def, params });
+                }
+                CompItem::Decl(decl) => decl.interpret(interpreter)?,
+                CompItem::Error { .. } => return Err("can't interpret code with syntax errors".to_string()),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'ast> Interpret<'ast> for Decl {
+    type Out = ();
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Const(decl) => {
+                for def in &decl.defs {
+                    interpreter.declare(&def.id, &def.dims, Some(&def.init))?;
+                }
+            }
+            Self::Var(decl) => {
+                for def in &decl.defs {
+                    interpreter.declare(&def.id, &def.dims, def.init.as_ref())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'ast> Interpret<'ast> for Block {
+    type Out = Flow;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        interpreter.locals.push(HashMap::new());
+        let base = interpreter.memory.len();
+        let mut flow = Flow::Next;
+        for item in &self.items {
+            flow = match item {
+                BlockItem::Stmt(stmt) => stmt.interpret(interpreter)?,
+                BlockItem::Decl(decl) => decl.interpret(interpreter).map(|_| Flow::Next)?,
+                BlockItem::Error { .. } => return Err("can't interpret code with syntax errors".to_string()),
+            };
+            if !matches!(flow, Flow::Next) {
+                break;
+            }
+        }
+        interpreter.memory.truncate(base);
+        interpreter.locals.pop();
+        Ok(flow)
+    }
+}
+
+impl<'ast> Interpret<'ast> for Stmt {
+    type Out = Flow;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        interpreter.step()?;
+        match self {
+            Self::Return(ret) => match &ret.exp {
+                Some(exp) => exp.interpret(interpreter).map(Flow::Return),
+                None => Ok(Flow::Return(0)),
+            },
+            Self::Assign(assign) => {
+                let value = assign.exp.interpret(interpreter)?;
+                let (address, left) = interpreter.address(&assign.lval)?;
+                if left != 0 {
+                    return Err(format!("assignment to array `{}`", assign.lval.id));
+                }
+                interpreter.store(address, value)?;
+                Ok(Flow::Next)
+            }
+            Self::Exp { exp, .. } => {
+                if let Some(exp) = exp {
+                    exp.interpret(interpreter)?;
+                }
+                Ok(Flow::Next)
+            }
+            Self::Block(block) => block.interpret(interpreter),
+            Self::If(stmt) => {
+                if stmt.cond.interpret(interpreter)? != 0 {
+                    stmt.then.interpret(interpreter)
+                }
+                else {
+                    match &stmt.els {
+                        Some(els) => els.interpret(interpreter),
+                        None => Ok(Flow::Next),
+                    }
+                }
+            }
+            Self::While(stmt) => {
+                while stmt.cond.interpret(interpreter)? != 0 {
+                    match stmt.body.interpret(interpreter)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Next | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Next)
+            }
+            Self::Break(_) => Ok(Flow::Break),
+            Self::Continue(_) => Ok(Flow::Continue),
+            Self::Error { .. } => Err("can't interpret code with syntax errors".to_string()),
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for Exp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        self.lor.interpret(interpreter)
+    }
+}
+
+impl<'ast> Interpret<'ast> for LVal {
+    type Out = i32;
+    /// The value of an element, or the address of a subarray.
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        let (address, left) = interpreter.address(self)?;
+        if left == 0 {
+            interpreter.load(address)
+        }
+        else {
+            Ok(address)
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for PrimaryExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Exp { exp, .. } => exp.interpret(interpreter),
+            Self::LVal(lval) => lval.interpret(interpreter),
+            Self::Num { num, .. } => Ok(*num),
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for UnaryExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Primary(primary) => primary.interpret(interpreter),
+            Self::Call(call) => {
+                let args = call.args.iter().map(|arg| arg.interpret(interpreter)).collect::<Result<Vec<_>, _>>()?;
+                interpreter.call(&call.id, args)
+            }
+            Self::Unary { op, unary, .. } => {
+                let value = unary.interpret(interpreter)?;
+                Ok(match op {
+                    UnaryOp::Pos => value,
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Not => (value == 0) as i32,
+                })
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for MulExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Unary(unary) => unary.interpret(interpreter),
+            Self::Mul(mul, op, unary) => {
+                let left = mul.interpret(interpreter)?;
+                let right = unary.interpret(interpreter)?;
+                match op {
+                    MulOp::Mul => Ok(left.wrapping_mul(right)),
+                    MulOp::Div | MulOp::Mod if right == 0 => Err("division by zero".to_string()),
+                    MulOp::Div => Ok(left.wrapping_div(right)),
+                    MulOp::Mod => Ok(left.wrapping_rem(right)),
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for AddExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Mul(mul) => mul.interpret(interpreter),
+            Self::Add(add, op, mul) => {
+                let left = add.interpret(interpreter)?;
+                let right = mul.interpret(interpreter)?;
+                match op {
+                    AddOp::Add => Ok(left.wrapping_add(right)),
+                    AddOp::Sub => Ok(left.wrapping_sub(right)),
+                }
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for RelExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Add(add) => add.interpret(interpreter),
+            Self::Rel(rel, op, add) => {
+                let left = rel.interpret(interpreter)?;
+                let right = add.interpret(interpreter)?;
+                Ok(match op {
+                    RelOp::Lt => (left < right) as i32,
+                    RelOp::Gt => (left > right) as i32,
+                    RelOp::Le => (left <= right) as i32,
+                    RelOp::Ge => (left >= right) as i32,
+                })
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for EqExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Rel(rel) => rel.interpret(interpreter),
+            Self::Eq(eq, op, rel) => {
+                let left = eq.interpret(interpreter)?;
+                let right = rel.interpret(interpreter)?;
+                Ok(match op {
+                    EqOp::Eq => (left == right) as i32,
+                    EqOp::Ne => (left != right) as i32,
+                })
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for LAndExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::Eq(eq) => eq.interpret(interpreter),
+            Self::LAnd(land, eq) => {
+                if land.interpret(interpreter)? == 0 {
+                    return Ok(0);
+                }
+                Ok((eq.interpret(interpreter)? != 0) as i32)
+            }
+        }
+    }
+}
+
+impl<'ast> Interpret<'ast> for LOrExp {
+    type Out = i32;
+    fn interpret<R: BufRead, W: Write>(&'ast self, interpreter: &mut Interpreter<'ast, R, W>) -> Result<Self::Out, String> {
+        match self {
+            Self::LAnd(land) => land.interpret(interpreter),
+            Self::LOr(lor, land) => {
+                if lor.interpret(interpreter)? != 0 {
+                    return Ok(1);
+                }
+                Ok((land.interpret(interpreter)? != 0) as i32)
+            }
+        }
+    }
+}
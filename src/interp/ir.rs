@@ -0,0 +1,313 @@
+//! # Ir
+//!
+//! In this file, we define an interpreter of Koopa IR in memory, so the IR can be checked without the backend.
+//!
+//! Memory is a vector of words, and a pointer is the index of a word in it. The first word is never used,
+//! so a null pointer is an invalid access. Globals come first, and every call allocates its stack objects
+//! after those of its caller, they are freed when it returns. Calls are frames in a vector instead of
+//! recursion of the interpreter, so deep recursion of the program is fine.
+//!
+//! Arithmetic wraps like on the target, and dividing by zero stops the program with an error.
+//!
+
+use crate::emu::runtime::Runtime;
+use crate::tools::get_size_form_ty;
+use koopa::ir::{ BasicBlock, BinaryOp, Function, Program, Type, TypeKind, Value, ValueKind };
+use std::collections::HashMap;
+use std::io::{ BufRead, Write };
+
+struct Frame {
+    func: Function,
+    bb: BasicBlock,
+    /// Index of the next instruction in the block.
+    index: usize,
+    values: HashMap<Value, i32>,
+    /// Size of memory when the function is called.
+    base: usize,
+}
+
+pub struct Interpreter<'p> {
+    program: &'p Program,
+    memory: Vec<i32>,
+    globals: HashMap<Value, i32>,
+    /// Instructions of each block with a body.
+    insts: HashMap<BasicBlock, Vec<Value>>,
+    steps: u64,
+    step_limit: Option<u64>,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(program: &'p Program) -> Self {
+        let mut insts = HashMap::new();
+        for &func in program.func_layout() {
+            for (&bb, node) in program.func(func).layout().bbs() {
+                insts.insert(bb, node.insts().keys().copied().collect());
+            }
+        }
+
+        let mut interpreter = Self { program, memory: vec![0], globals: HashMap::new(), insts, steps: 0, step_limit: None };
+        for &global in program.inst_layout() {
+            let address = interpreter.memory.len() as i32;
+            if let ValueKind::GlobalAlloc(alloc) = program.borrow_value(global).kind() {
+                interpreter.init(alloc.init());
+            }
+            interpreter.globals.insert(global, address);
+        }
+        interpreter
+    }
+
+    pub fn set_step_limit(&mut self, limit: Option<u64>) {
+        self.step_limit = limit;
+    }
+
+    /// Number of instructions executed.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Append the initial value of a global to memory.
+    fn init(&mut self, init: Value) {
+        let data = self.program.borrow_value(init);
+        match data.kind() {
+            ValueKind::Integer(integer) => self.memory.push(integer.value()),
+            ValueKind::Aggregate(aggregate) => {
+                for &elem in aggregate.elems() {
+                    self.init(elem);
+                }
+            }
+            _ => {
+                let size = get_size_form_ty(data.ty());
+                self.memory.resize(self.memory.len() + size, 0);
+            }
+        }
+    }
+
+    /// Run `@main` until it returns. Return the exit code of the program.
+    pub fn run<R: BufRead, W: Write>(&mut self, runtime: &mut Runtime<R, W>) -> Result<i32, String> {
+        let main = self.program.func_layout().iter().copied()
+            .find(|&func| self.program.func(func).name() == "@main")
+            .ok_or("no `main` function")?;
+        let mut frames = vec![self.frame(main, vec![])?];
+
+        loop {
+            if let Some(limit) = self.step_limit {
+                if self.steps >= limit {
+                    return Err("step limit exceeded".to_string());
+                }
+            }
+            self.steps += 1;
+
+            let frame = frames.last_mut().unwrap();
+            let inst = self.insts[&frame.bb][frame.index];
+            frame.index += 1;
+            let func = self.program.func(frame.func);
+            let data = func.dfg().value(inst);
+            match data.kind() {
+                // integers of this project are in the layout, before their users
+                ValueKind::Integer(integer) => {
+                    frame.values.insert(inst, integer.value());
+                }
+                ValueKind::Alloc(_) => {
+                    let size = match data.ty().kind() {
+                        TypeKind::Pointer(base) => get_size_form_ty(base),
+                        _ => unreachable!(),
+                    };
+                    let address = self.memory.len() as i32;
+                    self.memory.resize(self.memory.len() + size, 0);
+                    frame.values.insert(inst, address);
+                }
+                ValueKind::Load(load) => {
+                    let address = self.get(frame, load.src())?;
+                    let value = self.load(address)?;
+                    frame.values.insert(inst, value);
+                }
+                ValueKind::Store(store) => {
+                    let address = self.get(frame, store.dest())?;
+                    let value = self.get(frame, store.value())?;
+                    self.store(address, value)?;
+                }
+                ValueKind::GetElemPtr(gep) => {
+                    let stride = match self.ty(frame, gep.src()).kind() {
+                        TypeKind::Pointer(base) => match base.kind() {
+                            TypeKind::Array(elem, _) => get_size_form_ty(elem),
+                            _ => return Err("`getelemptr` on a pointer not to an array".to_string()),
+                        },
+                        _ => unreachable!(),
+                    };
+                    let address = self.offset(frame, gep.src(), gep.index(), stride)?;
+                    frame.values.insert(inst, address);
+                }
+                ValueKind::GetPtr(get_ptr) => {
+                    let stride = match self.ty(frame, get_ptr.src()).kind() {
+                        TypeKind::Pointer(base) => get_size_form_ty(base),
+                        _ => unreachable!(),
+                    };
+                    let address = self.offset(frame, get_ptr.src(), get_ptr.index(), stride)?;
+                    frame.values.insert(inst, address);
+                }
+                ValueKind::Binary(binary) => {
+                    let lhs = self.get(frame, binary.lhs())?;
+                    let rhs = self.get(frame, binary.rhs())?;
+                    let value = binary_op(binary.op(), lhs, rhs)?;
+                    frame.values.insert(inst, value);
+                }
+                ValueKind::Branch(branch) => {
+                    let (target, args) = if self.get(frame, branch.cond())? != 0 {
+                        (branch.true_bb(), branch.true_args())
+                    }
+                    else {
+                        (branch.false_bb(), branch.false_args())
+                    };
+                    self.enter(frame, target, args)?;
+                }
+                ValueKind::Jump(jump) => self.enter(frame, jump.target(), jump.args())?,
+                ValueKind::Call(call) => {
+                    let args = call.args().iter().map(|&arg| self.get(frame, arg)).collect::<Result<Vec<_>, _>>()?;
+                    if self.program.func(call.callee()).layout().entry_bb().is_some() {
+                        let callee = self.frame(call.callee(), args)?;
+                        frames.push(callee);
+                    }
+                    else {
+                        let name = self.program.func(call.callee()).name();
+                        let value = self.call_runtime(name, &args, runtime)?;
+                        frame.values.insert(inst, value);
+                    }
+                }
+                ValueKind::Return(ret) => {
+                    let value = match ret.value() {
+                        Some(value) => self.get(frame, value)?,
+                        None => 0,
+                    };
+                    let frame = frames.pop().unwrap();
+                    self.memory.truncate(frame.base);
+                    match frames.last_mut() {
+                        // the call is the instruction before the next one of the caller
+                        Some(caller) => {
+                            let call = self.insts[&caller.bb][caller.index - 1];
+                            caller.values.insert(call, value);
+                        }
+                        None => return Ok(value),
+                    }
+                }
+                _ => return Err(format!("unsupported instruction in {}", func.name())),
+            }
+        }
+    }
+
+    /// A frame calling the function with the arguments.
+    fn frame(&self, func: Function, args: Vec<i32>) -> Result<Frame, String> {
+        let data = self.program.func(func);
+        let bb = data.layout().entry_bb().ok_or_else(|| format!("{} has no body", data.name()))?;
+        let values = data.params().iter().copied().zip(args).collect();
+        Ok(Frame { func, bb, index: 0, values, base: self.memory.len() })
+    }
+
+    /// Jump to a block, passing the arguments to its parameters at once.
+    fn enter(&self, frame: &mut Frame, target: BasicBlock, args: &[Value]) -> Result<(), String> {
+        let args = args.iter().map(|&arg| self.get(frame, arg)).collect::<Result<Vec<_>, _>>()?;
+        let params = self.program.func(frame.func).dfg().bb(target).params();
+        frame.values.extend(params.iter().copied().zip(args));
+        frame.bb = target;
+        frame.index = 0;
+        Ok(())
+    }
+
+    fn get(&self, frame: &Frame, value: Value) -> Result<i32, String> {
+        if value.is_global() {
+            return Ok(self.globals[&value]);
+        }
+        if let Some(&value) = frame.values.get(&value) {
+            return Ok(value);
+        }
+        // constants out of the layout are read where they're used
+        match self.program.func(frame.func).dfg().value(value).kind() {
+            ValueKind::Integer(integer) => Ok(integer.value()),
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => Ok(0),
+            _ => Err(format!("use of a value before its definition in {}", self.program.func(frame.func).name())),
+        }
+    }
+
+    fn ty(&self, frame: &Frame, value: Value) -> Type {
+        if value.is_global() {
+            self.program.borrow_value(value).ty().clone()
+        }
+        else {
+            self.program.func(frame.func).dfg().value(value).ty().clone()
+        }
+    }
+
+    /// Address of the element `index` of `src`, elements being `stride` words.
+    fn offset(&self, frame: &Frame, src: Value, index: Value, stride: usize) -> Result<i32, String> {
+        let src = self.get(frame, src)?;
+        let index = self.get(frame, index)?;
+        Ok(src.wrapping_add(index.wrapping_mul(stride as i32)))
+    }
+
+    fn load(&self, address: i32) -> Result<i32, String> {
+        match usize::try_from(address) {
+            Ok(index) if index > 0 && index < self.memory.len() => Ok(self.memory[index]),
+            _ => Err(format!("invalid memory access at word {}", address)),
+        }
+    }
+
+    fn store(&mut self, address: i32, value: i32) -> Result<(), String> {
+        match usize::try_from(address) {
+            Ok(index) if index > 0 && index < self.memory.len() => {
+                self.memory[index] = value;
+                Ok(())
+            }
+            _ => Err(format!("invalid memory access at word {}", address)),
+        }
+    }
+
+    fn call_runtime<R: BufRead, W: Write>(&mut self, name: &str, args: &[i32], runtime: &mut Runtime<R, W>) -> Result<i32, String> {
+        let io = |error: std::io::Error| error.to_string();
+        match name {
+            "@getint" => runtime.getint().map_err(io),
+            "@getch" => runtime.getch().map_err(io),
+            "@getarray" => {
+                let values = runtime.getarray().map_err(io)?;
+                for (i, &value) in values.iter().enumerate() {
+                    self.store(args[0].wrapping_add(i as i32), value)?;
+                }
+                Ok(values.len() as i32)
+            }
+            "@putint" => runtime.putint(args[0]).map(|_| 0).map_err(io),
+            "@putch" => runtime.putch(args[0]).map(|_| 0).map_err(io),
+            "@putarray" => {
+                let values = (0..args[0].max(0)).map(|i| self.load(args[1].wrapping_add(i))).collect::<Result<Vec<_>, _>>()?;
+                runtime.putarray(&values).map(|_| 0).map_err(io)
+            }
+            "@starttime" => {
+                runtime.starttime();
+                Ok(0)
+            }
+            "@stoptime" => runtime.stoptime().map(|_| 0).map_err(io),
+            _ => Err(format!("call to {}, which has no body", name)),
+        }
+    }
+}
+
+fn binary_op(op: BinaryOp, lhs: i32, rhs: i32) -> Result<i32, String> {
+    let value = match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div | BinaryOp::Mod if rhs == 0 => return Err("division by zero".to_string()),
+        BinaryOp::Div => lhs.wrapping_div(rhs),
+        BinaryOp::Mod => lhs.wrapping_rem(rhs),
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => (lhs as u32).wrapping_shr(rhs as u32) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    };
+    Ok(value)
+}
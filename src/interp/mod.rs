@@ -0,0 +1,59 @@
+//! # Interp
+//!
+//! In this module, we execute programs before they become assembly, with the SysY runtime library
+//! reading stdin and writing stdout like the emulator does. Koopa IR in memory is interpreted,
+//! so bugs before the backend can be told apart from bugs in it. The AST is interpreted too,
+//! which tells what the program means without the compiler at all.
+//!
+
+mod ast;
+mod ir;
+
+use crate::ast::CompUnit;
+use crate::emu::runtime::Runtime;
+use crate::interp::ir::Interpreter;
+use koopa::ir::Program;
+use std::io::{ stdin, stdout, BufWriter, Write };
+use std::thread;
+
+/// Interpret the program, return its exit code, or the error stopping it.
+/// `steps` limits the number of instructions executed, and the number executed is reported with `stats`.
+pub fn run_ir(program: &Program, steps: Option<u64>, stats: bool) -> Result<i32, String> {
+    let mut interpreter = Interpreter::new(program);
+    interpreter.set_step_limit(steps);
+
+    let mut runtime = Runtime::new(stdin().lock(), BufWriter::new(stdout().lock()));
+    let result = interpreter.run(&mut runtime);
+    runtime.output_mut().flush().map_err(|error| error.to_string())?;
+    if stats {
+        eprintln!("steps: {}", interpreter.steps());
+    }
+    if let Some(total) = runtime.total() {
+        eprintln!("{}", total);
+    }
+    result.map(|code| code & 0xff)
+}
+
+/// Interpret the checked AST, return the exit code of the program, or the error stopping it.
+/// `steps` limits the number of statements executed, and the number executed is reported with `stats`.
+pub fn run_ast(ast: &CompUnit, steps: Option<u64>, stats: bool) -> Result<i32, String> {
+    // the interpreter recurses with the program, so it runs on a thread with a large stack
+    thread::scope(|scope| {
+        let thread = thread::Builder::new().stack_size(ast::STACK_SIZE).spawn_scoped(scope, || {
+            let runtime = Runtime::new(stdin().lock(), BufWriter::new(stdout().lock()));
+            let mut interpreter = ast::Interpreter::new(runtime);
+            interpreter.set_step_limit(steps);
+
+            let result = interpreter.run(ast);
+            interpreter.runtime_mut().output_mut().flush().map_err(|error| error.to_string())?;
+            if stats {
+                eprintln!("steps: {}", interpreter.steps());
+            }
+            if let Some(total) = interpreter.runtime_mut().total() {
+                eprintln!("{}", total);
+            }
+            result.map(|code| code & 0xff)
+        }).map_err(|error| error.to_string())?;
+        thread.join().map_err(|_| "the interpreter panicked".to_string())?
+    })
+}
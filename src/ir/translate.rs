@@ -8,8 +8,7 @@
 use crate::ir::scope::{ Scope, Entry, value };
 use crate::ir::writer::Writer;
 use crate::tools::TurnInto;
-use std::collections::HashSet;
-use koopa::ir::{ Program, FunctionData, BasicBlock, ValueKind, BinaryOp, TypeKind };
+use koopa::ir::{ Program, FunctionData, Value, ValueKind, BinaryOp, TypeKind };
 use koopa::ir::entities::ValueData;
 use koopa::ir::values::{ Return, Binary, Alloc, Load, Store, Branch, Jump, Call, GetElemPtr, GetPtr, GlobalAlloc };
 
@@ -51,45 +50,22 @@ impl Translate for FunctionData {
 
         w.func_bg(self.name(), self.ty(), params_name);
 
-        // if a branch is removed by return, its then and else block will be illegal
-        let mut end = false;
-        let mut illegal_bbs: HashSet<BasicBlock> = HashSet::new();
-
-        for (bb, node) in self.layout().bbs() {
-            // If this block is illegal, go to the next
-            if illegal_bbs.contains(bb) {
-                continue;
+        // parameters of blocks may be used before their blocks are written
+        for (bb, _) in self.layout().bbs() {
+            for param in self.dfg().bb(*bb).params() {
+                let register = scope.new_register();
+                scope.new_value(*param, register);
             }
+        }
 
-            let name = self.dfg().bb(bb.clone()).name().as_ref().unwrap();
-            w.bb_bg(name);
-            end = false;
+        for (bb, node) in self.layout().bbs() {
+            let data = self.dfg().bb(*bb);
+            let params = data.params().iter().map(|p| (scope.value(p).turn_into(()), self.dfg().value(*p).ty().clone())).collect();
+            w.bb_bg(data.name().as_ref().unwrap(), params);
 
             for (value, _) in node.insts() {
-                if end {
-                    // all the values after end should not be translated
-                    match value!(program, scope, value.clone()).kind() {
-                        ValueKind::Branch(b) => {
-                            illegal_bbs.insert(b.true_bb());
-                            illegal_bbs.insert(b.false_bb());
-                        },
-                        _ => {}
-                    }
-                    continue;
-                }
-
                 scope.set_cur_value(Some(value.clone()));
                 self.dfg().value(value.clone()).translate(program, scope, w);
-                match value!(program, scope, value.clone()).kind() {
-                    ValueKind::Return(_) => end = true,
-                    ValueKind::Jump(_) => end = true,
-                    ValueKind::Branch(_) => end = true,
-                    _ => {}
-                }
-            }
-
-            if end == false {
-                w.ret("");
             }
         }
 
@@ -220,7 +196,9 @@ impl Translate for Branch {
         };
         let then = program.func(scope.cur_func().clone()).dfg().bb(self.true_bb()).name().as_ref().unwrap();
         let els = program.func(scope.cur_func().clone()).dfg().bb(self.false_bb()).name().as_ref().unwrap();
-        w.branch(&cond, then, els);
+        let then = w.target(then, args(program, scope, self.true_args()));
+        let els = w.target(els, args(program, scope, self.false_args()));
+        w.branch(&cond, &then, &els);
     }
 }
 
@@ -229,10 +207,20 @@ impl Translate for Jump {
         // w.line();
         // w.note("// jump");
         let dst = program.func(scope.cur_func().clone()).dfg().bb(self.target()).name().as_ref().unwrap();
-        w.jump(dst);
+        w.jump(&w.target(dst, args(program, scope, self.args())));
     }
 }
 
+/// Arguments passed to parameters of a block.
+fn args(program: &Program, scope: &Scope, args: &[Value]) -> Vec<String> {
+    args.iter().map(|arg| {
+        match value!(program, scope, *arg).kind() {
+            ValueKind::Integer(i) => i.value().to_string(),
+            _ => scope.value(arg).turn_into(())
+        }
+    }).collect()
+}
+
 impl Translate for Call {
     fn translate(&self, program: &Program, scope: &mut Scope, w: &mut Writer) {
         // w.line();
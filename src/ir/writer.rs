@@ -81,8 +81,24 @@ impl<'f> Writer<'f> {
         writeln!(self.f, "}}").unwrap();
     }
 
-    pub fn bb_bg(&mut self, name: &str) {
-        writeln!(self.f, "{}:", name).unwrap();
+    pub fn bb_bg(&mut self, name: &str, params: Vec<(String, Type)>) {
+        if params.is_empty() {
+            writeln!(self.f, "{}:", name).unwrap();
+        }
+        else {
+            let params: Vec<String> = params.iter().map(|(name, ty)| format!("{}: {}", name, self.ty(ty))).collect();
+            writeln!(self.f, "{}({}):", name, params.join(", ")).unwrap();
+        }
+    }
+
+    /// Target of a jump or branch, with arguments passed to its parameters.
+    pub fn target(&self, name: &str, args: Vec<String>) -> String {
+        if args.is_empty() {
+            name.to_string()
+        }
+        else {
+            format!("{}({})", name, args.join(", "))
+        }
     }
 
     pub fn op3(&mut self, op: &str, dst: &str, lhs: &str, rhs: &str) {
@@ -1,44 +1,208 @@
 mod ast;
+mod diagnostic;
+mod check;
 mod tools;
 mod mem;
+mod opt;
 mod ir;
+mod analysis;
 mod asm;
+mod emu;
+mod interp;
+mod fuzz;
 
 use lalrpop_util::lalrpop_mod;
+use check::check;
 use mem::generate_mem;
+use opt::{ Config, PassManager, pipeline };
 use ir::generate_ir;
-use asm::generate_asm;
+use asm::{ generate_asm, write_asm };
+use asm::alloc::Allocator;
+use diagnostic::{ Diagnostic, LineIndex, from_parse_error };
 use std::env::args;
 use std::fs::read_to_string;
 use std::io::Result;
-use std::time::SystemTime;
+use std::path::Path;
+use std::panic;
+use std::process::exit;
 
 lalrpop_mod!(sysy);
 
+/// Print the diagnostics against the source and stop compiling.
+fn report(diagnostics: Vec<Diagnostic>, path: &str, index: &LineIndex) -> ! {
+    for diagnostic in &diagnostics {
+        eprint!("{}", diagnostic.render(path, index));
+    }
+    exit(1);
+}
+
+/// The number given to an option, or stop compiling.
+fn number(arg: &str, option: &str) -> usize {
+    match arg[option.len()..].parse() {
+        Ok(number) => number,
+        Err(_) => {
+            eprintln!("error: `{}` expects a number", option.trim_end_matches('='));
+            exit(1);
+        }
+    }
+}
+
+/// Exit with the exit code of a program run, or report the error stopping it.
+fn finish(result: std::result::Result<i32, String>) -> ! {
+    match result {
+        Ok(code) => exit(code),
+        Err(message) => {
+            eprintln!("error: {}", message);
+            exit(1);
+        }
+    }
+}
+
 fn main() -> Result<()> {
     let mut args = args();
     args.next();
     let mode = args.next().unwrap();
     let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
+    // `run`, `-interp-koopa` and `-interp-ast` execute the program instead of writing it out
+    let output = if mode == "run" || mode.starts_with("-interp-") {
+        String::new()
+    }
+    else {
+        args.next();
+        args.next().unwrap()
+    };
+
+    // `-O` chooses the passes and the register allocator, `--passes` and `--regalloc` override them
+    let mut level = 1;
+    let mut passes: Option<Vec<String>> = None;
+    let mut regalloc = None;
+    let mut stats = false;
+    let mut steps = None;
+    let mut interesting = None;
+    // options for the compiler in every mode `reduce` tries
+    let mut forwarded = vec![];
+    let mut config = Config::default();
+    for arg in args {
+        if !arg.starts_with("--max-steps=") && !arg.starts_with("--interesting=") {
+            forwarded.push(arg.clone());
+        }
+        match arg.as_str() {
+            "-O0" => level = 0,
+            "-O1" => level = 1,
+            "-O2" => level = 2,
+            "--regalloc=linear" => regalloc = Some(Allocator::LinearScan),
+            "--regalloc=coloring" => regalloc = Some(Allocator::Coloring),
+            "--stats" => stats = true,
+            _ if arg.starts_with("--max-steps=") => steps = Some(number(&arg, "--max-steps=") as u64),
+            _ if arg.starts_with("--interesting=") => interesting = Some(arg["--interesting=".len()..].to_string()),
+            "--no-inline" => config.inline = false,
+            _ if arg.starts_with("--inline-size=") => config.inline_size = number(&arg, "--inline-size="),
+            _ if arg.starts_with("--inline-recursion=") => config.inline_recursion = number(&arg, "--inline-recursion="),
+            _ if arg.starts_with("--passes=") => {
+                let list = &arg["--passes=".len()..];
+                passes = Some(list.split(',').filter(|name| !name.is_empty()).map(|name| name.to_string()).collect());
+            }
+            _ => {
+                eprintln!("error: unknown option `{}`", arg);
+                exit(1);
+            }
+        }
+    }
+    let allocator = regalloc.unwrap_or(if level >= 2 { Allocator::Coloring } else { Allocator::LinearScan });
+    let names: Vec<&str> = match &passes {
+        Some(passes) => passes.iter().map(|name| name.as_str()).collect(),
+        None => pipeline(level).to_vec(),
+    };
+    let mut manager = match PassManager::new(&names, &config) {
+        Ok(manager) => manager,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            exit(1);
+        }
+    };
 
-    let input = read_to_string(input)?;
-    let ast = sysy::CompUnitParser::new().parse(&input).unwrap();
+    // `gen` writes the program of a seed instead of reading one
+    if mode == "gen" {
+        let seed = match input.parse() {
+            Ok(seed) => seed,
+            Err(_) => {
+                eprintln!("error: `gen` expects a number as the seed");
+                exit(1);
+            }
+        };
+        std::fs::write(&output, fuzz::generate(seed))?;
+        return Ok(());
+    }
+
+    // errors in the source are reported as diagnostics, so a panic is always a bug of the compiler
+    panic::set_hook(Box::new(|info| {
+        eprintln!("internal compiler error: {}", info);
+    }));
+
+    let path = input;
+    let input = read_to_string(&path)?;
+    // the output of `-koopa` and `-riscv` is executed as it's written, instead of compiling SysY again
+    let extension = Path::new(&path).extension().and_then(|extension| extension.to_str());
+    if mode == "-interp-koopa" && extension == Some("koopa") {
+        // the parser of the `koopa` crate prints the errors it finds by itself
+        let program = koopa::front::Driver::from(input.as_str()).generate_program()
+            .map_err(|_| format!("invalid Koopa IR in {}", path));
+        finish(program.and_then(|program| interp::run_ir(&program, steps, stats)));
+    }
+    if mode == "run" && matches!(extension, Some("S" | "s")) {
+        finish(emu::run(&input, steps, stats));
+    }
+    let index = LineIndex::new(&input);
+    // recovered syntax errors are collected, and reported together with the one that stops the parser
+    let mut errors = vec![];
+    let ast = sysy::CompUnitParser::new().parse(&mut errors, &input);
+    let mut diagnostics: Vec<_> = errors.into_iter().map(|error| from_parse_error(error.error)).collect();
+    let ast = match ast {
+        Ok(ast) if diagnostics.is_empty() => ast,
+        Ok(_) => report(diagnostics, &path, &index),
+        Err(error) => {
+            diagnostics.push(from_parse_error(error));
+            report(diagnostics, &path, &index)
+        }
+    };
+
+    let diagnostics = check(&ast);
+    if !diagnostics.is_empty() {
+        report(diagnostics, &path, &index);
+    }
 
-    println!("start mem: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
-    let (program, mut info) = generate_mem(&ast);
-    println!("end mem: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
+    if mode == "reduce" {
+        finish(fuzz::reduce(&path, &output, interesting.as_deref(), steps, forwarded).map(|_| 0));
+    }
+
+    if mode == "-interp-ast" {
+        finish(interp::run_ast(&ast, steps, stats));
+    }
+
+    let (mut program, mut info) = match generate_mem(&ast) {
+        Ok(result) => result,
+        Err(diagnostic) => report(vec![diagnostic], &path, &index),
+    };
+    manager.run(&mut program);
+    if stats {
+        for line in manager.report() {
+            eprintln!("{}", line);
+        }
+    }
 
     if mode == "-koopa" {
-        println!("start ir: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
         generate_ir(&program, &output, &info);
-        println!("end ir: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
+    }
+    else if mode == "-interp-koopa" {
+        finish(interp::run_ir(&program, steps, stats));
+    }
+    else if mode == "run" {
+        let mut source = vec![];
+        write_asm(&program, &mut info, &mut source, allocator);
+        finish(emu::run(&String::from_utf8(source).unwrap(), steps, stats));
     }
     else {
-        println!("start asm: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
-        generate_asm(&program, &mut info, &output);
-        println!("end asm: {}", SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs());
+        generate_asm(&program, &mut info, &output, allocator);
     }
 
     Ok(())
@@ -9,17 +9,24 @@ use crate::tools::{ TurnInto, global_const_array_init, local_const_array_init, g
 use crate::mem::scope::{ Scope, Entry, new_value, push_value, new_bb, push_bb };
 use crate::mem::eval::Eval;
 use crate::mem::info::Info;
+use crate::diagnostic::Diagnostic;
 use koopa::ir::builder_traits::*;
-use koopa::ir::{ Program, FunctionData, Value, BinaryOp, Type, TypeKind };
+use koopa::ir::{ Program, FunctionData, Value, BasicBlock, BinaryOp, Type, TypeKind };
 
 pub trait Create<'ast> {
     type Out;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out;
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic>;
+}
+
+/// Conditions of `if` and `while` jump to one of two blocks instead of giving a value,
+/// so `&&` and `||` become chains of branches.
+pub trait Cond<'ast> {
+    fn cond(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info, true_bb: BasicBlock, false_bb: BasicBlock) -> Result<(), Diagnostic>;
 }
 
 impl<'ast> Create<'ast> for CompUnit {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let mut decl = |id: &'ast str, params_ty: Vec<Type>, ret_ty: Type| {
             let func = FunctionData::new_decl(format!("@{}", id), params_ty, ret_ty);
             scope.new_func(id, program.new_func(func));
@@ -34,24 +41,26 @@ impl<'ast> Create<'ast> for CompUnit {
         decl("stoptime", vec![], Type::get_unit());
 
         for item in &self.items {
-            item.create(program, scope, info);
+            item.create(program, scope, info)?;
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for CompItem {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
             Self::Func(func) => func.create(program, scope, info),
             Self::Decl(decl) => decl.create(program, scope, info),
+            Self::Error { span } => Err(Diagnostic::error("can't create IR for code with syntax errors").with_primary(*span)),
         }
     }
 }
 
 impl<'ast> Create<'ast> for Decl {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
             Self::Const(const_decl) => const_decl.create(program, scope, info),
             Self::Var(var_decl) => var_decl.create(program, scope, info),
@@ -61,53 +70,53 @@ impl<'ast> Create<'ast> for Decl {
 
 impl<'ast> Create<'ast> for ConstDecl {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         for def in &self.defs {
-            def.create(program, scope, info);
+            def.create(program, scope, info)?;
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for ConstDef {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         if self.dims.is_empty() {
             // single variable
             let num = match &self.init {
-                ConstInitVal::Exp(exp) => exp.create(program, scope, info),
-                ConstInitVal::List(_) => panic!("can't initialize a single variable with a list"),
+                ConstInitVal::Exp(exp) => exp.create(program, scope, info)?,
+                ConstInitVal::List { span, .. } => return Err(Diagnostic::error("can't initialize a single variable with a list").with_primary(*span)),
             };
             scope.new_value(&self.id, Entry::Const(num));
         } 
         else {
             // array
-            let array_info: Vec<_> = self.dims.iter().map(|dim| dim.create(program, scope, info) as usize).collect();
+            let array_info = self.dims.iter().map(|dim| dim.create(program, scope, info).map(|dim| dim as usize)).collect::<Result<Vec<_>, _>>()?;
             scope.set_array_info(array_info.clone());
-            let nums = self.init.create(program, scope, info);
+            let nums = self.init.create(program, scope, info)?;
 
             if scope.is_global() {
                 let init = global_const_array_init(program, nums, array_info);
                 let global_alloc = program.new_value().global_alloc(init);
-                info.new_info(global_alloc.clone());
                 scope.new_value(&self.id, Entry::Value(global_alloc));
             }
             else {
                 let alloc = new_value!(program, scope).alloc(array_info.turn_into(()));
                 push_value!(program, scope, alloc.clone());
-                info.new_info(alloc.clone());
 
-                local_const_array_init(program, scope, info, nums, array_info, alloc.clone());
+                local_const_array_init(program, scope, info, nums, array_info, alloc.clone())?;
                 scope.new_value(&self.id, Entry::Value(alloc));
             }
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for ConstInitVal {
     type Out = Vec<i32>;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         if scope.array_info().is_empty() {
-            return vec![];
+            return Ok(vec![]);
         }
 
         let mut result = vec![];
@@ -117,15 +126,15 @@ impl<'ast> Create<'ast> for ConstInitVal {
         let mut count = 0;
 
         match self {
-            Self::Exp(_) => panic!("can't initialize an array with a single expression"),
-            Self::List(list) => {
+            Self::Exp(exp) => Err(Diagnostic::error("can't initialize an array with a single expression").with_primary(exp.span())),
+            Self::List { list, .. } => {
                 for elem in list.iter() {
                     match elem {
                         Self::Exp(exp) => {
-                            result.push(exp.create(program, scope, info));
+                            result.push(exp.create(program, scope, info)?);
                             count += 1;
                         }
-                        Self::List(_) => {
+                        Self::List { .. } => {
                             if count % base != 0 {
                                 let remain = base - count % base;
                                 result.append(&mut vec![0; remain]);
@@ -144,7 +153,7 @@ impl<'ast> Create<'ast> for ConstInitVal {
                             }
 
                             scope.set_array_info(cur_array_info[begin..].to_vec());
-                            result.append(&mut elem.create(program, scope, info));
+                            result.append(&mut elem.create(program, scope, info)?);
                             count += len;
                         }
                     }
@@ -152,7 +161,7 @@ impl<'ast> Create<'ast> for ConstInitVal {
 
                 result.append(&mut vec![0; cur_array_info.iter().fold(1, |acc, num| acc * num) - count]);
 
-                result
+                Ok(result)
             }
         }
     }
@@ -160,55 +169,51 @@ impl<'ast> Create<'ast> for ConstInitVal {
 
 impl<'ast> Create<'ast> for VarDecl {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         for def in &self.defs {
-            def.create(program, scope, info);
+            def.create(program, scope, info)?;
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for VarDef {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         if self.dims.is_empty() {
             // single variable
             if scope.is_global() {
-                let init = self.init.as_ref().map_or(0, |val| {
-                    match val {
-                        InitVal::Exp(exp) => exp.evaluate(scope).unwrap(),
-                        InitVal::List(_) => panic!("can't initialize a single variable with a list"),
-                    }
-                });
+                let init = match &self.init {
+                    Some(InitVal::Exp(exp)) => match exp.evaluate(scope) {
+                        Some(num) => num,
+                        None => return Err(Diagnostic::error("initializer of a global variable must be a constant expression").with_primary(exp.span())),
+                    },
+                    Some(InitVal::List { span, .. }) => return Err(Diagnostic::error("can't initialize a single variable with a list").with_primary(*span)),
+                    None => 0,
+                };
                 let init = program.new_value().integer(init);
                 let global_alloc = program.new_value().global_alloc(init);
-                info.new_info(global_alloc.clone());
                 scope.new_value(&self.id, Entry::Value(global_alloc));
             }
             else {
                 match &self.init {
                     Some(init) => {
                         let value = match init {
-                            InitVal::Exp(exp) => exp.create(program, scope, info),
-                            InitVal::List(_) => panic!("can't initialize a single variable with a list"),
+                            InitVal::Exp(exp) => exp.create(program, scope, info)?,
+                            InitVal::List { span, .. } => return Err(Diagnostic::error("can't initialize a single variable with a list").with_primary(*span)),
                         };
 
                         let alloc = new_value!(program, scope).alloc(Type::get_i32());
                         push_value!(program, scope, alloc.clone());
-                        info.new_info(alloc.clone());
 
                         let store = new_value!(program, scope).store(value.clone(), alloc.clone());
                         push_value!(program, scope, store.clone());
-                        info.new_info(store.clone());
-
-                        info.info_mut(value).unwrap().death = info.counter();
-                        info.info_mut(alloc).unwrap().death = info.counter();
 
                         scope.new_value(&self.id, Entry::Value(alloc));
                     }
                     None => {
                         let alloc = new_value!(program, scope).alloc(Type::get_i32());
                         push_value!(program, scope, alloc.clone());
-                        info.new_info(alloc.clone());
 
                         scope.new_value(&self.id, Entry::Value(alloc));
                     }
@@ -217,19 +222,19 @@ impl<'ast> Create<'ast> for VarDef {
         }
         else {
             // array
-            let array_info: Vec<_> = self.dims.iter().map(|dim| dim.create(program, scope, info) as usize).collect();
+            let array_info = self.dims.iter().map(|dim| dim.create(program, scope, info).map(|dim| dim as usize)).collect::<Result<Vec<_>, _>>()?;
             scope.set_array_info(array_info.clone());
 
             if scope.is_global() {
                 let init = match &self.init {
                     Some(val) => {
                         let mut empty_init = false;
-                        if let InitVal::List(list) = val {
+                        if let InitVal::List { list, .. } = val {
                             if list.is_empty() {
                                 empty_init = true;
                             }
                         }
-                        let init = val.create(program, scope, info);
+                        let init = val.create(program, scope, info)?;
                         let value = global_array_init(program, init, array_info.clone());
                         if empty_init {
                             info.new_array_info(value.clone(), array_info.clone());
@@ -245,35 +250,34 @@ impl<'ast> Create<'ast> for VarDef {
                     }
                 };
                 let global_alloc = program.new_value().global_alloc(init);
-                info.new_info(global_alloc.clone());
                 scope.new_value(&self.id, Entry::Value(global_alloc));
             }
             else {
                 let zero = new_value!(program, scope).integer(0);
                 push_value!(program, scope, zero.clone());
-                info.new_info(zero.clone());
 
-                let values = self.init.as_ref().map_or(vec![zero; array_info.iter().fold(1, |acc, num| acc * num)], |val| {
-                    val.create(program, scope, info)
-                });
+                let values = match &self.init {
+                    Some(val) => val.create(program, scope, info)?,
+                    None => vec![zero; array_info.iter().fold(1, |acc, num| acc * num)],
+                };
 
                 let alloc = new_value!(program, scope).alloc(array_info.turn_into(()));
                 push_value!(program, scope, alloc.clone());
-                info.new_info(alloc.clone());
 
-                local_array_init(program, scope, info, values, array_info, alloc.clone());
+                local_array_init(program, scope, info, values, array_info, alloc.clone())?;
 
                 scope.new_value(&self.id, Entry::Value(alloc));
             }
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for InitVal {
     type Out = Vec<Value>;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         if scope.array_info().is_empty() {
-            return vec![];
+            return Ok(vec![]);
         }
 
         let mut result = vec![];
@@ -288,25 +292,28 @@ impl<'ast> Create<'ast> for InitVal {
         else {
             let zero = new_value!(program, scope).integer(0);
             push_value!(program, scope, zero.clone());
-            info.new_info(zero.clone());
             zero
         };
 
         match self {
-            Self::Exp(_) => panic!("can't initialize an array with a single expression"),
-            Self::List(list) => {
+            Self::Exp(exp) => Err(Diagnostic::error("can't initialize an array with a single expression").with_primary(exp.span())),
+            Self::List { list, .. } => {
                 for elem in list.iter() {
                     match elem {
                         Self::Exp(exp) => {
                             if scope.is_global() {
-                                result.push(program.new_value().integer(exp.evaluate(scope).unwrap()));
+                                let num = match exp.evaluate(scope) {
+                                    Some(num) => num,
+                                    None => return Err(Diagnostic::error("initializer of a global array must be a constant expression").with_primary(exp.span())),
+                                };
+                                result.push(program.new_value().integer(num));
                             }
                             else {
-                                result.push(exp.create(program, scope, info));
+                                result.push(exp.create(program, scope, info)?);
                             }
                             count += 1;
                         }
-                        Self::List(_) => {
+                        Self::List { .. } => {
                             if count % base != 0 {
                                 let remain = base - count % base;
                                 result.append(&mut vec![zero.clone(); remain]);
@@ -325,7 +332,7 @@ impl<'ast> Create<'ast> for InitVal {
                             }
 
                             scope.set_array_info(cur_array_info[begin..].to_vec());
-                            result.append(&mut elem.create(program, scope, info));
+                            result.append(&mut elem.create(program, scope, info)?);
                             count += len;
                         }
                     }
@@ -333,7 +340,7 @@ impl<'ast> Create<'ast> for InitVal {
 
                 result.append(&mut vec![zero.clone(); cur_array_info.iter().fold(1, |acc, num| acc * num) - count]);
 
-                result
+                Ok(result)
             }
         }
     }
@@ -341,10 +348,10 @@ impl<'ast> Create<'ast> for InitVal {
 
 impl<'ast> Create<'ast> for FuncDef {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let name = format!("@{}", self.id);
-        let params_ty: Vec<_> = self.params.iter().map(|param| param.create(program, scope, info)).collect();
-        let ret_ty = self.ty.create(program, scope, info);
+        let params_ty = self.params.iter().map(|param| param.create(program, scope, info)).collect::<Result<Vec<_>, _>>()?;
+        let ret_ty = self.ty.create(program, scope, info)?;
 
         let data = FunctionData::new(name, params_ty.clone(), ret_ty);
         let func = program.new_func(data);
@@ -362,115 +369,120 @@ impl<'ast> Create<'ast> for FuncDef {
         for param in params {
             let alloc = new_value!(program, scope).alloc(params_ty[count].clone());
             push_value!(program, scope, alloc.clone());
-            info.new_info(alloc.clone());
 
             let store = new_value!(program, scope).store(param.clone(), alloc.clone());
             push_value!(program, scope, store.clone());
-            info.new_info(store.clone());
-
-            // info.info_mut(param.clone()).unwrap().death = info.counter();
-            info.info_mut(alloc).unwrap().death = info.counter();
 
             scope.new_value(&self.params[count].id, Entry::Value(alloc));
             count += 1;
         }
 
-        self.body.create(program, scope, info);
+        self.body.create(program, scope, info)?;
 
         scope.exit();
         scope.set_cur_bb(None);
         scope.set_cur_func(None);
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for FuncParam {
     type Out = Type;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        self.dims.as_ref().map_or(Type::get_i32(), |array_info| {
-            let array_info: Vec<_> = array_info.iter().map(|dim| dim.create(program, scope, info) as usize).collect();
-            Type::get_pointer(array_info.turn_into(()))
-        })
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
+        match &self.dims {
+            Some(array_info) => {
+                let array_info = array_info.iter().map(|dim| dim.create(program, scope, info).map(|dim| dim as usize)).collect::<Result<Vec<_>, _>>()?;
+                Ok(Type::get_pointer(array_info.turn_into(())))
+            }
+            None => Ok(Type::get_i32()),
+        }
     }
 }
 
 impl<'ast> Create<'ast> for FuncType {
     type Out = Type;
-    fn create(&'ast self, _: &mut Program, _: &mut Scope<'ast>, _: &mut Info) -> Self::Out {
+    fn create(&'ast self, _: &mut Program, _: &mut Scope<'ast>, _: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
-            Self::Void => Type::get_unit(),
-            Self::Int => Type::get_i32(),
+            Self::Void => Ok(Type::get_unit()),
+            Self::Int => Ok(Type::get_i32()),
         }
     }
 }
 
 impl<'ast> Create<'ast> for Block {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         scope.enter();
         for item in &self.items {
-            item.create(program, scope, info);
+            item.create(program, scope, info)?;
         }
         scope.exit();
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for BlockItem {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
             Self::Decl(decl) => decl.create(program, scope, info),
             Self::Stmt(stmt) => stmt.create(program, scope, info),
+            Self::Error { span } => Err(Diagnostic::error("can't create IR for code with syntax errors").with_primary(*span)),
         }
     }
 }
 
 impl<'ast> Create<'ast> for Stmt {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
             Self::Return(ret) => ret.create(program, scope, info),
             Self::Assign(asg) => asg.create(program, scope, info),
-            Self::Exp(exp) => if let Some(exp) = exp {
-                exp.create(program, scope, info);
-            },
+            Self::Exp { exp, .. } => {
+                if let Some(exp) = exp {
+                    exp.create(program, scope, info)?;
+                }
+                Ok(())
+            }
             Self::Block(blk) => blk.create(program, scope, info),
             Self::If(f) => f.create(program, scope, info),
             Self::While(whl) => whl.create(program, scope, info),
             Self::Break(brk) => brk.create(program, scope, info),
             Self::Continue(ctn) => ctn.create(program, scope, info),
+            Self::Error { span } => Err(Diagnostic::error("can't create IR for code with syntax errors").with_primary(*span)),
         }
     }
 }
 
 impl<'ast> Create<'ast> for Return {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        let value = self.exp.as_ref().map_or(None, |exp| Some(exp.create(program, scope, info)));
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
+        let value = match &self.exp {
+            Some(exp) => Some(exp.create(program, scope, info)?),
+            None => None,
+        };
 
         let ret = new_value!(program, scope).ret(value);
         push_value!(program, scope, ret.clone());
-        info.new_info(ret.clone());
-
-        if let Some(value) = value {
-            info.info_mut(value).unwrap().death = info.counter();
-        }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for Assign {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let mut dest = match scope.value(&self.lval.id) {
-            Entry::Value(value) => value.clone(),
-            Entry::Const(_) => panic!("have solved const"),
+            Some(Entry::Value(value)) => *value,
+            Some(Entry::Const(_)) => return Err(Diagnostic::error(format!("can't assign to constant `{}`", self.lval.id)).with_primary(self.lval.span)),
+            None => return Err(Diagnostic::error(format!("use of undeclared identifier `{}`", self.lval.id)).with_primary(self.lval.span)),
         };
-        let value = self.exp.create(program, scope, info);
+        let value = self.exp.create(program, scope, info)?;
         
         // 0 presents undef
         // 1 presents a single variable
         // 2 presents an element in an array
-        // 3 presnts an array or a slice
-        // 4 presents a pointer(solved as case 3)
+        // 3 presnts an array or a slice, which can't be assigned
+        // 4 presents an element through a pointer
         let mut case = 0;
         if dest.is_global() {
             match program.borrow_value(dest.clone()).ty().kind() {
@@ -485,15 +497,22 @@ impl<'ast> Create<'ast> for Assign {
                                 case = 2
                             }
                         }
-                        TypeKind::Pointer(_) => case = 4,
-                        _ => panic!("can't assign to a unit or function")
+                        TypeKind::Pointer(ty) => {
+                            if self.lval.dims.len() != ty.turn_into(()) + 1 {
+                                case = 3;
+                            }
+                            else {
+                                case = 4;
+                            }
+                        }
+                        _ => return Err(Diagnostic::error("can't assign to a unit or function").with_primary(self.lval.span)),
                     }
                 }
-                _ => panic!("can't assign to a value not created by alloc")
+                _ => return Err(Diagnostic::error("can't assign to a value not created by alloc").with_primary(self.lval.span)),
             }
         }
         else {
-            match program.func(scope.cur_func().clone()).dfg().value(dest.clone()).ty().kind() {
+            match program.func(scope.cur_func()?.clone()).dfg().value(dest.clone()).ty().kind() {
                 TypeKind::Pointer(base) => {
                     match base.kind() {
                         TypeKind::Int32 => case = 1,
@@ -505,54 +524,50 @@ impl<'ast> Create<'ast> for Assign {
                                 case = 2
                             }
                         }
-                        TypeKind::Pointer(_) => case = 4,
-                        _ => panic!("can't assign to a unit or function")
+                        TypeKind::Pointer(ty) => {
+                            if self.lval.dims.len() != ty.turn_into(()) + 1 {
+                                case = 3;
+                            }
+                            else {
+                                case = 4;
+                            }
+                        }
+                        _ => return Err(Diagnostic::error("can't assign to a unit or function").with_primary(self.lval.span)),
                     }
                 }
-                _ => panic!("can't assign to a value not created by alloc")
+                _ => return Err(Diagnostic::error("can't assign to a value not created by alloc").with_primary(self.lval.span)),
             }
         }
 
+        if case == 3 {
+            return Err(Diagnostic::error(format!("`{}` with {} subscript(s) is not an int and can't be assigned", self.lval.id, self.lval.dims.len())).with_primary(self.lval.span));
+        }
+
         if case == 1 {
             let store = new_value!(program, scope).store(value.clone(), dest.clone());
             push_value!(program, scope, store.clone());
-            info.new_info(store.clone());
-
-            info.info_mut(value).unwrap().death = info.counter();
-            info.info_mut(dest).unwrap().death = info.counter();
         }
         else if case == 2 {
             for dim in &self.lval.dims {
-                let index = dim.create(program, scope, info);
+                let index = dim.create(program, scope, info)?;
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(dest.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(dest.clone()).unwrap().death = info.counter();
 
                 dest = get_elem_ptr;
             }
 
             let store = new_value!(program, scope).store(value.clone(), dest.clone());
             push_value!(program, scope, store.clone());
-            info.new_info(store.clone());
-
-            info.info_mut(value).unwrap().death = info.counter();
-            info.info_mut(dest).unwrap().death = info.counter();
         }
         else {
             let load = new_value!(program, scope).load(dest.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
-
-            info.info_mut(dest.clone()).unwrap().death = info.counter();
 
             dest = load;
             let mut first = true;
 
             for dim in &self.lval.dims {
-                let index = dim.create(program, scope, info);
+                let index = dim.create(program, scope, info)?;
                 let value = if first {
                     first = false;
                     new_value!(program, scope).get_ptr(dest.clone(), index.clone())
@@ -561,72 +576,59 @@ impl<'ast> Create<'ast> for Assign {
                     new_value!(program, scope).get_elem_ptr(dest.clone(), index.clone())
                 };
                 push_value!(program, scope, value.clone());
-                info.new_info(value.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(dest.clone()).unwrap().death = info.counter();
 
                 dest = value;
             }
 
             let store = new_value!(program, scope).store(value.clone(), dest.clone());
             push_value!(program, scope, store.clone());
-            info.new_info(store.clone());
-
-            info.info_mut(value).unwrap().death = info.counter();
-            info.info_mut(dest).unwrap().death = info.counter();
         }
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for If {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        let cond = self.cond.create(program, scope, info);
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let (then_bb, else_bb, end_bb) = scope.label_mut().if_label();
 
         let then_bb = new_bb!(program, scope).basic_block(Some(then_bb));
         let else_bb = new_bb!(program, scope).basic_block(Some(else_bb));
         let end_bb = new_bb!(program, scope).basic_block(Some(end_bb));
 
-        let branch = new_value!(program, scope).branch(cond.clone(), then_bb.clone(), match &self.els {
-            Some(_) => else_bb.clone(),
-            None => end_bb.clone(),
-        });
-        push_value!(program, scope, branch.clone());
-        info.new_info(branch.clone());
-
-        info.info_mut(cond).unwrap().death = info.counter();
+        self.cond.cond(program, scope, info, then_bb, match &self.els {
+            Some(_) => else_bb,
+            None => end_bb,
+        })?;
 
         push_bb!(program, scope, then_bb.clone());
         scope.set_cur_bb(Some(then_bb.clone()));
-        self.then.create(program, scope, info);
+        self.then.create(program, scope, info)?;
 
         let jump = new_value!(program, scope).jump(end_bb.clone());
         push_value!(program, scope, jump.clone());
-        info.new_info(jump.clone());
 
         match &self.els {
             Some(els) => {
                 push_bb!(program, scope, else_bb.clone());
                 scope.set_cur_bb(Some(else_bb.clone()));
-                els.create(program, scope, info);
+                els.create(program, scope, info)?;
 
                 let jump = new_value!(program, scope).jump(end_bb.clone());
                 push_value!(program, scope, jump.clone());
-                info.new_info(jump.clone());
             }
             None => {}
         }
 
         push_bb!(program, scope, end_bb.clone());
         scope.set_cur_bb(Some(end_bb.clone()));
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for While {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let (entry_bb, body_bb, end_bb) = scope.label_mut().while_label();
 
         let entry_bb = new_bb!(program, scope).basic_block(Some(entry_bb));
@@ -635,72 +637,71 @@ impl<'ast> Create<'ast> for While {
 
         let jump = new_value!(program, scope).jump(entry_bb.clone());
         push_value!(program, scope, jump.clone());
-        info.new_info(jump.clone());
 
         push_bb!(program, scope, entry_bb.clone());
         scope.set_cur_bb(Some(entry_bb.clone()));
 
-        let cond = self.cond.create(program, scope, info);
-        let branch = new_value!(program, scope).branch(cond.clone(), body_bb.clone(), end_bb.clone());
-        push_value!(program, scope, branch.clone());
-        info.new_info(branch.clone());
-
-        info.info_mut(cond).unwrap().death = info.counter();
+        self.cond.cond(program, scope, info, body_bb, end_bb)?;
 
         push_bb!(program, scope, body_bb.clone());
         scope.set_cur_bb(Some(body_bb.clone()));
         scope.loop_info_mut().push((entry_bb.clone(), end_bb.clone()));
-        self.body.create(program, scope, info);
+        self.body.create(program, scope, info)?;
 
         let jump = new_value!(program, scope).jump(entry_bb.clone());
         push_value!(program, scope, jump.clone());
-        info.new_info(jump.clone());
 
         scope.loop_info_mut().pop();
         push_bb!(program, scope, end_bb.clone());
         scope.set_cur_bb(Some(end_bb.clone()));
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for Break {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        let (_, end_bb) = scope.loop_info().last().unwrap();
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, _: &mut Info) -> Result<Self::Out, Diagnostic> {
+        let (_, end_bb) = match scope.loop_info().last() {
+            Some(info) => *info,
+            None => return Err(Diagnostic::error("`break` outside of a loop").with_primary(self.span)),
+        };
         let jump = new_value!(program, scope).jump(end_bb.clone());
         push_value!(program, scope, jump.clone());
-        info.new_info(jump.clone()); 
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for Continue {
     type Out = ();
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        let (entry_bb, _) = scope.loop_info().last().unwrap();
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, _: &mut Info) -> Result<Self::Out, Diagnostic> {
+        let (entry_bb, _) = match scope.loop_info().last() {
+            Some(info) => *info,
+            None => return Err(Diagnostic::error("`continue` outside of a loop").with_primary(self.span)),
+        };
         let jump = new_value!(program, scope).jump(entry_bb.clone());
         push_value!(program, scope, jump.clone());
-        info.new_info(jump.clone()); 
+        Ok(())
     }
 }
 
 impl<'ast> Create<'ast> for Exp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         self.lor.create(program, scope, info)
     }
 }
 
 impl<'ast> Create<'ast> for LVal {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        if let Entry::Const(num) = scope.value(&self.id) {
-            let integer = new_value!(program, scope).integer(*num);
-            push_value!(program, scope, integer.clone());
-            info.new_info(integer.clone());
-            return integer;
-        }
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         let value = match scope.value(&self.id) {
-            Entry::Value(value) => value,
-            Entry::Const(_) => panic!("have solved const"),
+            Some(Entry::Const(num)) => {
+                let integer = new_value!(program, scope).integer(*num);
+                push_value!(program, scope, integer.clone());
+                return Ok(integer);
+            }
+            Some(Entry::Value(value)) => *value,
+            None => return Err(Diagnostic::error(format!("use of undeclared identifier `{}`", self.id)).with_primary(self.span)),
         };
         
         let mut case = 0;
@@ -725,14 +726,14 @@ impl<'ast> Create<'ast> for LVal {
                                 case = 4;
                             }
                         }
-                        _ => panic!("can't assign to a unit or function")
+                        _ => return Err(Diagnostic::error(format!("`{}` is not a variable", self.id)).with_primary(self.span)),
                     }
                 }
-                _ => panic!("can't assign to a value not created by alloc")
+                _ => return Err(Diagnostic::error(format!("`{}` is not a variable", self.id)).with_primary(self.span)),
             }
         }
         else {
-            match program.func(scope.cur_func().clone()).dfg().value(value.clone()).ty().kind() {
+            match program.func(scope.cur_func()?.clone()).dfg().value(value.clone()).ty().kind() {
                 TypeKind::Pointer(base) => {
                     match base.kind() {
                         TypeKind::Int32 => case = 1,
@@ -752,83 +753,61 @@ impl<'ast> Create<'ast> for LVal {
                                 case = 4;
                             }
                         }
-                        _ => panic!("can't assign to a unit or function")
+                        _ => return Err(Diagnostic::error(format!("`{}` is not a variable", self.id)).with_primary(self.span)),
                     }
                 }
-                _ => panic!("can't assign to a value not created by alloc")
+                _ => return Err(Diagnostic::error(format!("`{}` is not a variable", self.id)).with_primary(self.span)),
             }
         }
 
         if case == 1 {
             let load = new_value!(program, scope).load(value.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
 
-            info.info_mut(value.clone()).unwrap().death = info.counter();
-
-            load
+            Ok(load)
         }
         else if case == 2 {
             let mut src = value.clone();
             for exp in &self.dims {
-                let index = exp.create(program, scope, info);
+                let index = exp.create(program, scope, info)?;
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(src.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(src.clone()).unwrap().death = info.counter();
 
                 src = get_elem_ptr;
             }
 
             let load = new_value!(program, scope).load(src.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
-
-            info.info_mut(src.clone()).unwrap().death = info.counter();
 
-            load
+            Ok(load)
         } 
         else if case == 3 {
             let mut src = value.clone();
             for exp in &self.dims {
-                let index = exp.create(program, scope, info);
+                let index = exp.create(program, scope, info)?;
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(src.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(src.clone()).unwrap().death = info.counter();
 
                 src = get_elem_ptr;
             }
 
             let index = new_value!(program, scope).integer(0);
             push_value!(program, scope, index.clone());
-            info.new_info(index.clone());
 
             let get_elem_ptr = new_value!(program, scope).get_elem_ptr(src.clone(), index.clone());
             push_value!(program, scope, get_elem_ptr.clone());
-            info.new_info(get_elem_ptr.clone());
-
-            info.info_mut(index.clone()).unwrap().death = info.counter();
-            info.info_mut(src.clone()).unwrap().death = info.counter();
 
-            get_elem_ptr
+            Ok(get_elem_ptr)
         }
         else if case == 4 {
             let load = new_value!(program, scope).load(value.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
-
-            info.info_mut(value.clone()).unwrap().death = info.counter();
 
             let mut src = load;
             let mut first = true;
             
             for exp in &self.dims {
-                let index = exp.create(program, scope, info);
+                let index = exp.create(program, scope, info)?;
                 let value = if first {
                     first = false;
                     new_value!(program, scope).get_ptr(src.clone(), index.clone())
@@ -837,34 +816,24 @@ impl<'ast> Create<'ast> for LVal {
                     new_value!(program, scope).get_elem_ptr(src.clone(), index.clone())
                 };
                 push_value!(program, scope, value.clone());
-                info.new_info(value.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(src.clone()).unwrap().death = info.counter();
 
                 src = value;
             }
 
             let load = new_value!(program, scope).load(src.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
-
-            info.info_mut(src.clone()).unwrap().death = info.counter();
 
-            load
+            Ok(load)
         }
         else {
             let load = new_value!(program, scope).load(value.clone());
             push_value!(program, scope, load.clone());
-            info.new_info(load.clone());
-
-            info.info_mut(value.clone()).unwrap().death = info.counter();
 
             let mut src = load;
             let mut first = true;
             
             for exp in &self.dims {
-                let index = exp.create(program, scope, info);
+                let index = exp.create(program, scope, info)?;
                 let value = if first {
                     first = false;
                     new_value!(program, scope).get_ptr(src.clone(), index.clone())
@@ -873,30 +842,21 @@ impl<'ast> Create<'ast> for LVal {
                     new_value!(program, scope).get_elem_ptr(src.clone(), index.clone())
                 };
                 push_value!(program, scope, value.clone());
-                info.new_info(value.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(src.clone()).unwrap().death = info.counter();
 
                 src = value;
             }
 
             let index = new_value!(program, scope).integer(0);
             push_value!(program, scope, index.clone());
-            info.new_info(index.clone());
 
             if self.dims.len() > 0 {
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(src.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index.clone()).unwrap().death = info.counter();
-                info.info_mut(src.clone()).unwrap().death = info.counter();
 
-                get_elem_ptr
+                Ok(get_elem_ptr)
             }
             else {
-                src
+                Ok(src)
             }
         }
     }
@@ -904,15 +864,14 @@ impl<'ast> Create<'ast> for LVal {
 
 impl<'ast> Create<'ast> for PrimaryExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self {
-            Self::Exp(exp) => exp.create(program, scope, info),
+            Self::Exp { exp, .. } => exp.create(program, scope, info),
             Self::LVal(lval) => lval.create(program, scope, info),
-            Self::Num(num) => {
+            Self::Num { num, .. } => {
                 let integer = new_value!(program, scope).integer(*num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                integer
+                Ok(integer)
             }
         }
     }
@@ -920,40 +879,34 @@ impl<'ast> Create<'ast> for PrimaryExp {
 
 impl<'ast> Create<'ast> for UnaryExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Primary(primary) => primary.create(program, scope, info),
             Self::Call(call) => call.create(program, scope, info),
-            Self::Unary(op, unary) => {
-                let rhs = unary.create(program, scope, info);
+            Self::Unary { op, unary, .. } => {
+                let rhs = unary.create(program, scope, info)?;
                 if let UnaryOp::Pos = op {
-                    return rhs;
+                    return Ok(rhs);
                 }
                 let lhs = new_value!(program, scope).integer(0);
                 push_value!(program, scope, lhs.clone());
-                info.new_info(lhs.clone());
 
                 let binary = new_value!(program, scope).binary(match op {
                     UnaryOp::Neg => BinaryOp::Sub,
                     UnaryOp::Not => BinaryOp::Eq,
-                    _ => panic!("this op isn't defined in unary")
+                    UnaryOp::Pos => unreachable!(),
                 }, lhs.clone(), rhs.clone());
                 push_value!(program, scope, binary.clone());
-                info.new_info(binary.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
 
-                binary
+                Ok(binary)
             }
         }
     }
@@ -961,36 +914,35 @@ impl<'ast> Create<'ast> for UnaryExp {
 
 impl<'ast> Create<'ast> for Call {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
-        let args: Vec<_> = self.args.iter().map(|arg| arg.create(program, scope, info)).collect();
-        let func = scope.func(&self.id);
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
+        let args = self.args.iter().map(|arg| arg.create(program, scope, info)).collect::<Result<Vec<_>, _>>()?;
+        let func = match scope.func(&self.id) {
+            Some(func) => *func,
+            None => return Err(Diagnostic::error(format!("call to undeclared function `{}`", self.id)).with_primary(self.span)),
+        };
 
         let call = new_value!(program, scope).call(func.clone(), args.clone());
         push_value!(program, scope, call.clone());
-        info.new_info(call.clone());
-
-        args.iter().for_each(|arg| info.info_mut(arg.clone()).unwrap().death = info.counter());
-        call
+        Ok(call)
     }
 }
 
 impl<'ast> Create<'ast> for MulExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Unary(unary) => unary.create(program, scope, info),
             Self::Mul(mul, op, unary) => {
-                let lhs = mul.create(program, scope, info);
-                let rhs = unary.create(program, scope, info);
+                let lhs = mul.create(program, scope, info)?;
+                let rhs = unary.create(program, scope, info)?;
 
                 let binary = new_value!(program, scope).binary(match op {
                     MulOp::Mul => BinaryOp::Mul,
@@ -998,12 +950,8 @@ impl<'ast> Create<'ast> for MulExp {
                     MulOp::Mod => BinaryOp::Mod,
                 }, lhs.clone(), rhs.clone());
                 push_value!(program, scope, binary.clone());
-                info.new_info(binary.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
 
-                binary
+                Ok(binary)
             }
         }
     }
@@ -1011,33 +959,28 @@ impl<'ast> Create<'ast> for MulExp {
 
 impl<'ast> Create<'ast> for AddExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out { 
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> { 
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Mul(mul) => mul.create(program, scope, info),
             Self::Add(add, op, mul) => {
-                let lhs = add.create(program, scope, info);
-                let rhs = mul.create(program, scope, info);
+                let lhs = add.create(program, scope, info)?;
+                let rhs = mul.create(program, scope, info)?;
 
                 let binary = new_value!(program, scope).binary(match op {
                     AddOp::Add => BinaryOp::Add,
                     AddOp::Sub => BinaryOp::Sub,
                 }, lhs.clone(), rhs.clone());
                 push_value!(program, scope, binary.clone());
-                info.new_info(binary.clone());
 
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
-
-                binary
+                Ok(binary)
             }
         }
     }
@@ -1045,21 +988,20 @@ impl<'ast> Create<'ast> for AddExp {
 
 impl<'ast> Create<'ast> for RelExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out { 
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> { 
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Add(add) => add.create(program, scope, info),
             Self::Rel(rel, op, add) => {
-                let lhs = rel.create(program, scope, info);
-                let rhs = add.create(program, scope, info);
+                let lhs = rel.create(program, scope, info)?;
+                let rhs = add.create(program, scope, info)?;
 
                 let binary = new_value!(program, scope).binary(match op {
                     RelOp::Lt => BinaryOp::Lt,
@@ -1068,12 +1010,8 @@ impl<'ast> Create<'ast> for RelExp {
                     RelOp::Ge => BinaryOp::Ge,
                 }, lhs.clone(), rhs.clone());
                 push_value!(program, scope, binary.clone());
-                info.new_info(binary.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
 
-                binary
+                Ok(binary)
             }
         }
     }
@@ -1081,33 +1019,28 @@ impl<'ast> Create<'ast> for RelExp {
 
 impl<'ast> Create<'ast> for EqExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out { 
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> { 
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Rel(rel) => rel.create(program, scope, info),
             Self::Eq(eq, op, rel) => {
-                let lhs = eq.create(program, scope, info);
-                let rhs = rel.create(program, scope, info);
+                let lhs = eq.create(program, scope, info)?;
+                let rhs = rel.create(program, scope, info)?;
 
                 let binary = new_value!(program, scope).binary(match op {
                     EqOp::Eq => BinaryOp::Eq,
                     EqOp::Ne => BinaryOp::NotEq,
                 }, lhs.clone(), rhs.clone());
                 push_value!(program, scope, binary.clone());
-                info.new_info(binary.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
 
-                binary
+                Ok(binary)
             }
         }
     }
@@ -1115,20 +1048,19 @@ impl<'ast> Create<'ast> for EqExp {
 
 impl<'ast> Create<'ast> for LAndExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::Eq(eq) => eq.create(program, scope, info),
             Self::LAnd(land, eq) => {
-                let lhs = land.create(program, scope, info);
+                let lhs = land.create(program, scope, info)?;
                 let (then_bb, _, end_bb) = scope.label_mut().if_label();
 
                 let then_bb = new_bb!(program, scope).basic_block(Some(then_bb));
@@ -1136,64 +1068,39 @@ impl<'ast> Create<'ast> for LAndExp {
 
                 let alloc = new_value!(program, scope).alloc(Type::get_i32());
                 push_value!(program, scope, alloc.clone());
-                info.new_info(alloc.clone());
 
                 let zero = new_value!(program, scope).integer(0);
                 push_value!(program, scope, zero.clone());
-                info.new_info(zero.clone());
 
                 let store = new_value!(program, scope).store(zero.clone(), alloc.clone());
                 push_value!(program, scope, store.clone());
-                info.new_info(store.clone());
-
-                info.info_mut(zero.clone()).unwrap().death = info.counter();
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
 
                 let cond = new_value!(program, scope).binary(BinaryOp::NotEq, lhs.clone(), zero.clone());
                 push_value!(program, scope, cond.clone());
-                info.new_info(cond.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(zero.clone()).unwrap().death = info.counter();
 
                 let branch = new_value!(program, scope).branch(cond.clone(), then_bb.clone(), end_bb.clone());
                 push_value!(program, scope, branch.clone());
-                info.new_info(branch.clone());
-
-                info.info_mut(cond.clone()).unwrap().death = info.counter();
 
                 push_bb!(program, scope, then_bb.clone());
                 scope.set_cur_bb(Some(then_bb.clone()));
 
-                let rhs = eq.create(program, scope, info);
+                let rhs = eq.create(program, scope, info)?;
                 let result = new_value!(program, scope).binary(BinaryOp::NotEq, rhs.clone(), zero.clone());
                 push_value!(program, scope, result.clone());
-                info.new_info(result.clone());
-
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
-                info.info_mut(zero.clone()).unwrap().death = info.counter();
 
                 let store = new_value!(program, scope).store(result.clone(), alloc.clone());
                 push_value!(program, scope, store.clone());
-                info.new_info(store.clone());
-
-                info.info_mut(result.clone()).unwrap().death = info.counter();
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
 
                 let jump = new_value!(program, scope).jump(end_bb.clone());
                 push_value!(program, scope, jump.clone());
-                info.new_info(jump.clone());
 
                 push_bb!(program, scope, end_bb.clone());
                 scope.set_cur_bb(Some(end_bb.clone()));
 
                 let load = new_value!(program, scope).load(alloc.clone());
                 push_value!(program, scope, load.clone());
-                info.new_info(load.clone());
-
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
 
-                load
+                Ok(load)
             }
         }
     }
@@ -1201,20 +1108,19 @@ impl<'ast> Create<'ast> for LAndExp {
 
 impl<'ast> Create<'ast> for LOrExp {
     type Out = Value;
-    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Self::Out {
+    fn create(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self.evaluate(scope) {
             Some(num) => {
                 let integer = new_value!(program, scope).integer(num);
                 push_value!(program, scope, integer.clone());
-                info.new_info(integer.clone());
-                return integer;
+                return Ok(integer);
             }
             None => {}
         }
         match self {
             Self::LAnd(land) => land.create(program, scope, info),
             Self::LOr(lor, land) => {
-                let lhs = lor.create(program, scope, info);
+                let lhs = lor.create(program, scope, info)?;
                 let (then_bb, _, end_bb) = scope.label_mut().if_label();
 
                 let then_bb = new_bb!(program, scope).basic_block(Some(then_bb));
@@ -1222,79 +1128,115 @@ impl<'ast> Create<'ast> for LOrExp {
 
                 let alloc = new_value!(program, scope).alloc(Type::get_i32());
                 push_value!(program, scope, alloc.clone());
-                info.new_info(alloc.clone());
 
                 let zero = new_value!(program, scope).integer(0);
                 push_value!(program, scope, zero.clone());
-                info.new_info(zero.clone());
 
                 let one = new_value!(program, scope).integer(1);
                 push_value!(program, scope, one.clone());
-                info.new_info(one.clone());
 
                 let store = new_value!(program, scope).store(one.clone(), alloc.clone());
                 push_value!(program, scope, store.clone());
-                info.new_info(store.clone());
-
-                info.info_mut(one.clone()).unwrap().death = info.counter();
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
 
                 let cond = new_value!(program, scope).binary(BinaryOp::Eq, lhs.clone(), zero.clone());
                 push_value!(program, scope, cond.clone());
-                info.new_info(cond.clone());
-
-                info.info_mut(lhs.clone()).unwrap().death = info.counter();
-                info.info_mut(zero.clone()).unwrap().death = info.counter();
 
                 let branch = new_value!(program, scope).branch(cond.clone(), then_bb.clone(), end_bb.clone());
                 push_value!(program, scope, branch.clone());
-                info.new_info(branch.clone());
-
-                info.info_mut(cond.clone()).unwrap().death = info.counter();
 
                 push_bb!(program, scope, then_bb.clone());
                 scope.set_cur_bb(Some(then_bb.clone()));
 
-                let rhs = land.create(program, scope, info);
+                let rhs = land.create(program, scope, info)?;
                 let result = new_value!(program, scope).binary(BinaryOp::NotEq, rhs.clone(), zero.clone());
                 push_value!(program, scope, result.clone());
-                info.new_info(result.clone());
-
-                info.info_mut(rhs.clone()).unwrap().death = info.counter();
-                info.info_mut(zero.clone()).unwrap().death = info.counter();
 
                 let store = new_value!(program, scope).store(result.clone(), alloc.clone());
                 push_value!(program, scope, store.clone());
-                info.new_info(store.clone());
-
-                info.info_mut(result.clone()).unwrap().death = info.counter();
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
 
                 let jump = new_value!(program, scope).jump(end_bb.clone());
                 push_value!(program, scope, jump.clone());
-                info.new_info(jump.clone());
 
                 push_bb!(program, scope, end_bb.clone());
                 scope.set_cur_bb(Some(end_bb.clone()));
 
                 let load = new_value!(program, scope).load(alloc.clone());
                 push_value!(program, scope, load.clone());
-                info.new_info(load.clone());
 
-                info.info_mut(alloc.clone()).unwrap().death = info.counter();
+                Ok(load)
+            }
+        }
+    }
+}
+
+impl<'ast> Cond<'ast> for Exp {
+    fn cond(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info, true_bb: BasicBlock, false_bb: BasicBlock) -> Result<(), Diagnostic> {
+        self.lor.cond(program, scope, info, true_bb, false_bb)
+    }
+}
 
-                load
+impl<'ast> Cond<'ast> for LOrExp {
+    fn cond(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info, true_bb: BasicBlock, false_bb: BasicBlock) -> Result<(), Diagnostic> {
+        if let Some(num) = self.evaluate(scope) {
+            let jump = new_value!(program, scope).jump(if num != 0 { true_bb } else { false_bb });
+            push_value!(program, scope, jump);
+            return Ok(());
+        }
+        match self {
+            Self::LAnd(land) => land.cond(program, scope, info, true_bb, false_bb),
+            Self::LOr(lor, land) => {
+                // the right operand is tested only when the left one is false
+                let rhs_bb = scope.label_mut().cond_label();
+                let rhs_bb = new_bb!(program, scope).basic_block(Some(rhs_bb));
+                lor.cond(program, scope, info, true_bb, rhs_bb)?;
+
+                push_bb!(program, scope, rhs_bb);
+                scope.set_cur_bb(Some(rhs_bb));
+                land.cond(program, scope, info, true_bb, false_bb)
             }
         }
     }
 }
 
+impl<'ast> Cond<'ast> for LAndExp {
+    fn cond(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info, true_bb: BasicBlock, false_bb: BasicBlock) -> Result<(), Diagnostic> {
+        if let Some(num) = self.evaluate(scope) {
+            let jump = new_value!(program, scope).jump(if num != 0 { true_bb } else { false_bb });
+            push_value!(program, scope, jump);
+            return Ok(());
+        }
+        match self {
+            Self::Eq(eq) => eq.cond(program, scope, info, true_bb, false_bb),
+            Self::LAnd(land, eq) => {
+                // the right operand is tested only when the left one is true
+                let rhs_bb = scope.label_mut().cond_label();
+                let rhs_bb = new_bb!(program, scope).basic_block(Some(rhs_bb));
+                land.cond(program, scope, info, rhs_bb, false_bb)?;
+
+                push_bb!(program, scope, rhs_bb);
+                scope.set_cur_bb(Some(rhs_bb));
+                eq.cond(program, scope, info, true_bb, false_bb)
+            }
+        }
+    }
+}
+
+impl<'ast> Cond<'ast> for EqExp {
+    fn cond(&'ast self, program: &mut Program, scope: &mut Scope<'ast>, info: &mut Info, true_bb: BasicBlock, false_bb: BasicBlock) -> Result<(), Diagnostic> {
+        // any other expression is tested by its value
+        let cond = self.create(program, scope, info)?;
+        let branch = new_value!(program, scope).branch(cond, true_bb, false_bb);
+        push_value!(program, scope, branch);
+        Ok(())
+    }
+}
+
 impl<'ast> Create<'ast> for ConstExp {
     type Out = i32;
-    fn create(&self, _: &mut Program, scope: &mut Scope<'ast>, _: &mut Info) -> Self::Out {
+    fn create(&self, _: &mut Program, scope: &mut Scope<'ast>, _: &mut Info) -> Result<Self::Out, Diagnostic> {
         match self.exp.evaluate(scope) {
-            Some(num) => num,
-            None => panic!("can't evaluate constant expression"),
+            Some(num) => Ok(num),
+            None => Err(Diagnostic::error("can't evaluate constant expression").with_primary(self.span())),
         }
     }
 }
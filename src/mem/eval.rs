@@ -4,45 +4,46 @@
 //! 
 
 use crate::ast::*;
-use crate::mem::scope::{ Scope, Entry };
+
+/// Anything that knows the values of constants in scope.
+pub trait Constants {
+    fn constant(&self, id: &str) -> Option<i32>;
+}
 
 pub trait Eval {
-    fn evaluate(&self, scope: &Scope) -> Option<i32>;
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32>;
 }
 
 impl Eval for Exp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         self.lor.evaluate(scope)
     }
 }
 
 impl Eval for LVal {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
-        match scope.value(&self.id) {
-            Entry::Const(num) => Some(*num),
-            Entry::Value(_) => None,
-        }
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
+        scope.constant(&self.id)
     }
 }
 
 impl Eval for PrimaryExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
-            Self::Exp(exp) => exp.evaluate(scope),
+            Self::Exp { exp, .. } => exp.evaluate(scope),
             Self::LVal(lval) => lval.evaluate(scope),
-            Self::Num(num) => Some(*num),
+            Self::Num { num, .. } => Some(*num),
         }
     }
 }
 
 impl Eval for UnaryExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Primary(primary) => primary.evaluate(scope),
-            Self::Unary(op, unary) => {
+            Self::Unary { op, unary, .. } => {
                 match op {
                     UnaryOp::Pos => unary.evaluate(scope),
-                    UnaryOp::Neg => unary.evaluate(scope).map(|num| -num),
+                    UnaryOp::Neg => unary.evaluate(scope).map(|num| num.wrapping_neg()),
                     UnaryOp::Not => unary.evaluate(scope).map(|num| if num == 0 { 1 } else { 0 }),
                 }
             }
@@ -52,16 +53,17 @@ impl Eval for UnaryExp {
 }
 
 impl Eval for MulExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Unary(unary) => unary.evaluate(scope),
             Self::Mul(mul, op, unary) => {
                 let left = mul.evaluate(scope)?;
                 let right = unary.evaluate(scope)?;
                 match op {
-                    MulOp::Mul => Some(left * right),
-                    MulOp::Div => Some(left / right),
-                    MulOp::Mod => Some(left % right),
+                    MulOp::Mul => Some(left.wrapping_mul(right)),
+                    // dividing by zero is left to the runtime
+                    MulOp::Div => if right == 0 { None } else { Some(left.wrapping_div(right)) },
+                    MulOp::Mod => if right == 0 { None } else { Some(left.wrapping_rem(right)) },
                 }
             }
         }
@@ -69,15 +71,15 @@ impl Eval for MulExp {
 }
 
 impl Eval for AddExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Mul(mul) => mul.evaluate(scope),
             Self::Add(add, op, mul) => {
                 let left = add.evaluate(scope)?;
                 let right = mul.evaluate(scope)?;
                 match op {
-                    AddOp::Add => Some(left + right),
-                    AddOp::Sub => Some(left - right),
+                    AddOp::Add => Some(left.wrapping_add(right)),
+                    AddOp::Sub => Some(left.wrapping_sub(right)),
                 }
             }
         }
@@ -85,7 +87,7 @@ impl Eval for AddExp {
 }
 
 impl Eval for RelExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Add(add) => add.evaluate(scope),
             Self::Rel(rel, op, add) => {
@@ -103,7 +105,7 @@ impl Eval for RelExp {
 }
 
 impl Eval for EqExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Rel(rel) => rel.evaluate(scope),
             Self::Eq(eq, op, rel) => {
@@ -119,7 +121,7 @@ impl Eval for EqExp {
 }
 
 impl Eval for LAndExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::Eq(eq) => eq.evaluate(scope),
             Self::LAnd(land, eq) => {
@@ -138,7 +140,7 @@ impl Eval for LAndExp {
 }
 
 impl Eval for LOrExp {
-    fn evaluate(&self, scope: &Scope) -> Option<i32> {
+    fn evaluate<S: Constants>(&self, scope: &S) -> Option<i32> {
         match self {
             Self::LAnd(land) => land.evaluate(scope),
             Self::LOr(lor, land) => {
@@ -6,6 +6,7 @@
 pub struct Label {
     if_counter: usize,
     while_counter: usize,
+    cond_counter: usize,
 }
 
 impl Label {
@@ -13,6 +14,7 @@ impl Label {
         Label {
             if_counter: 0,
             while_counter: 0,
+            cond_counter: 0,
         }
     }
 
@@ -35,4 +37,11 @@ impl Label {
             format!("%While_End_{}", counter),
         )
     }
+
+    /// Label of the block testing the right operand of `&&` or `||`.
+    pub fn cond_label(&mut self) -> String {
+        let counter = self.cond_counter;
+        self.cond_counter += 1;
+        format!("%Cond_{}", counter)
+    }
 }
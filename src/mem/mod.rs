@@ -2,26 +2,27 @@
 //! 
 //! This module provides functions for translating compiler unit into program in memory.
 //! 
-//! In this module, three optimizations are performed:
-//! * documenting birth and death "time" of each value to allocate registers better.
+//! In this module, two optimizations are performed:
 //! * when an expression can be evaluated, replace it with the number.
+//! * conditions of `if` and `while` branch to their targets directly, without giving a value.
 //! 
 
 pub mod info;
 pub mod scope;
 mod label;
 mod create;
-mod eval;
+pub mod eval;
 
 use crate::ast::CompUnit;
 use crate::mem::scope::Scope;
 use crate::mem::info::Info;
 use crate::mem::create::Create;
+use crate::diagnostic::Diagnostic;
 use koopa::ir::Program;
 
-pub fn generate_mem(ast: &CompUnit) -> (Program, Info) {
+pub fn generate_mem(ast: &CompUnit) -> Result<(Program, Info), Diagnostic> {
     let mut program = Program::new();
     let mut info = Info::new();
-    ast.create(&mut program, &mut Scope::new(), &mut info);
-    (program, info)
+    ast.create(&mut program, &mut Scope::new(), &mut info)?;
+    Ok((program, info))
 }
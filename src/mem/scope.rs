@@ -9,6 +9,8 @@
 //! 
 
 use crate::mem::label::Label;
+use crate::mem::eval::Constants;
+use crate::diagnostic::Diagnostic;
 use std::collections::HashMap;
 use koopa::ir::{ Value, Function, BasicBlock };
 
@@ -19,7 +21,7 @@ pub enum Entry {
 
 macro_rules! new_value {
     ($program: expr, $scope: expr) => {
-        $program.func_mut($scope.cur_func().clone())
+        $program.func_mut($scope.cur_func()?.clone())
             .dfg_mut()
             .new_value()
     };
@@ -28,9 +30,9 @@ pub(crate) use new_value;
 
 macro_rules! push_value {
     ($program: expr, $scope: expr, $value: expr) => {
-        let _ = $program.func_mut($scope.cur_func().clone())
+        let _ = $program.func_mut($scope.cur_func()?.clone())
             .layout_mut()
-            .bb_mut($scope.cur_bb().clone())
+            .bb_mut($scope.cur_bb()?.clone())
             .insts_mut()
             .push_key_back($value);
     };
@@ -39,7 +41,7 @@ pub(crate) use push_value;
 
 macro_rules! new_bb {
     ($program: expr, $scope: expr) => {
-        $program.func_mut($scope.cur_func().clone())
+        $program.func_mut($scope.cur_func()?.clone())
             .dfg_mut()
             .new_bb()
     };
@@ -48,7 +50,7 @@ pub(crate) use new_bb;
 
 macro_rules! push_bb {
     ($program: expr, $scope: expr, $bb: expr) => {
-        let _ = $program.func_mut($scope.cur_func().clone())
+        let _ = $program.func_mut($scope.cur_func()?.clone())
             .layout_mut()
             .bbs_mut()
             .push_key_back($bb);
@@ -85,13 +87,8 @@ impl<'ast> Scope<'ast> {
         }
     }
 
-    pub fn value(&self, id: &'ast str) -> &Entry {
-        for scope in self.values.iter().rev() {
-            if let Some(entry) = scope.get(id) {
-                return entry;
-            }
-        }
-        panic!("value {} not found", id);
+    pub fn value(&self, id: &str) -> Option<&Entry> {
+        self.values.iter().rev().find_map(|scope| scope.get(id))
     }
 
     pub fn new_value(&mut self, id: &'ast str, entry: Entry) {
@@ -106,21 +103,18 @@ impl<'ast> Scope<'ast> {
         self.values.pop();
     }
 
-    pub fn func(&self, id: &'ast str) -> &Function {
-        match self.funcs.get(id) {
-            Some(func) => func,
-            None => panic!("function {} not found", id),
-        }
+    pub fn func(&self, id: &'ast str) -> Option<&Function> {
+        self.funcs.get(id)
     }
 
     pub fn new_func(&mut self, id: &'ast str, func: Function) {
         self.funcs.insert(id, func);
     }
 
-    pub fn cur_func(&self) -> &Function {
+    pub fn cur_func(&self) -> Result<&Function, Diagnostic> {
         match &self.cur_func {
-            Some(func) => func,
-            None => panic!("no function"),
+            Some(func) => Ok(func),
+            None => Err(Diagnostic::error("expression is only allowed inside a function")),
         }
     }
 
@@ -135,10 +129,10 @@ impl<'ast> Scope<'ast> {
         }
     }
 
-    pub fn cur_bb(&self) -> &BasicBlock {
+    pub fn cur_bb(&self) -> Result<&BasicBlock, Diagnostic> {
         match &self.cur_bb {
-            Some(bb) => bb,
-            None => panic!("no basic block"),
+            Some(bb) => Ok(bb),
+            None => Err(Diagnostic::error("statement is only allowed inside a function")),
         }
     }
 
@@ -166,3 +160,12 @@ impl<'ast> Scope<'ast> {
         &mut self.label
     }
 }
+
+impl<'ast> Constants for Scope<'ast> {
+    fn constant(&self, id: &str) -> Option<i32> {
+        match self.value(id) {
+            Some(Entry::Const(num)) => Some(*num),
+            _ => None,
+        }
+    }
+}
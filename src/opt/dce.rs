@@ -0,0 +1,139 @@
+//! # Dce
+//!
+//! In this file, we remove dead code, so backends see a clean CFG.
+//!
+//! `Unreachable` removes instructions after terminators and blocks unreachable from the entry,
+//! and ends every block falling off its end with a return. It always runs last.
+//!
+//! `Dce` also removes values never used by stores, calls or terminators. Live values are marked from
+//! these roots, and an argument passed to a block is live only if its parameter is. Dead parameters
+//! are dropped with their arguments, and the rest are renumbered.
+//!
+
+use crate::opt::edit::{ args_to, new_params, remove_insts, remove_unreachable, replace_uses, set_kind, terminate, terminator };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::{ BasicBlock, FunctionData, Value, ValueKind };
+use std::collections::{ HashMap, HashSet };
+
+pub struct Unreachable;
+
+impl FunctionPass for Unreachable {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let removed = remove_unreachable(func);
+        terminate(func) || removed
+    }
+}
+
+pub struct Dce;
+
+impl FunctionPass for Dce {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let changed = Unreachable.run_on(func);
+        sweep(func) || changed
+    }
+}
+
+/// Remove dead values and parameters, return whether anything is removed.
+fn sweep(func: &mut FunctionData) -> bool {
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+
+    // the block and index of each parameter, and terminators passing arguments to each block
+    let mut param_of: HashMap<Value, (BasicBlock, usize)> = HashMap::new();
+    let mut incoming: HashMap<BasicBlock, Vec<Value>> = HashMap::new();
+    for &bb in &bbs {
+        for (i, &param) in func.dfg().bb(bb).params().iter().enumerate() {
+            param_of.insert(param, (bb, i));
+        }
+        if let Some(term) = terminator(func, bb) {
+            for target in func.dfg().value(term).kind().bb_uses() {
+                incoming.entry(target).or_default().push(term);
+            }
+        }
+    }
+
+    let mut live: HashSet<Value> = HashSet::new();
+    let mut worklist: Vec<Value> = vec![];
+    for &bb in &bbs {
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            if matches!(func.dfg().value(inst).kind(), ValueKind::Store(_) | ValueKind::Call(_) | ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_)) {
+                live.insert(inst);
+                worklist.push(inst);
+            }
+        }
+    }
+    while let Some(value) = worklist.pop() {
+        let used: Vec<Value> = match param_of.get(&value) {
+            // a parameter uses the arguments passed to it
+            Some(&(bb, i)) => incoming.get(&bb).map_or(vec![], |terms| {
+                terms.iter().flat_map(|&term| args_to(func, term, bb, i)).collect()
+            }),
+            None => match func.dfg().value(value).kind() {
+                ValueKind::Branch(branch) => vec![branch.cond()],
+                ValueKind::Jump(_) => vec![],
+                kind => kind.value_uses().collect(),
+            },
+        };
+        for used in used {
+            // globals and function parameters are not removed anyway
+            if func.dfg().values().contains_key(&used) && live.insert(used) {
+                worklist.push(used);
+            }
+        }
+    }
+
+    let dead: Vec<Value> = bbs.iter()
+        .flat_map(|bb| func.layout().bbs().node(bb).unwrap().insts().keys().copied().collect::<Vec<_>>())
+        .filter(|inst| !live.contains(inst))
+        .collect();
+    let pruned: Vec<BasicBlock> = bbs.iter().copied()
+        .filter(|&bb| func.dfg().bb(bb).params().iter().any(|param| !live.contains(param)))
+        .collect();
+
+    // arguments to dead parameters go first, then their users are all dead
+    for &bb in &pruned {
+        let keep: Vec<bool> = func.dfg().bb(bb).params().iter().map(|param| live.contains(param)).collect();
+        let filter = |args: &mut Vec<Value>| {
+            let mut i = 0;
+            args.retain(|_| {
+                i += 1;
+                keep[i - 1]
+            });
+        };
+        for &term in incoming.get(&bb).map_or(&[][..], |terms| terms.as_slice()) {
+            let mut kind = func.dfg().value(term).kind().clone();
+            match &mut kind {
+                ValueKind::Jump(jump) => filter(jump.args_mut()),
+                ValueKind::Branch(branch) => {
+                    // both edges of a branch may go to the block
+                    if branch.true_bb() == bb {
+                        filter(branch.true_args_mut());
+                    }
+                    if branch.false_bb() == bb {
+                        filter(branch.false_args_mut());
+                    }
+                }
+                _ => unreachable!(),
+            }
+            set_kind(func, term, kind);
+        }
+    }
+    remove_insts(func, &dead);
+
+    // parameters are numbered by their index, so the survivors are created again
+    let mut replaced: HashMap<Value, Value> = HashMap::new();
+    let mut removed: Vec<Value> = vec![];
+    for &bb in &pruned {
+        let (kept, gone): (Vec<Value>, Vec<Value>) = func.dfg().bb(bb).params().iter().partition(|param| live.contains(param));
+        let tys = kept.iter().map(|&param| func.dfg().value(param).ty().clone()).collect();
+        let new = new_params(func, tys, 0);
+        replaced.extend(kept.into_iter().zip(new.iter().copied()));
+        *func.dfg_mut().bb_mut(bb).params_mut() = new;
+        removed.extend(gone);
+    }
+    replace_uses(func, &replaced);
+    for param in replaced.into_keys().chain(removed) {
+        func.dfg_mut().remove_value(param);
+    }
+
+    !dead.is_empty() || !pruned.is_empty()
+}
@@ -0,0 +1,221 @@
+//! # Edit
+//!
+//! In this file, we define functions editing a function in memory, shared by passes.
+//!
+//! Koopa records the users of every value, but replacing a value forgets who uses it.
+//! So instructions are rewritten from definitions to uses, and a rewritten value gets its users back
+//! when they are rewritten after it.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{ BasicBlock, FunctionData, Type, TypeKind, Value, ValueKind };
+use std::collections::{ HashMap, HashSet };
+
+/// The first terminator of a block, instructions after it are never executed.
+pub fn terminator(func: &FunctionData, bb: BasicBlock) -> Option<Value> {
+    func.layout().bbs().node(&bb)?.insts().keys().copied().find(|&inst| is_terminator(func, inst))
+}
+
+pub fn is_terminator(func: &FunctionData, inst: Value) -> bool {
+    matches!(func.dfg().value(inst).kind(), ValueKind::Return(_) | ValueKind::Jump(_) | ValueKind::Branch(_))
+}
+
+/// Arguments a terminator passes to the parameter `i` of a block.
+pub fn args_to(func: &FunctionData, term: Value, bb: BasicBlock, i: usize) -> Vec<Value> {
+    match func.dfg().value(term).kind() {
+        ValueKind::Jump(jump) => vec![jump.args()[i]],
+        ValueKind::Branch(branch) => {
+            let mut args = vec![];
+            if branch.true_bb() == bb {
+                args.push(branch.true_args()[i]);
+            }
+            if branch.false_bb() == bb {
+                args.push(branch.false_args()[i]);
+            }
+            args
+        }
+        _ => vec![],
+    }
+}
+
+/// Replace operands of a value kind found in the map.
+pub fn substitute(kind: &mut ValueKind, map: &HashMap<Value, Value>) {
+    let replace = |value: &mut Value| {
+        if let Some(&new) = map.get(value) {
+            *value = new;
+        }
+    };
+    match kind {
+        ValueKind::Return(ret) => {
+            if let Some(value) = ret.value_mut() {
+                replace(value);
+            }
+        }
+        ValueKind::Binary(binary) => {
+            replace(binary.lhs_mut());
+            replace(binary.rhs_mut());
+        }
+        ValueKind::Load(load) => replace(load.src_mut()),
+        ValueKind::Store(store) => {
+            replace(store.value_mut());
+            replace(store.dest_mut());
+        }
+        ValueKind::GetPtr(get_ptr) => {
+            replace(get_ptr.src_mut());
+            replace(get_ptr.index_mut());
+        }
+        ValueKind::GetElemPtr(get_elem_ptr) => {
+            replace(get_elem_ptr.src_mut());
+            replace(get_elem_ptr.index_mut());
+        }
+        ValueKind::Branch(branch) => {
+            replace(branch.cond_mut());
+            branch.true_args_mut().iter_mut().for_each(replace);
+            branch.false_args_mut().iter_mut().for_each(replace);
+        }
+        ValueKind::Jump(jump) => jump.args_mut().iter_mut().for_each(replace),
+        ValueKind::Call(call) => call.args_mut().iter_mut().for_each(replace),
+        _ => {}
+    }
+}
+
+/// Set the kind of an instruction, its users must be rewritten after it.
+pub fn set_kind(func: &mut FunctionData, inst: Value, kind: ValueKind) {
+    let mut data = func.dfg().value(inst).clone();
+    *data.kind_mut() = kind;
+    func.dfg_mut().replace_value_with(inst).raw(data);
+}
+
+/// Replace every use of a key of the map with its value.
+/// Values in the map should be final, they are not replaced again.
+pub fn replace_uses(func: &mut FunctionData, map: &HashMap<Value, Value>) {
+    if map.is_empty() {
+        return;
+    }
+    let mut rewritten: HashSet<Value> = HashSet::new();
+    for bb in def_order(func) {
+        let insts: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for inst in insts {
+            let kind = func.dfg().value(inst).kind();
+            if !kind.value_uses().any(|used| map.contains_key(&used) || rewritten.contains(&used)) {
+                continue;
+            }
+            let mut kind = kind.clone();
+            substitute(&mut kind, map);
+            set_kind(func, inst, kind);
+            rewritten.insert(inst);
+        }
+    }
+}
+
+/// Blocks with definitions before uses, reachable blocks in reverse postorder first.
+fn def_order(func: &FunctionData) -> Vec<BasicBlock> {
+    let mut order = Dominators::new(&Cfg::new(func)).order().to_vec();
+    let reachable: HashSet<BasicBlock> = order.iter().copied().collect();
+    order.extend(func.layout().bbs().keys().filter(|bb| !reachable.contains(bb)));
+    order
+}
+
+/// Remove instructions from the layout and the function.
+/// Every user of a removed instruction must be removed together.
+pub fn remove_insts(func: &mut FunctionData, insts: &[Value]) {
+    for &inst in insts {
+        if let Some(bb) = func.layout().parent_bb(inst) {
+            func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+        }
+    }
+    // users go first, the later an instruction is, the earlier it goes
+    let mut pending: Vec<Value> = insts.iter().rev().copied().collect();
+    while !pending.is_empty() {
+        let before = pending.len();
+        pending.retain(|&inst| {
+            if func.dfg().value(inst).used_by().is_empty() {
+                func.dfg_mut().remove_value(inst);
+                false
+            }
+            else {
+                true
+            }
+        });
+        assert!(pending.len() < before, "removed instructions are still used");
+    }
+}
+
+/// Create parameters of the types, numbered from `first`, without a block.
+pub fn new_params(func: &mut FunctionData, tys: Vec<Type>, first: usize) -> Vec<Value> {
+    // parameters can only be created with a block, so borrow them from a temporary one
+    let mut all = vec![Type::get_i32(); first];
+    all.extend(tys);
+    let temp = func.dfg_mut().new_bb().basic_block_with_params(None, all);
+    let mut params = std::mem::take(func.dfg_mut().bb_mut(temp).params_mut());
+    let new = params.split_off(first);
+    *func.dfg_mut().bb_mut(temp).params_mut() = params;
+    func.dfg_mut().remove_bb(temp);
+    new
+}
+
+/// End every block falling off its end with a return, of zero if the function returns `int`.
+/// Return whether any block is changed.
+pub fn terminate(func: &mut FunctionData) -> bool {
+    let returns_int = match func.ty().kind() {
+        TypeKind::Function(_, ret) => ret.is_i32(),
+        _ => false,
+    };
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    let mut changed = false;
+    for bb in bbs {
+        let last = func.layout().bbs().node(&bb).unwrap().insts().back_key().copied();
+        if last.is_some_and(|inst| is_terminator(func, inst)) {
+            continue;
+        }
+        let value = if returns_int {
+            let zero = func.dfg_mut().new_value().integer(0);
+            func.layout_mut().bb_mut(bb).insts_mut().push_key_back(zero).unwrap();
+            Some(zero)
+        }
+        else {
+            None
+        };
+        let ret = func.dfg_mut().new_value().ret(value);
+        func.layout_mut().bb_mut(bb).insts_mut().push_key_back(ret).unwrap();
+        changed = true;
+    }
+    changed
+}
+
+/// Remove instructions after terminators and blocks unreachable from the entry.
+/// Return whether anything is removed.
+pub fn remove_unreachable(func: &mut FunctionData) -> bool {
+    let mut dead = vec![];
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for &bb in &bbs {
+        let insts: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        if let Some(end) = insts.iter().position(|&inst| is_terminator(func, inst)) {
+            dead.extend_from_slice(&insts[end + 1..]);
+        }
+    }
+    for &inst in &dead {
+        let bb = func.layout().parent_bb(inst).unwrap();
+        func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+    }
+
+    let dom = Dominators::new(&Cfg::new(func));
+    let unreachable: Vec<BasicBlock> = bbs.into_iter().filter(|&bb| !dom.is_reachable(bb)).collect();
+    for &bb in &unreachable {
+        let insts: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for &inst in &insts {
+            func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+        }
+        func.layout_mut().bbs_mut().remove(&bb);
+        dead.extend(insts);
+    }
+
+    let changed = !dead.is_empty() || !unreachable.is_empty();
+    remove_insts(func, &dead);
+    for bb in unreachable {
+        func.dfg_mut().remove_bb(bb);
+    }
+    changed
+}
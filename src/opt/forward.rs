@@ -0,0 +1,49 @@
+//! # Forward
+//!
+//! In this file, we forward values stored in memory to the loads reading them again.
+//!
+//! In a block, a load from an address just stored to, or just loaded from, reads the same value,
+//! as long as no store or call in between could write memory. Addresses are compared as values,
+//! so different addresses of the same memory are treated as unknown, and any store forgets the others.
+//!
+
+use crate::opt::edit::{ remove_insts, replace_uses };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::{ FunctionData, Value, ValueKind };
+use std::collections::HashMap;
+
+pub struct Forward;
+
+impl FunctionPass for Forward {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let mut replaced: HashMap<Value, Value> = HashMap::new();
+        for (_, node) in func.layout().bbs() {
+            // what each address holds now
+            let mut known: HashMap<Value, Value> = HashMap::new();
+            for &inst in node.insts().keys() {
+                match func.dfg().value(inst).kind() {
+                    ValueKind::Load(load) => match known.get(&load.src()) {
+                        Some(&value) => {
+                            replaced.insert(inst, value);
+                        }
+                        None => {
+                            known.insert(load.src(), inst);
+                        }
+                    },
+                    ValueKind::Store(store) => {
+                        let value = store.value();
+                        known.clear();
+                        known.insert(store.dest(), *replaced.get(&value).unwrap_or(&value));
+                    }
+                    ValueKind::Call(_) => known.clear(),
+                    _ => {}
+                }
+            }
+        }
+
+        let removed: Vec<Value> = replaced.keys().copied().collect();
+        replace_uses(func, &replaced);
+        remove_insts(func, &removed);
+        !removed.is_empty()
+    }
+}
@@ -0,0 +1,140 @@
+//! # Gvn
+//!
+//! In this file, we number pure values by their operands and remove the redundant ones.
+//!
+//! Every access of an array computes its address again, and so do repeated expressions.
+//! The dominator tree is walked with a table from the operator and operands of each `Binary`,
+//! `GetElemPtr` and `GetPtr` to the value computing it first, and the table is scoped,
+//! so a value is only replaced by one dominating it. Constants are compared by their numbers,
+//! and operands of commutative operators are compared in both orders.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use crate::opt::edit::{ remove_insts, replace_uses };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::{ BasicBlock, BinaryOp, FunctionData, Value, ValueKind };
+use std::collections::HashMap;
+
+/// Eliminated values of each function run on.
+#[derive(Default)]
+pub struct Gvn {
+    eliminated: Vec<(String, usize)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Operand {
+    Int(i32),
+    Value(Value),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Binary(BinaryOp, Operand, Operand),
+    GetElemPtr(Operand, Operand),
+    GetPtr(Operand, Operand),
+}
+
+impl FunctionPass for Gvn {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let count = gvn(func);
+        self.eliminated.push((func.name().to_string(), count));
+        count > 0
+    }
+
+    fn report(&self) -> Vec<String> {
+        self.eliminated.iter().map(|(name, count)| format!("gvn: {}: {} values eliminated", name, count)).collect()
+    }
+}
+
+/// Replace redundant values of a function, return the number of them.
+fn gvn(func: &mut FunctionData) -> usize {
+    let cfg = Cfg::new(func);
+    let dom = Dominators::new(&cfg);
+    let entry = match func.layout().entry_bb() {
+        Some(entry) => entry,
+        None => return 0,
+    };
+
+    let mut table: HashMap<Key, Value> = HashMap::new();
+    let mut replaced: HashMap<Value, Value> = HashMap::new();
+
+    enum Visit {
+        Enter(BasicBlock),
+        Exit(Vec<Key>),
+    }
+    let mut stack = vec![Visit::Enter(entry)];
+    while let Some(visit) = stack.pop() {
+        let bb = match visit {
+            Visit::Enter(bb) => bb,
+            Visit::Exit(inserted) => {
+                for key in inserted {
+                    table.remove(&key);
+                }
+                continue;
+            }
+        };
+
+        let mut inserted = vec![];
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            let key = match key(func, &replaced, inst) {
+                Some(key) => key,
+                None => continue,
+            };
+            match lookup(&table, key) {
+                Some(leader) => {
+                    replaced.insert(inst, leader);
+                }
+                None => {
+                    table.insert(key, inst);
+                    inserted.push(key);
+                }
+            }
+        }
+
+        stack.push(Visit::Exit(inserted));
+        for &child in dom.children(bb).iter().rev() {
+            stack.push(Visit::Enter(child));
+        }
+    }
+
+    let removed: Vec<Value> = replaced.keys().copied().collect();
+    replace_uses(func, &replaced);
+    remove_insts(func, &removed);
+    removed.len()
+}
+
+/// The key of a pure value, with operands replaced by their leaders.
+fn key(func: &FunctionData, replaced: &HashMap<Value, Value>, inst: Value) -> Option<Key> {
+    let operand = |value: Value| {
+        let value = *replaced.get(&value).unwrap_or(&value);
+        // globals are not in the function
+        if func.dfg().values().contains_key(&value) {
+            if let ValueKind::Integer(int) = func.dfg().value(value).kind() {
+                return Operand::Int(int.value());
+            }
+        }
+        Operand::Value(value)
+    };
+    match func.dfg().value(inst).kind() {
+        ValueKind::Binary(binary) => Some(Key::Binary(binary.op(), operand(binary.lhs()), operand(binary.rhs()))),
+        ValueKind::GetElemPtr(get_elem_ptr) => Some(Key::GetElemPtr(operand(get_elem_ptr.src()), operand(get_elem_ptr.index()))),
+        ValueKind::GetPtr(get_ptr) => Some(Key::GetPtr(operand(get_ptr.src()), operand(get_ptr.index()))),
+        _ => None,
+    }
+}
+
+/// Find a value with the key, commutative operators match operands in both orders.
+fn lookup(table: &HashMap<Key, Value>, key: Key) -> Option<Value> {
+    if let Some(&value) = table.get(&key) {
+        return Some(value);
+    }
+    match key {
+        Key::Binary(op, lhs, rhs) if commutative(op) => table.get(&Key::Binary(op, rhs, lhs)).copied(),
+        _ => None,
+    }
+}
+
+fn commutative(op: BinaryOp) -> bool {
+    matches!(op, BinaryOp::Add | BinaryOp::Mul | BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::And | BinaryOp::Or | BinaryOp::Xor)
+}
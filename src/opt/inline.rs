@@ -0,0 +1,281 @@
+//! # Inline
+//!
+//! In this file, we inline calls of small functions and functions called once.
+//!
+//! A call is replaced by a copy of the callee. The block of the call is split at the call,
+//! the part after it becomes the continuation, and it takes the returned value as a parameter.
+//! Blocks of the callee are cloned after the block of the call, its parameters are replaced
+//! by the arguments, its allocations go to the entry of the caller,
+//! and its returns become jumps to the continuation.
+//!
+//! Functions are visited callees first, so a callee is inlined with its own calls inlined.
+//! A function is inlined at every call if it's not larger than `size`,
+//! or at its only call if it's called once. Calls of a function in itself are inlined
+//! `recursion` times, with its body before any of them. Functions inlined and no longer called
+//! are removed.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use crate::opt::dce::Unreachable;
+use crate::opt::edit::{ remove_insts, replace_uses, substitute };
+use crate::opt::pass::{ FunctionPass, ModulePass };
+use koopa::ir::builder_traits::*;
+use koopa::ir::entities::ValueData;
+use koopa::ir::{ BasicBlock, Function, FunctionData, Program, Value, ValueKind };
+use std::collections::{ HashMap, HashSet };
+
+pub struct Inliner {
+    /// Functions with at most this many instructions are inlined at every call.
+    pub size: usize,
+    /// Times a call of a function in itself is inlined.
+    pub recursion: usize,
+    /// Number of calls inlined, also used to name cloned blocks.
+    count: usize,
+    inlined: Vec<(String, usize)>,
+}
+
+impl Inliner {
+    pub fn new(size: usize, recursion: usize) -> Self {
+        Self { size, recursion, count: 0, inlined: vec![] }
+    }
+}
+
+/// A copy of a function to inline.
+struct Body {
+    params: Vec<Value>,
+    /// Blocks in layout, with their names, parameters and instructions.
+    blocks: Vec<(BasicBlock, String, Vec<Value>, Vec<Value>)>,
+    /// Every value in the blocks, the parameters included.
+    values: HashMap<Value, ValueData>,
+    /// Indices of blocks with definitions before uses.
+    order: Vec<usize>,
+}
+
+impl ModulePass for Inliner {
+    fn run(&mut self, program: &mut Program) -> bool {
+        // returns are found by terminators, so blocks must end with them
+        let funcs: Vec<Function> = program.func_layout().to_vec();
+        for &func in &funcs {
+            if program.func(func).layout().entry_bb().is_some() {
+                Unreachable.run_on(program.func_mut(func));
+            }
+        }
+
+        let sites = call_sites(program);
+        let mut calls: HashMap<Function, usize> = HashMap::new();
+        for (&caller, callees) in &sites {
+            for &(_, callee) in callees {
+                if callee != caller {
+                    *calls.entry(callee).or_default() += 1;
+                }
+            }
+        }
+
+        let mut changed = false;
+        let mut inlined: HashSet<Function> = HashSet::new();
+        for caller in postorder(program, &sites) {
+            let mut count = 0;
+            for (call, callee) in calls_in(program.func(caller)) {
+                if callee == caller || program.func(callee).layout().entry_bb().is_none() {
+                    continue;
+                }
+                let size = program.func(callee).layout().bbs().nodes().map(|node| node.insts().len()).sum::<usize>();
+                let once = calls[&callee] == 1 && !sites[&callee].iter().any(|&(_, f)| f == callee);
+                if size > self.size && !once {
+                    continue;
+                }
+                let body = snapshot(program.func(callee));
+                self.inline(program.func_mut(caller), call, &body);
+                count += 1;
+                inlined.insert(callee);
+            }
+
+            // each round inlines the body into the calls left by the last one
+            let body = snapshot(program.func(caller));
+            for _ in 0..self.recursion {
+                let recursive: Vec<Value> = calls_in(program.func(caller)).into_iter()
+                    .filter(|&(_, callee)| callee == caller)
+                    .map(|(call, _)| call)
+                    .collect();
+                for call in recursive {
+                    self.inline(program.func_mut(caller), call, &body);
+                    count += 1;
+                }
+            }
+
+            if count > 0 {
+                self.inlined.push((program.func(caller).name().to_string(), count));
+                changed = true;
+            }
+        }
+
+        // calls may be left in functions inlined into others, so they are counted again
+        let called: HashSet<Function> = call_sites(program).into_values().flatten().map(|(_, callee)| callee).collect();
+        for func in inlined {
+            if !called.contains(&func) && program.func(func).name() != "@main" {
+                program.remove_func(func);
+            }
+        }
+        changed
+    }
+
+    fn report(&self) -> Vec<String> {
+        self.inlined.iter().map(|(name, count)| format!("inline: {}: {} calls inlined", name, count)).collect()
+    }
+}
+
+impl Inliner {
+    /// Replace a call by a copy of the body of the callee.
+    fn inline(&mut self, func: &mut FunctionData, call: Value, body: &Body) {
+        self.count += 1;
+        let prefix = format!("%inline{}", self.count);
+        let bb = func.layout().parent_bb(call).unwrap();
+
+        // the continuation takes instructions after the call
+        let ty = func.dfg().value(call).ty().clone();
+        let tys = if ty.is_unit() { vec![] } else { vec![ty] };
+        let cont = func.dfg_mut().new_bb().basic_block_with_params(Some(format!("{}_ret", prefix)), tys);
+        func.layout_mut().bbs_mut().cursor_mut(bb).insert_key_after(cont).unwrap();
+        let after: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys()
+            .skip_while(|&&inst| inst != call)
+            .skip(1)
+            .copied()
+            .collect();
+        for inst in after {
+            func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+            func.layout_mut().bb_mut(cont).insts_mut().push_key_back(inst).unwrap();
+        }
+
+        // arguments replace parameters, and blocks are created before values jumping to them
+        let args: Vec<Value> = match func.dfg().value(call).kind() {
+            ValueKind::Call(call) => call.args().to_vec(),
+            _ => unreachable!(),
+        };
+        let mut values: HashMap<Value, Value> = body.params.iter().copied().zip(args).collect();
+        let mut bbs: HashMap<BasicBlock, BasicBlock> = HashMap::new();
+        let mut prev = bb;
+        for (old, name, params, _) in &body.blocks {
+            let tys = params.iter().map(|param| body.values[param].ty().clone()).collect();
+            let new = func.dfg_mut().new_bb().basic_block_with_params(Some(format!("{}_{}", prefix, &name[1..])), tys);
+            values.extend(params.iter().copied().zip(func.dfg().bb(new).params().iter().copied()));
+            func.layout_mut().bbs_mut().cursor_mut(prev).insert_key_after(new).unwrap();
+            bbs.insert(*old, new);
+            prev = new;
+        }
+
+        let entry = func.layout().entry_bb().unwrap();
+        for &i in &body.order {
+            let (old, _, _, insts) = &body.blocks[i];
+            let new = bbs[old];
+            for inst in insts {
+                let mut data = body.values[inst].clone();
+                let value = match data.kind_mut() {
+                    ValueKind::Return(ret) => {
+                        let mut args = vec![];
+                        if !func.dfg().bb(cont).params().is_empty() {
+                            // falling off a function returning `int` gives zero
+                            let value = match ret.value() {
+                                Some(value) => values[&value],
+                                None => {
+                                    let zero = func.dfg_mut().new_value().integer(0);
+                                    func.layout_mut().bb_mut(new).insts_mut().push_key_back(zero).unwrap();
+                                    zero
+                                }
+                            };
+                            args.push(value);
+                        }
+                        func.dfg_mut().new_value().jump_with_args(cont, args)
+                    }
+                    kind => {
+                        substitute(kind, &values);
+                        match kind {
+                            ValueKind::Jump(jump) => *jump.target_mut() = bbs[&jump.target()],
+                            ValueKind::Branch(branch) => {
+                                *branch.true_bb_mut() = bbs[&branch.true_bb()];
+                                *branch.false_bb_mut() = bbs[&branch.false_bb()];
+                            }
+                            _ => {}
+                        }
+                        func.dfg_mut().new_value().raw(data)
+                    }
+                };
+                values.insert(*inst, value);
+                if let ValueKind::Alloc(_) = func.dfg().value(value).kind() {
+                    func.layout_mut().bb_mut(entry).insts_mut().push_key_front(value).unwrap();
+                }
+                else {
+                    func.layout_mut().bb_mut(new).insts_mut().push_key_back(value).unwrap();
+                }
+            }
+        }
+
+        // the call jumps to the entry of the callee, and its value is returned to the continuation
+        let jump = func.dfg_mut().new_value().jump(bbs[&body.blocks[0].0]);
+        func.layout_mut().bb_mut(bb).insts_mut().push_key_back(jump).unwrap();
+        if let Some(&result) = func.dfg().bb(cont).params().first() {
+            replace_uses(func, &HashMap::from([(call, result)]));
+        }
+        remove_insts(func, &[call]);
+    }
+}
+
+/// Calls in a function, with their callees.
+fn calls_in(func: &FunctionData) -> Vec<(Value, Function)> {
+    let mut calls = vec![];
+    for (_, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            if let ValueKind::Call(call) = func.dfg().value(inst).kind() {
+                calls.push((inst, call.callee()));
+            }
+        }
+    }
+    calls
+}
+
+fn call_sites(program: &Program) -> HashMap<Function, Vec<(Value, Function)>> {
+    program.func_layout().iter().map(|&func| (func, calls_in(program.func(func)))).collect()
+}
+
+/// Functions with callees before callers, a cycle of calls is broken anywhere.
+fn postorder(program: &Program, sites: &HashMap<Function, Vec<(Value, Function)>>) -> Vec<Function> {
+    let mut order = vec![];
+    let mut visited: HashSet<Function> = HashSet::new();
+    for &root in program.func_layout() {
+        if !visited.insert(root) {
+            continue;
+        }
+        let mut stack = vec![(root, 0)];
+        while let Some((func, i)) = stack.pop() {
+            match sites[&func].get(i) {
+                Some(&(_, callee)) => {
+                    stack.push((func, i + 1));
+                    if visited.insert(callee) {
+                        stack.push((callee, 0));
+                    }
+                }
+                None => order.push(func),
+            }
+        }
+    }
+    // declarations have nothing to inline
+    order.retain(|&func| program.func(func).layout().entry_bb().is_some());
+    order
+}
+
+/// Copy a function with a body, so it can be inlined into any function, itself included.
+fn snapshot(func: &FunctionData) -> Body {
+    let mut values: HashMap<Value, ValueData> = HashMap::new();
+    let mut blocks = vec![];
+    for (&bb, node) in func.layout().bbs() {
+        let params = func.dfg().bb(bb).params().to_vec();
+        let insts: Vec<Value> = node.insts().keys().copied().collect();
+        for &value in params.iter().chain(&insts) {
+            values.insert(value, func.dfg().value(value).clone());
+        }
+        blocks.push((bb, func.dfg().bb(bb).name().clone().unwrap(), params, insts));
+    }
+    let index: HashMap<BasicBlock, usize> = blocks.iter().enumerate().map(|(i, block)| (block.0, i)).collect();
+    let order = Dominators::new(&Cfg::new(func)).order().iter().map(|bb| index[bb]).collect();
+    Body { params: func.params().to_vec(), blocks, values, order }
+}
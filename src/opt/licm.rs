@@ -0,0 +1,146 @@
+//! # Licm
+//!
+//! In this file, we hoist computations not changing in a loop out of it.
+//!
+//! A `Binary`, `GetElemPtr` or `GetPtr` whose operands are all defined out of a loop, or hoisted,
+//! computes the same value in every iteration. Such values never trap on the target,
+//! so they are moved into the preheader even if the loop may not reach them. Dividing by zero
+//! is an error in Koopa IR though, so a `div` or `mod` is only hoisted by a constant other than zero.
+//! The preheader is the only block entering the header from out of the loop,
+//! and it's created if the header has no such block. Loops are visited from inner to outer,
+//! so a value can leave a loop nest one loop at a time.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use crate::analysis::loops::Loops;
+use crate::opt::edit::{ set_kind, terminator };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{ BasicBlock, BinaryOp, FunctionData, Value, ValueKind };
+use std::collections::HashSet;
+
+pub struct Licm;
+
+impl FunctionPass for Licm {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let mut changed = false;
+        let mut visited: HashSet<BasicBlock> = HashSet::new();
+        // creating preheaders changes the CFG, so loops are found again for every loop
+        loop {
+            let cfg = Cfg::new(func);
+            let dom = Dominators::new(&cfg);
+            let loops = Loops::new(&cfg, &dom);
+            let next = loops.loops().iter().find(|lp| !visited.contains(&lp.header));
+            let (header, blocks) = match next {
+                Some(lp) => (lp.header, lp.blocks.clone()),
+                None => break,
+            };
+            visited.insert(header);
+
+            let hoisted = invariants(func, &dom, &blocks);
+            if hoisted.is_empty() {
+                continue;
+            }
+            let preheader = preheader(func, &cfg, header, &blocks);
+            let term = terminator(func, preheader).unwrap();
+            for inst in hoisted {
+                let bb = func.layout().parent_bb(inst).unwrap();
+                func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+                func.layout_mut().bb_mut(preheader).insts_mut().cursor_mut(term).insert_key_before(inst).unwrap();
+            }
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Values of a loop not changing in it, definitions before uses.
+fn invariants(func: &FunctionData, dom: &Dominators, blocks: &HashSet<BasicBlock>) -> Vec<Value> {
+    // values defined in the loop
+    let mut defined: HashSet<Value> = HashSet::new();
+    for &bb in blocks {
+        defined.extend(func.dfg().bb(bb).params());
+        defined.extend(func.layout().bbs().node(&bb).unwrap().insts().keys());
+    }
+
+    let mut invariant: HashSet<Value> = HashSet::new();
+    let mut hoisted: Vec<Value> = vec![];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for bb in dom.order().iter().filter(|bb| blocks.contains(bb)) {
+            for &inst in func.layout().bbs().node(bb).unwrap().insts().keys() {
+                if invariant.contains(&inst) {
+                    continue;
+                }
+                let kind = func.dfg().value(inst).kind();
+                if !matches!(kind, ValueKind::Binary(_) | ValueKind::GetElemPtr(_) | ValueKind::GetPtr(_)) || may_trap(func, inst) {
+                    continue;
+                }
+                let is_const = |value: &Value| defined.contains(value) && matches!(func.dfg().value(*value).kind(), ValueKind::Integer(_));
+                if !kind.value_uses().all(|used| !defined.contains(&used) || invariant.contains(&used) || is_const(&used)) {
+                    continue;
+                }
+                // constants in the loop go with their users
+                for used in kind.value_uses() {
+                    if is_const(&used) && invariant.insert(used) {
+                        hoisted.push(used);
+                    }
+                }
+                invariant.insert(inst);
+                hoisted.push(inst);
+                changed = true;
+            }
+        }
+    }
+    hoisted
+}
+
+/// Whether the value divides by something which may be zero.
+fn may_trap(func: &FunctionData, inst: Value) -> bool {
+    match func.dfg().value(inst).kind() {
+        ValueKind::Binary(binary) if matches!(binary.op(), BinaryOp::Div | BinaryOp::Mod) => {
+            !matches!(func.dfg().value(binary.rhs()).kind(), ValueKind::Integer(rhs) if rhs.value() != 0)
+        }
+        _ => false,
+    }
+}
+
+/// The block entering the header from out of the loop, created if there isn't one.
+fn preheader(func: &mut FunctionData, cfg: &Cfg, header: BasicBlock, blocks: &HashSet<BasicBlock>) -> BasicBlock {
+    let outside: Vec<BasicBlock> = cfg.preds(header).iter().copied().filter(|pred| !blocks.contains(pred)).collect();
+    if let [pred] = outside[..] {
+        if cfg.succs(pred).len() == 1 {
+            return pred;
+        }
+    }
+
+    // the preheader takes the parameters of the header, and passes them on
+    let name = format!("{}_preheader", func.dfg().bb(header).name().as_ref().unwrap());
+    let tys = func.dfg().bb(header).params().iter().map(|&param| func.dfg().value(param).ty().clone()).collect();
+    let preheader = func.dfg_mut().new_bb().basic_block_with_params(Some(name), tys);
+    let params = func.dfg().bb(preheader).params().to_vec();
+    let jump = func.dfg_mut().new_value().jump_with_args(header, params);
+    func.layout_mut().bbs_mut().cursor_mut(header).insert_key_before(preheader).unwrap();
+    func.layout_mut().bb_mut(preheader).insts_mut().push_key_back(jump).unwrap();
+
+    for pred in outside {
+        let term = terminator(func, pred).unwrap();
+        let mut kind = func.dfg().value(term).kind().clone();
+        match &mut kind {
+            ValueKind::Jump(jump) => *jump.target_mut() = preheader,
+            ValueKind::Branch(branch) => {
+                if branch.true_bb() == header {
+                    *branch.true_bb_mut() = preheader;
+                }
+                if branch.false_bb() == header {
+                    *branch.false_bb_mut() = preheader;
+                }
+            }
+            _ => unreachable!(),
+        }
+        set_kind(func, term, kind);
+    }
+    preheader
+}
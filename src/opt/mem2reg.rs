@@ -0,0 +1,255 @@
+//! # Mem2reg
+//!
+//! In this file, we promote scalar variables in stack into SSA values.
+//!
+//! Every local `int` is created as an `alloc` with `load`s and `store`s around it.
+//! An `alloc i32` used only as the address of loads and stores never escapes, so its loads can read
+//! the value stored last directly. Where different stores meet, at the iterated dominance frontier
+//! of the stores, the block gets a parameter instead of a phi node, and its predecessors pass the
+//! reaching values as arguments. Parameters are only placed where the variable is live.
+//!
+
+use crate::analysis::cfg::Cfg;
+use crate::analysis::dom::Dominators;
+use crate::opt::edit::{ new_params, remove_insts, remove_unreachable, replace_uses, set_kind, terminator };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{ BasicBlock, FunctionData, Type, TypeKind, Value, ValueKind };
+use std::collections::{ HashMap, HashSet };
+
+pub struct Mem2Reg;
+
+impl FunctionPass for Mem2Reg {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        // loads in dead code could see no store, so dead code goes first
+        let changed = remove_unreachable(func);
+        mem2reg(func) > 0 || changed
+    }
+}
+
+/// Promote variables of a function, return the number of promoted variables.
+fn mem2reg(func: &mut FunctionData) -> usize {
+    let allocs = promotable(func);
+    if allocs.is_empty() {
+        return 0;
+    }
+    let index: HashMap<Value, usize> = allocs.iter().enumerate().map(|(i, &alloc)| (alloc, i)).collect();
+
+    let cfg = Cfg::new(func);
+    let dom = Dominators::new(&cfg);
+    let params = place_params(func, &cfg, &dom, &allocs, &index);
+
+    // parameters of each block, with the variables they stand for
+    let mut block_params: HashMap<BasicBlock, Vec<(usize, Value)>> = HashMap::new();
+    for (bb, vars) in params {
+        let values = append_params(func, bb, vars.len());
+        block_params.insert(bb, vars.into_iter().zip(values).collect());
+    }
+
+    // walk the dominator tree with the values reaching the current point
+    let mut undef: Option<Value> = None;
+    let mut current: Vec<Vec<Value>> = vec![vec![]; allocs.len()];
+    let mut replaced: HashMap<Value, Value> = HashMap::new();
+    let mut removed: Vec<Value> = vec![];
+    let mut args: HashMap<(BasicBlock, BasicBlock), Vec<Value>> = HashMap::new();
+
+    enum Visit {
+        Enter(BasicBlock),
+        Exit(Vec<usize>),
+    }
+    let entry = func.layout().entry_bb().unwrap();
+    let mut stack = vec![Visit::Enter(entry)];
+    while let Some(visit) = stack.pop() {
+        let bb = match visit {
+            Visit::Enter(bb) => bb,
+            Visit::Exit(pushed) => {
+                for var in pushed {
+                    current[var].pop();
+                }
+                continue;
+            }
+        };
+
+        let mut pushed = vec![];
+        for &(var, param) in block_params.get(&bb).map_or(&[][..], |params| params.as_slice()) {
+            current[var].push(param);
+            pushed.push(var);
+        }
+        let insts: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for inst in insts {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Load(load) if index.contains_key(&load.src()) => {
+                    let var = index[&load.src()];
+                    let value = match current[var].last() {
+                        Some(&value) => value,
+                        None => *undef.get_or_insert_with(|| new_zero(func)),
+                    };
+                    replaced.insert(inst, value);
+                    removed.push(inst);
+                }
+                ValueKind::Store(store) if index.contains_key(&store.dest()) => {
+                    let var = index[&store.dest()];
+                    let value = store.value();
+                    current[var].push(*replaced.get(&value).unwrap_or(&value));
+                    pushed.push(var);
+                    removed.push(inst);
+                }
+                _ => {}
+            }
+        }
+
+        for &succ in cfg.succs(bb) {
+            if let Some(params) = block_params.get(&succ) {
+                let values = params.iter()
+                    .map(|&(var, _)| match current[var].last() {
+                        Some(&value) => value,
+                        None => *undef.get_or_insert_with(|| new_zero(func)),
+                    })
+                    .collect();
+                args.insert((bb, succ), values);
+            }
+        }
+
+        stack.push(Visit::Exit(pushed));
+        for &child in dom.children(bb).iter().rev() {
+            stack.push(Visit::Enter(child));
+        }
+    }
+
+    // predecessors pass the reaching values to parameters
+    for &bb in dom.order() {
+        let term = match terminator(func, bb) {
+            Some(term) => term,
+            None => continue,
+        };
+        let mut kind = func.dfg().value(term).kind().clone();
+        let changed = match &mut kind {
+            ValueKind::Jump(jump) => match args.get(&(bb, jump.target())) {
+                Some(values) => {
+                    jump.args_mut().extend(values);
+                    true
+                }
+                None => false,
+            },
+            ValueKind::Branch(branch) => {
+                let true_args = args.get(&(bb, branch.true_bb())).cloned();
+                let false_args = args.get(&(bb, branch.false_bb())).cloned();
+                if let Some(values) = &true_args {
+                    branch.true_args_mut().extend(values);
+                }
+                if let Some(values) = &false_args {
+                    branch.false_args_mut().extend(values);
+                }
+                true_args.is_some() || false_args.is_some()
+            }
+            _ => false,
+        };
+        if changed {
+            set_kind(func, term, kind);
+        }
+    }
+
+    replace_uses(func, &replaced);
+    removed.extend(allocs.iter().copied());
+    remove_insts(func, &removed);
+    allocs.len()
+}
+
+/// Allocations of `i32` whose address is only loaded from and stored to.
+fn promotable(func: &FunctionData) -> Vec<Value> {
+    let mut allocs = vec![];
+    let mut escaped: HashSet<Value> = HashSet::new();
+    for (_, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            let data = func.dfg().value(inst);
+            match data.kind() {
+                ValueKind::Alloc(_) => {
+                    if let TypeKind::Pointer(base) = data.ty().kind() {
+                        if base.is_i32() {
+                            allocs.push(inst);
+                        }
+                    }
+                }
+                ValueKind::Load(_) => {}
+                ValueKind::Store(store) => {
+                    escaped.insert(store.value());
+                }
+                kind => escaped.extend(kind.value_uses()),
+            }
+        }
+    }
+    allocs.retain(|alloc| !escaped.contains(alloc));
+    allocs
+}
+
+/// Blocks needing a parameter for each variable, at the iterated dominance frontier of its stores
+/// where the variable is live.
+fn place_params(func: &FunctionData, cfg: &Cfg, dom: &Dominators, allocs: &[Value], index: &HashMap<Value, usize>) -> Vec<(BasicBlock, Vec<usize>)> {
+    // blocks storing each variable, and blocks loading it before any store
+    let mut stores: Vec<HashSet<BasicBlock>> = vec![HashSet::new(); allocs.len()];
+    let mut exposed: Vec<HashSet<BasicBlock>> = vec![HashSet::new(); allocs.len()];
+    for &bb in dom.order() {
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Load(load) if index.contains_key(&load.src()) => {
+                    let var = index[&load.src()];
+                    if !stores[var].contains(&bb) {
+                        exposed[var].insert(bb);
+                    }
+                }
+                ValueKind::Store(store) if index.contains_key(&store.dest()) => {
+                    stores[index[&store.dest()]].insert(bb);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let frontiers = dom.frontiers(cfg);
+    let mut params: HashMap<BasicBlock, Vec<usize>> = HashMap::new();
+    for var in 0..allocs.len() {
+        // blocks where the variable is live on entry
+        let mut live: HashSet<BasicBlock> = exposed[var].clone();
+        let mut worklist: Vec<BasicBlock> = live.iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            for &pred in cfg.preds(bb) {
+                if dom.is_reachable(pred) && !stores[var].contains(&pred) && live.insert(pred) {
+                    worklist.push(pred);
+                }
+            }
+        }
+
+        let mut placed: HashSet<BasicBlock> = HashSet::new();
+        let mut worklist: Vec<BasicBlock> = stores[var].iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            for &frontier in &frontiers[&bb] {
+                if live.contains(&frontier) && placed.insert(frontier) {
+                    params.entry(frontier).or_default().push(var);
+                    worklist.push(frontier);
+                }
+            }
+        }
+    }
+
+    // keep the order of parameters stable
+    dom.order().iter().filter_map(|bb| params.remove(bb).map(|mut vars| {
+        vars.sort_unstable();
+        (*bb, vars)
+    })).collect()
+}
+
+/// Append `count` parameters of `i32` to a block.
+fn append_params(func: &mut FunctionData, bb: BasicBlock, count: usize) -> Vec<Value> {
+    let existing = func.dfg().bb(bb).params().len();
+    let new = new_params(func, vec![Type::get_i32(); count], existing);
+    func.dfg_mut().bb_mut(bb).params_mut().extend(new.iter().copied());
+    new
+}
+
+/// Zero read by a variable used before any store.
+fn new_zero(func: &mut FunctionData) -> Value {
+    let zero = func.dfg_mut().new_value().integer(0);
+    let entry = func.layout().entry_bb().unwrap();
+    func.layout_mut().bb_mut(entry).insts_mut().push_key_front(zero).unwrap();
+    zero
+}
@@ -0,0 +1,32 @@
+//! # Opt
+//!
+//! In this module, we optimize the program in memory, between `mem` and the backends.
+//! * `pass`: pass traits, the registry of passes and the pass manager.
+//! * `edit`: functions editing a function in memory, shared by passes.
+//! * `inline`: inline calls of small functions and functions called once.
+//! * `mem2reg`: promote scalar variables in stack into SSA values with block parameters.
+//! * `tailrec`: turn calls of a function in itself, returned right after, into loops.
+//! * `forward`: forward stored values to loads in the same block.
+//! * `sccp`: propagate constants through values and branches, and fold branches on constants.
+//! * `gvn`: replace values computed again by the dominating ones.
+//! * `licm`: hoist computations not changing in a loop into its preheader.
+//! * `dce`: remove dead code, unreachable blocks and unused values.
+//!
+//! `-O0` runs only the cleanup of unreachable code, `-O1` (the default) and `-O2` run the pipelines in `pass::pipeline`,
+//! and `--passes=a,b,c` runs the given passes instead.
+//! `--no-inline` leaves calls alone, and `--inline-size=N` and `--inline-recursion=N` tune the inliner.
+//! `--stats` prints what the passes have done to stderr.
+//!
+
+mod pass;
+mod edit;
+mod inline;
+mod mem2reg;
+mod tailrec;
+mod forward;
+mod sccp;
+mod gvn;
+mod licm;
+mod dce;
+
+pub use pass::{ Config, PassManager, pipeline };
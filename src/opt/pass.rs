@@ -0,0 +1,139 @@
+//! # Pass
+//!
+//! In this file, we define passes over the program in memory and the manager running them.
+//!
+//! A function pass sees one function at a time, declarations are skipped,
+//! and a module pass sees the whole program. Passes are created by name from the registry,
+//! so that a pipeline can be given as a list of names. Whatever the pipeline is,
+//! the manager ends it with `unreachable`, so that every block ends with its only terminator.
+//!
+
+use crate::opt::mem2reg::Mem2Reg;
+use crate::opt::forward::Forward;
+use crate::opt::sccp::Sccp;
+use crate::opt::gvn::Gvn;
+use crate::opt::licm::Licm;
+use crate::opt::inline::Inliner;
+use crate::opt::tailrec::TailRec;
+use crate::opt::dce::{ Dce, Unreachable };
+use koopa::ir::{ FunctionData, Program };
+
+pub trait FunctionPass {
+    /// Run on a function with a body, return whether it's changed.
+    fn run_on(&mut self, func: &mut FunctionData) -> bool;
+
+    /// Lines telling what the pass has done, printed with `--stats`.
+    fn report(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+pub trait ModulePass {
+    /// Run on the whole program, return whether it's changed.
+    fn run(&mut self, program: &mut Program) -> bool;
+
+    /// Lines telling what the pass has done, printed with `--stats`.
+    fn report(&self) -> Vec<String> {
+        vec![]
+    }
+}
+
+pub enum Pass {
+    Function(Box<dyn FunctionPass>),
+    Module(Box<dyn ModulePass>),
+}
+
+/// Names of all passes, in the order they usually run.
+pub const PASSES: &[&str] = &["inline", "mem2reg", "tailrec", "forward", "sccp", "gvn", "licm", "dce", "unreachable"];
+
+/// Options of passes given on the command line.
+pub struct Config {
+    /// Whether `inline` runs, even if it's asked for.
+    pub inline: bool,
+    /// Functions with at most this many instructions are inlined at every call.
+    pub inline_size: usize,
+    /// Times a call of a function in itself is inlined.
+    pub inline_recursion: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self { inline: true, inline_size: 50, inline_recursion: 0 }
+    }
+}
+
+/// Create a pass by its name.
+pub fn create(name: &str, config: &Config) -> Option<Pass> {
+    match name {
+        "inline" => Some(Pass::Module(Box::new(Inliner::new(config.inline_size, config.inline_recursion)))),
+        "mem2reg" => Some(Pass::Function(Box::new(Mem2Reg))),
+        "tailrec" => Some(Pass::Module(Box::new(TailRec))),
+        "forward" => Some(Pass::Function(Box::new(Forward))),
+        "sccp" => Some(Pass::Function(Box::new(Sccp))),
+        "gvn" => Some(Pass::Function(Box::new(Gvn::default()))),
+        "licm" => Some(Pass::Function(Box::new(Licm))),
+        "dce" => Some(Pass::Function(Box::new(Dce))),
+        "unreachable" => Some(Pass::Function(Box::new(Unreachable))),
+        _ => None,
+    }
+}
+
+/// Passes run at an optimization level.
+/// `-O2` runs `sccp` and `gvn` again after `licm`, as values hoisted into a preheader
+/// may be folded or numbered with those computed there already.
+pub fn pipeline(level: usize) -> &'static [&'static str] {
+    match level {
+        0 => &[],
+        1 => &["inline", "mem2reg", "tailrec", "forward", "sccp", "gvn", "licm", "dce"],
+        _ => &["inline", "mem2reg", "tailrec", "forward", "sccp", "gvn", "licm", "sccp", "gvn", "dce"],
+    }
+}
+
+pub struct PassManager {
+    passes: Vec<Pass>,
+}
+
+impl PassManager {
+    /// Create the passes in order, fail with the first unknown name.
+    /// `unreachable` always runs last, since backends rely on a clean CFG.
+    pub fn new(names: &[&str], config: &Config) -> Result<Self, String> {
+        let mut passes = vec![];
+        for &name in names {
+            if name == "inline" && !config.inline {
+                continue;
+            }
+            match create(name, config) {
+                Some(pass) => passes.push(pass),
+                None => return Err(format!("unknown pass `{}`, available passes are: {}", name, PASSES.join(", "))),
+            }
+        }
+        passes.push(Pass::Function(Box::new(Unreachable)));
+        Ok(Self { passes })
+    }
+
+    pub fn run(&mut self, program: &mut Program) {
+        for pass in &mut self.passes {
+            match pass {
+                Pass::Function(pass) => {
+                    for func in program.func_layout().to_vec() {
+                        let func = program.func_mut(func);
+                        if func.layout().entry_bb().is_some() {
+                            pass.run_on(func);
+                        }
+                    }
+                }
+                Pass::Module(pass) => {
+                    pass.run(program);
+                }
+            }
+        }
+    }
+
+    /// Reports of the passes in order.
+    pub fn report(&self) -> Vec<String> {
+        self.passes.iter().flat_map(|pass| match pass {
+            Pass::Function(pass) => pass.report(),
+            Pass::Module(pass) => pass.report(),
+        }).collect()
+    }
+}
@@ -0,0 +1,258 @@
+//! # Sccp
+//!
+//! In this file, we propagate constants through the function, with sparse conditional constant
+//! propagation by Wegman and Zadeck.
+//!
+//! Every value starts unknown, and is lowered to a constant or to varying as blocks are found
+//! executable. A block parameter meets the arguments of the executable edges into it only,
+//! so a variable promoted by `mem2reg` stays constant if every store reaching it does,
+//! and a branch on a constant only makes one of its edges executable.
+//! Then constant values are replaced, branches on constants become jumps,
+//! and blocks never found executable are removed.
+//!
+
+use crate::opt::edit::{ args_to, remove_insts, remove_unreachable, replace_uses, terminator };
+use crate::opt::pass::FunctionPass;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{ BasicBlock, BinaryOp, FunctionData, Value, ValueKind };
+use std::collections::{ HashMap, HashSet };
+
+pub struct Sccp;
+
+impl FunctionPass for Sccp {
+    fn run_on(&mut self, func: &mut FunctionData) -> bool {
+        let (lattice, executable) = propagate(func);
+        let folded = fold_branches(func, &lattice, &executable);
+        let removed = remove_unreachable(func);
+        let replaced = replace_constants(func, &lattice);
+        folded || removed || replaced
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    Unknown,
+    Const(i32),
+    Varying,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, other) | (other, Lattice::Unknown) => other,
+            (Lattice::Const(a), Lattice::Const(b)) if a == b => self,
+            _ => Lattice::Varying,
+        }
+    }
+}
+
+/// Lattice values of values, and executable blocks.
+fn propagate(func: &FunctionData) -> (HashMap<Value, Lattice>, HashSet<BasicBlock>) {
+    // instructions and parameters of blocks using each value
+    let mut users: HashMap<Value, Vec<Value>> = HashMap::new();
+    let mut param_of: HashMap<Value, BasicBlock> = HashMap::new();
+    let mut preds: HashMap<BasicBlock, Vec<(BasicBlock, Value)>> = HashMap::new();
+    for (&bb, node) in func.layout().bbs() {
+        for &param in func.dfg().bb(bb).params() {
+            param_of.insert(param, bb);
+        }
+        for &inst in node.insts().keys() {
+            let kind = func.dfg().value(inst).kind();
+            for used in kind.value_uses() {
+                users.entry(used).or_default().push(inst);
+            }
+            for target in kind.bb_uses() {
+                preds.entry(target).or_default().push((bb, inst));
+            }
+        }
+    }
+
+    let mut lattice: HashMap<Value, Lattice> = HashMap::new();
+    let mut executable: HashSet<BasicBlock> = HashSet::new();
+    let mut edges: HashSet<(BasicBlock, BasicBlock)> = HashSet::new();
+    let mut flow: Vec<(BasicBlock, BasicBlock)> = vec![];
+    let mut ssa: Vec<Value> = vec![];
+
+    let entry = func.layout().entry_bb().unwrap();
+    executable.insert(entry);
+    let mut blocks = vec![entry];
+    loop {
+        // visit every instruction of a block found executable
+        if let Some(bb) = blocks.pop() {
+            for &param in func.dfg().bb(bb).params() {
+                ssa.push(param);
+            }
+            for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+                ssa.push(inst);
+            }
+        }
+        else if let Some((from, to)) = flow.pop() {
+            if !edges.insert((from, to)) {
+                continue;
+            }
+            // parameters meet the arguments of the new edge
+            ssa.extend(func.dfg().bb(to).params().iter().copied());
+            if executable.insert(to) {
+                blocks.push(to);
+            }
+        }
+        else if let Some(value) = ssa.pop() {
+            let new = match param_of.get(&value) {
+                Some(&bb) => {
+                    let i = func.dfg().bb(bb).params().iter().position(|&param| param == value).unwrap();
+                    preds.get(&bb).map_or(&[][..], |preds| preds.as_slice()).iter()
+                        .filter(|&&(pred, _)| edges.contains(&(pred, bb)))
+                        .flat_map(|&(_, term)| args_to(func, term, bb, i))
+                        .fold(Lattice::Unknown, |acc, arg| acc.meet(get(func, &lattice, arg)))
+                }
+                None => {
+                    let bb = func.layout().parent_bb(value).unwrap();
+                    if !executable.contains(&bb) {
+                        continue;
+                    }
+                    match func.dfg().value(value).kind() {
+                        ValueKind::Binary(binary) => match (get(func, &lattice, binary.lhs()), get(func, &lattice, binary.rhs())) {
+                            (Lattice::Const(lhs), Lattice::Const(rhs)) => match fold(binary.op(), lhs, rhs) {
+                                Some(result) => Lattice::Const(result),
+                                None => Lattice::Varying,
+                            },
+                            (Lattice::Unknown, _) | (_, Lattice::Unknown) => Lattice::Unknown,
+                            _ => Lattice::Varying,
+                        },
+                        ValueKind::Integer(int) => Lattice::Const(int.value()),
+                        ValueKind::Branch(branch) => {
+                            match get(func, &lattice, branch.cond()) {
+                                Lattice::Const(cond) => flow.push((bb, if cond != 0 { branch.true_bb() } else { branch.false_bb() })),
+                                Lattice::Varying => {
+                                    flow.push((bb, branch.true_bb()));
+                                    flow.push((bb, branch.false_bb()));
+                                }
+                                Lattice::Unknown => {}
+                            }
+                            // arguments may have changed, so parameters of executable edges meet again
+                            for target in [branch.true_bb(), branch.false_bb()] {
+                                if edges.contains(&(bb, target)) {
+                                    ssa.extend(func.dfg().bb(target).params().iter().copied());
+                                }
+                            }
+                            continue;
+                        }
+                        ValueKind::Jump(jump) => {
+                            flow.push((bb, jump.target()));
+                            if edges.contains(&(bb, jump.target())) {
+                                ssa.extend(func.dfg().bb(jump.target()).params().iter().copied());
+                            }
+                            continue;
+                        }
+                        _ => Lattice::Varying,
+                    }
+                }
+            };
+            let old = lattice.get(&value).copied().unwrap_or(Lattice::Unknown);
+            // values only go down, so every value changes at most twice
+            let new = old.meet(new);
+            if new != old {
+                lattice.insert(value, new);
+                ssa.extend(users.get(&value).map_or(&[][..], |users| users.as_slice()));
+            }
+        }
+        else {
+            break;
+        }
+    }
+    (lattice, executable)
+}
+
+/// The lattice value of an operand, constants are known and values out of the function vary.
+fn get(func: &FunctionData, lattice: &HashMap<Value, Lattice>, value: Value) -> Lattice {
+    if !func.dfg().values().contains_key(&value) {
+        return Lattice::Varying;
+    }
+    match func.dfg().value(value).kind() {
+        ValueKind::Integer(int) => Lattice::Const(int.value()),
+        ValueKind::FuncArgRef(_) => Lattice::Varying,
+        _ => lattice.get(&value).copied().unwrap_or(Lattice::Unknown),
+    }
+}
+
+/// Compute a binary operator as the target does, dividing by zero is left to the runtime.
+fn fold(op: BinaryOp, lhs: i32, rhs: i32) -> Option<i32> {
+    Some(match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div => if rhs == 0 { return None } else { lhs.wrapping_div(rhs) },
+        BinaryOp::Mod => if rhs == 0 { return None } else { lhs.wrapping_rem(rhs) },
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => ((lhs as u32).wrapping_shr(rhs as u32)) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    })
+}
+
+/// Turn branches on constants into jumps, return whether any is turned.
+fn fold_branches(func: &mut FunctionData, lattice: &HashMap<Value, Lattice>, executable: &HashSet<BasicBlock>) -> bool {
+    let mut changed = false;
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        if !executable.contains(&bb) {
+            continue;
+        }
+        let term = match terminator(func, bb) {
+            Some(term) => term,
+            None => continue,
+        };
+        let (target, args) = match func.dfg().value(term).kind() {
+            ValueKind::Branch(branch) => match get(func, lattice, branch.cond()) {
+                Lattice::Const(0) => (branch.false_bb(), branch.false_args().to_vec()),
+                Lattice::Const(_) => (branch.true_bb(), branch.true_args().to_vec()),
+                _ => continue,
+            },
+            _ => continue,
+        };
+        // a terminator has no users to lose
+        func.dfg_mut().replace_value_with(term).jump_with_args(target, args);
+        changed = true;
+    }
+    changed
+}
+
+/// Replace values found constant by integers, return whether any is replaced.
+fn replace_constants(func: &mut FunctionData, lattice: &HashMap<Value, Lattice>) -> bool {
+    let mut replaced: HashMap<Value, Value> = HashMap::new();
+    let mut removed: Vec<Value> = vec![];
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        // parameters are left to `dce` once their uses are gone
+        for param in func.dfg().bb(bb).params().to_vec() {
+            if let Some(&Lattice::Const(value)) = lattice.get(&param) {
+                let int = func.dfg_mut().new_value().integer(value);
+                func.layout_mut().bb_mut(bb).insts_mut().push_key_front(int).unwrap();
+                replaced.insert(param, int);
+            }
+        }
+        let insts: Vec<Value> = func.layout().bbs().node(&bb).unwrap().insts().keys().copied().collect();
+        for inst in insts {
+            if !matches!(func.dfg().value(inst).kind(), ValueKind::Binary(_)) {
+                continue;
+            }
+            if let Some(&Lattice::Const(value)) = lattice.get(&inst) {
+                let int = func.dfg_mut().new_value().integer(value);
+                func.layout_mut().bb_mut(bb).insts_mut().cursor_mut(inst).insert_key_before(int).unwrap();
+                replaced.insert(inst, int);
+                removed.push(inst);
+            }
+        }
+    }
+    replace_uses(func, &replaced);
+    remove_insts(func, &removed);
+    !replaced.is_empty()
+}
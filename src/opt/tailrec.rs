@@ -0,0 +1,81 @@
+//! # Tailrec
+//!
+//! In this file, we turn calls of a function in itself, returned right after, into loops.
+//!
+//! The entry of the function becomes the header of the loop, and takes the parameters of the
+//! function as its own. A new entry jumps to it with the arguments of the function,
+//! and every tail call jumps to it with the arguments of the call. The `alloc`s of the function
+//! move to the new entry, so the loop reuses the objects of its first iteration instead of
+//! allocating them again in every one.
+//! Other tail calls are left to the backend, which jumps to the callee with `tail`.
+//!
+
+use crate::opt::dce::Unreachable;
+use crate::opt::edit::{ new_params, remove_insts, replace_uses };
+use crate::opt::pass::{ FunctionPass, ModulePass };
+use crate::tools::tail_calls;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{ BasicBlock, Function, FunctionData, Program, Value, ValueKind };
+use std::collections::HashMap;
+
+pub struct TailRec;
+
+impl ModulePass for TailRec {
+    fn run(&mut self, program: &mut Program) -> bool {
+        let mut changed = false;
+        for func in program.func_layout().to_vec() {
+            if program.func(func).layout().entry_bb().is_some() {
+                // a block falling off the end of a function returns after the call too
+                changed |= Unreachable.run_on(program.func_mut(func));
+                changed |= tailrec(program.func_mut(func), func);
+            }
+        }
+        changed
+    }
+}
+
+/// Turn tail calls of a function in itself into jumps, return whether there's any.
+fn tailrec(data: &mut FunctionData, func: Function) -> bool {
+    let calls: Vec<(Value, Value)> = tail_calls(data).into_iter()
+        .filter(|&(call, _)| matches!(data.dfg().value(call).kind(), ValueKind::Call(call) if call.callee() == func))
+        .collect();
+    if calls.is_empty() {
+        return false;
+    }
+
+    // the old entry takes the parameters, and `%entry` stays the name of the entry
+    let header = data.layout().entry_bb().unwrap();
+    let name = format!("%tailrec_{}", &data.name()[1..]);
+    data.dfg_mut().bb_mut(header).set_name(Some(name));
+    let tys = data.params().iter().map(|&param| data.dfg().value(param).ty().clone()).collect();
+    let params = new_params(data, tys, 0);
+    *data.dfg_mut().bb_mut(header).params_mut() = params.clone();
+    let replaced: HashMap<Value, Value> = data.params().iter().copied().zip(params).collect();
+    replace_uses(data, &replaced);
+
+    let entry = data.dfg_mut().new_bb().basic_block(Some("%entry".to_string()));
+    data.layout_mut().bbs_mut().push_key_front(entry).unwrap();
+    let allocs: Vec<(BasicBlock, Value)> = data.layout().bbs().iter()
+        .flat_map(|(&bb, node)| node.insts().keys().map(move |&inst| (bb, inst)))
+        .filter(|&(_, inst)| matches!(data.dfg().value(inst).kind(), ValueKind::Alloc(_)))
+        .collect();
+    for (bb, alloc) in allocs {
+        data.layout_mut().bb_mut(bb).insts_mut().remove(&alloc);
+        data.layout_mut().bb_mut(entry).insts_mut().push_key_back(alloc).unwrap();
+    }
+    let args = data.params().to_vec();
+    let jump = data.dfg_mut().new_value().jump_with_args(header, args);
+    data.layout_mut().bb_mut(entry).insts_mut().push_key_back(jump).unwrap();
+
+    // a call without the return has no users, so it can become the jump in place
+    let rets: Vec<Value> = calls.iter().map(|&(_, ret)| ret).collect();
+    remove_insts(data, &rets);
+    for (call, _) in calls {
+        let args = match data.dfg().value(call).kind() {
+            ValueKind::Call(call) => call.args().to_vec(),
+            _ => unreachable!(),
+        };
+        data.dfg_mut().replace_value_with(call).jump_with_args(header, args);
+    }
+    true
+}
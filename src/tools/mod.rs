@@ -5,7 +5,8 @@
 
 use crate::mem::scope::{ Scope, new_value, push_value };
 use crate::mem::info::Info;
-use koopa::ir::{ Program, Value, Type, TypeKind };
+use crate::diagnostic::Diagnostic;
+use koopa::ir::{ Program, FunctionData, Value, ValueKind, Type, TypeKind };
 use koopa::ir::builder_traits::*;
 
 /// Initialize a global const array. Return aggregate value.
@@ -33,18 +34,13 @@ pub fn global_const_array_init(program: &mut Program, nums: Vec<i32>, array_info
 }
 
 /// Initialize a local const array. No return.
-pub fn local_const_array_init(program: &mut Program, scope: &mut Scope, info: &mut Info, nums: Vec<i32>, array_info: Vec<usize>, dest: Value) {
+pub fn local_const_array_init(program: &mut Program, scope: &mut Scope, info: &mut Info, nums: Vec<i32>, array_info: Vec<usize>, dest: Value) -> Result<(), Diagnostic> {
     if array_info.is_empty() {
         let value = new_value!(program, scope).integer(nums[0]);
         push_value!(program, scope, value.clone());
-        info.new_info(value.clone());
 
         let store = new_value!(program, scope).store(value.clone(), dest.clone());
         push_value!(program, scope, store.clone());
-        info.new_info(store.clone());
-
-        info.info_mut(value).unwrap().death = info.counter();
-        info.info_mut(dest).unwrap().death = info.counter();
     }
     else {
         let mut next_nums = vec![];
@@ -57,20 +53,16 @@ pub fn local_const_array_init(program: &mut Program, scope: &mut Scope, info: &m
             if (i + 1) % elem_len == 0 {
                 let index = new_value!(program, scope).integer((i / elem_len) as i32);
                 push_value!(program, scope, index.clone());
-                info.new_info(index.clone());
 
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(dest.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(dest.clone()).unwrap().death = info.counter();
 
-                local_const_array_init(program, scope, info, next_nums, next_array_info.clone(), get_elem_ptr);
+                local_const_array_init(program, scope, info, next_nums, next_array_info.clone(), get_elem_ptr)?;
                 next_nums = vec![];
             }
         }
     }
+    Ok(())
 }
 
 /// Initialize a global array. Return aggregate value.
@@ -99,15 +91,11 @@ pub fn global_array_init(program: &mut Program, values: Vec<Value>, array_info:
     }
 }
 
-pub fn local_array_init(program: &mut Program, scope: &mut Scope, info: &mut Info, values: Vec<Value>, array_info: Vec<usize>, dest: Value) {
+pub fn local_array_init(program: &mut Program, scope: &mut Scope, info: &mut Info, values: Vec<Value>, array_info: Vec<usize>, dest: Value) -> Result<(), Diagnostic> {
     if array_info.is_empty() {
         let value = values[0].clone();
         let store = new_value!(program, scope).store(value.clone(), dest.clone());
         push_value!(program, scope, store);
-        info.new_info(store);
-
-        info.info_mut(value).unwrap().death = info.counter();
-        info.info_mut(dest).unwrap().death = info.counter();
     }
     else {
         let mut next_values = vec![];
@@ -120,20 +108,16 @@ pub fn local_array_init(program: &mut Program, scope: &mut Scope, info: &mut Inf
             if (i + 1) % elem_len == 0 {
                 let index = new_value!(program, scope).integer((i / elem_len) as i32);
                 push_value!(program, scope, index.clone());
-                info.new_info(index.clone());
 
                 let get_elem_ptr = new_value!(program, scope).get_elem_ptr(dest.clone(), index.clone());
                 push_value!(program, scope, get_elem_ptr.clone());
-                info.new_info(get_elem_ptr.clone());
-
-                info.info_mut(index).unwrap().death = info.counter();
-                info.info_mut(dest.clone()).unwrap().death = info.counter();
 
-                local_array_init(program, scope, info, next_values, next_array_info.clone(), get_elem_ptr);
+                local_array_init(program, scope, info, next_values, next_array_info.clone(), get_elem_ptr)?;
                 next_values = vec![];
             }
         }
     }
+    Ok(())
 }
 
 /// My own Into trait for type conversion in this project.
@@ -154,21 +138,67 @@ impl TurnInto<Type> for Vec<usize> {
     }
 }
 
+/// Number of dimensions of an array type, other types have no dimension.
 impl TurnInto<usize> for Type {
     type Addition = ();
     fn turn_into(&self, _: Self::Addition) -> usize {
         match self.kind() {
-            TypeKind::Int32 => 0,
             TypeKind::Array(ty, _) => 1 + ty.turn_into(()),
-            _ => panic!("Type error!"),
+            _ => 0,
         }
     }
 }
 
+/// Size of a type in words. Unit and function take no space.
 pub fn get_size_form_ty(ty: &Type) -> usize {
     match ty.kind() {
         TypeKind::Int32 => 1,
+        TypeKind::Pointer(_) => 1,
         TypeKind::Array(ty, len) => len * get_size_form_ty(ty),
-        _ => panic!("we only expect int32 and array type"),
+        _ => 0,
+    }
+}
+
+/// Whether a pointer may point into the frame of the function.
+pub fn in_frame(func: &FunctionData, value: Value) -> bool {
+    let mut value = value;
+    loop {
+        // globals are not in the function
+        if !func.dfg().values().contains_key(&value) {
+            return false;
+        }
+        match func.dfg().value(value).kind() {
+            ValueKind::Alloc(_) => return true,
+            ValueKind::GetElemPtr(get_elem_ptr) => value = get_elem_ptr.src(),
+            ValueKind::GetPtr(get_ptr) => value = get_ptr.src(),
+            _ => return false,
+        }
+    }
+}
+
+/// Calls whose value is returned right after them, with the returns.
+/// The callee can take the frame of the caller, so no argument points into it.
+pub fn tail_calls(func: &FunctionData) -> Vec<(Value, Value)> {
+    let mut calls = vec![];
+    for (_, node) in func.layout().bbs() {
+        let insts: Vec<Value> = node.insts().keys().copied().collect();
+        for pair in insts.windows(2) {
+            let (call, ret) = (pair[0], pair[1]);
+            let args = match func.dfg().value(call).kind() {
+                ValueKind::Call(call) => call.args(),
+                _ => continue,
+            };
+            let returned = match func.dfg().value(ret).kind() {
+                ValueKind::Return(ret) => match ret.value() {
+                    Some(value) => value == call,
+                    None => func.dfg().value(call).ty().is_unit(),
+                },
+                _ => false,
+            };
+            if returned && !args.iter().any(|&arg| in_frame(func, arg)) {
+                calls.push((call, ret));
+            }
+        }
     }
+    calls
 }
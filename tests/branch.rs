@@ -0,0 +1,56 @@
+//! # Branch
+//!
+//! In this file, we test which branches a condition becomes in assembly. Every condition is the one of
+//! an `if` in a function of two parameters, compiled with `-riscv` at `-O2` and without inlining, and the
+//! branches of the function must be the expected ones, operands included. `a` is in `a0` and `b` in `a1`.
+//!
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_better_compiler");
+
+/// The branch instructions of `f` testing the condition, with their operands.
+fn branches(name: &str, cond: &str) -> Vec<String> {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("branch");
+    fs::create_dir_all(&dir).unwrap();
+    let source = dir.join(format!("{}.sy", name));
+    let output = source.with_extension("S");
+    fs::write(&source, format!(
+        "int f(int a, int b) {{\n  if ({}) return 1;\n  return 0;\n}}\nint main() {{ return f(getint(), getint()); }}\n", cond,
+    )).unwrap();
+    let result = Command::new(COMPILER).arg("-riscv").arg(&source).arg("-o").arg(&output).arg("-O2").arg("--no-inline").output().unwrap();
+    assert!(result.status.success(), "failed to compile `{}`:\n{}", cond, String::from_utf8_lossy(&result.stderr));
+
+    let asm = fs::read_to_string(&output).unwrap();
+    asm.lines()
+        .skip_while(|line| *line != "f:")
+        .take_while(|line| !line.contains(".globl"))
+        .map(|line| line.trim())
+        .filter(|line| line.starts_with('b'))
+        .map(|line| line.rsplit_once(", ").map_or(line, |(inst, _)| inst).to_string())
+        .collect()
+}
+
+#[test]
+fn compare() {
+    assert_eq!(branches("lt", "a < b"), ["blt a0, a1"]);
+    assert_eq!(branches("ge", "a >= b"), ["bge a0, a1"]);
+    assert_eq!(branches("eq", "a == b"), ["beq a0, a1"]);
+    assert_eq!(branches("ne", "a != b"), ["bne a0, a1"]);
+    // `>` and `<=` swap the operands of `<` and `>=`
+    assert_eq!(branches("gt", "a > b"), ["blt a1, a0"]);
+    assert_eq!(branches("le", "a <= b"), ["bge a1, a0"]);
+}
+
+#[test]
+fn range() {
+    assert_eq!(branches("range", "0 <= a && a < 10"), ["bltu a0, t5"]);
+    assert_eq!(branches("range_swapped", "a > -1 && 10 > a"), ["bltu a0, t5"]);
+    assert_eq!(branches("range_empty", "0 <= a && a < 0"), ["bltu a0, x0"]);
+    // unsigned `a < n` isn't `0 <= a && a < n` if `n` may be negative
+    assert_eq!(branches("range_variable", "0 <= a && a < b"), ["bge a0, x0", "blt a0, a1"]);
+    assert_eq!(branches("range_negative", "0 <= a && a < -1"), ["bge a0, x0", "blt a0, t5"]);
+    assert_eq!(branches("range_reversed", "a < 10 && 0 <= a"), ["blt a0, t5", "bge a0, x0"]);
+}
@@ -0,0 +1,58 @@
+//! # Common
+//!
+//! In this file, we define what the end-to-end tests share: running a command with some stdin,
+//! and telling its stdout and exit code in the format of `.out` files.
+//!
+
+use std::env;
+use std::io::Write;
+use std::path::{ Path, PathBuf };
+use std::process::{ Command, Output, Stdio };
+
+pub const COMPILER: &str = env!("CARGO_BIN_EXE_better_compiler");
+
+/// Instructions a program may execute in the emulator or the interpreters, so a miscompiled loop still fails.
+pub const MAX_STEPS: u64 = 1_000_000_000;
+
+/// Run a command with the input as stdin.
+pub fn execute(command: &mut Command, input: &[u8]) -> Output {
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    // a program may exit without reading all of its input
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().unwrap()
+}
+
+/// Stdout and exit code in the format of `.out` files.
+pub fn actual(output: &Output) -> String {
+    let mut actual = String::from_utf8_lossy(&output.stdout).to_string();
+    if !actual.is_empty() && !actual.ends_with('\n') {
+        actual.push('\n');
+    }
+    match output.status.code() {
+        Some(code) => actual + &format!("{}\n", code),
+        None => actual + "killed by a signal\n",
+    }
+}
+
+/// A command from an environment variable, with the file to run appended.
+pub fn runner(var: &str, file: &Path) -> Option<Command> {
+    let line = env::var(var).ok().filter(|line| !line.trim().is_empty())?;
+    let mut words = line.split_whitespace();
+    let mut command = Command::new(words.next().unwrap());
+    command.args(words).arg(file);
+    Some(command)
+}
+
+/// The file a mode writes, by the extension the compiler executes it by.
+pub fn output(dir: &Path, name: &str, mode: &str, level: &str) -> PathBuf {
+    let extension = if mode == "-koopa" { "koopa" } else { "S" };
+    dir.join(format!("{}.{}.{}", name, &level[1..], extension))
+}
+
+/// The compiler executing a file by itself in a mode, such as `run` or `-interp-ast`.
+/// With `run` and `-interp-koopa`, the file is what `-riscv` or `-koopa` wrote, executed as it is.
+pub fn builtin(file: &Path, mode: &str) -> Command {
+    let mut command = Command::new(COMPILER);
+    command.arg(mode).arg(file).arg(format!("--max-steps={}", MAX_STEPS));
+    command
+}
@@ -0,0 +1,105 @@
+//! # Fuzz
+//!
+//! In this file, we test the compiler on random programs from the `gen` mode. Every program is interpreted
+//! with `-interp-ast`, which is the oracle, then compiled in both `-koopa` and `-riscv` modes at every
+//! optimization level and executed like in `run.rs`. Its stdout and exit code must be those of the oracle.
+//!
+//! `SYSY_FUZZ_SEED` is the first seed, 1 by default, and `SYSY_FUZZ_COUNT` the number of programs,
+//! 3 by default. A program which fails is kept in the target directory, and `gen` makes it again from its seed.
+//!
+
+mod common;
+
+use common::{ actual, builtin, execute, output, runner, COMPILER };
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::Command;
+use std::sync::Mutex;
+use std::thread;
+
+const LEVELS: [&str; 3] = ["-O0", "-O1", "-O2"];
+
+fn number(var: &str, default: u64) -> u64 {
+    match env::var(var) {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| panic!("`{}` must be a number", var)),
+        Err(_) => default,
+    }
+}
+
+/// Compile the program in a mode at a level and execute the output, by the runner in `var` or the compiler
+/// in `builtin` mode. Return the output in the format of `.out` files, or why it failed.
+fn test(source: &Path, mode: &str, level: &str, var: &str, builtin_mode: &str) -> Result<String, String> {
+    let name = source.file_stem().unwrap().to_string_lossy();
+    let output = output(source.parent().unwrap(), &name, mode, level);
+    let result = Command::new(COMPILER).arg(mode).arg(source).arg("-o").arg(&output).arg(level).output().unwrap();
+    if !result.status.success() {
+        return Err(format!("failed to compile:\n{}", String::from_utf8_lossy(&result.stderr)));
+    }
+    let mut command = runner(var, &output).unwrap_or_else(|| builtin(&output, builtin_mode));
+    Ok(actual(&execute(&mut command, &[])))
+}
+
+/// Generate the program of a seed and compare every mode with the oracle, return why they're different.
+fn fuzz(seed: u64, dir: &Path) -> Result<(), String> {
+    let source = dir.join(format!("{}.sy", seed));
+    let result = Command::new(COMPILER).arg("gen").arg(seed.to_string()).arg("-o").arg(&source).output().unwrap();
+    if !result.status.success() {
+        return Err(format!("failed to generate:\n{}", String::from_utf8_lossy(&result.stderr)));
+    }
+    let oracle = execute(&mut builtin(&source, "-interp-ast"), &[]);
+    if !oracle.stderr.is_empty() {
+        return Err(format!("the oracle failed, the generator made an invalid program:\n{}", String::from_utf8_lossy(&oracle.stderr)));
+    }
+    let expected = actual(&oracle);
+
+    for level in LEVELS {
+        let koopa = test(&source, "-koopa", level, "SYSY_KOOPA_RUNNER", "-interp-koopa");
+        let riscv = test(&source, "-riscv", level, "SYSY_RISCV_RUNNER", "run");
+        for (mode, result) in [("koopa", koopa), ("riscv", riscv)] {
+            let actual = result?;
+            if actual != expected {
+                return Err(format!("{} {} differs:\n  expected: {:?}\n  actual:   {:?}", mode, level, expected, actual));
+            }
+        }
+    }
+    fs::remove_file(&source).unwrap();
+    Ok(())
+}
+
+#[test]
+fn programs() {
+    let first = number("SYSY_FUZZ_SEED", 1);
+    let count = number("SYSY_FUZZ_COUNT", 3);
+    let dir: PathBuf = Path::new(env!("CARGO_TARGET_TMPDIR")).join("fuzz");
+    fs::create_dir_all(&dir).unwrap();
+
+    // programs are independent, so every thread takes the next seed until none is left
+    let next = Mutex::new(first);
+    let failures = Mutex::new(vec![]);
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| loop {
+                let seed = {
+                    let mut next = next.lock().unwrap();
+                    if *next >= first + count {
+                        break;
+                    }
+                    *next += 1;
+                    *next - 1
+                };
+                if let Err(reason) = fuzz(seed, &dir) {
+                    failures.lock().unwrap().push((seed, reason));
+                }
+            });
+        }
+    });
+
+    let mut failures = failures.into_inner().unwrap();
+    failures.sort();
+    for (seed, reason) in &failures {
+        eprintln!("FAIL seed {}, kept in {}: {}", seed, dir.join(format!("{}.sy", seed)).display(), reason);
+    }
+    assert!(failures.is_empty(), "{} of {} programs failed", failures.len(), count);
+}
@@ -0,0 +1,148 @@
+//! # Run
+//!
+//! In this file, we test the compiler end to end. Every `.sy` program in `tests/cases` is compiled
+//! in both `-koopa` and `-riscv` modes at `-O0`, `-O1` and `-O2` and executed, and interpreted with `-interp-ast` as well,
+//! with the `.in` file of the same name as stdin if there's one. Its stdout, followed by a newline if it doesn't end with one and its exit code,
+//! must be the `.out` file of the same name.
+//!
+//! The assembly file is executed by the built-in emulator with `run`, unless `SYSY_RISCV_RUNNER` names
+//! a command taking it. Likewise, the Koopa IR file is interpreted with `-interp-koopa`,
+//! unless `SYSY_KOOPA_RUNNER` names a command taking it.
+//! `SYSY_TEST_DIR` runs the programs of another directory instead.
+//!
+//! Every `.sy` program in `tests/errors` must fail to compile instead. Its stderr followed by its exit code
+//! must be the `.err` file of the same name, so diagnostics are tested as they're rendered.
+//!
+
+mod common;
+
+use common::{ actual, builtin, execute, output, runner, COMPILER };
+use std::env;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::process::{ Command, Output };
+
+struct Case {
+    name: String,
+    source: PathBuf,
+    input: Vec<u8>,
+    expected: String,
+}
+
+fn sources(dir: &Path) -> Vec<PathBuf> {
+    let mut sources: Vec<PathBuf> = fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sy"))
+        .collect();
+    sources.sort();
+    sources
+}
+
+fn cases(dir: &Path) -> Vec<Case> {
+    sources(dir).into_iter().map(|source| {
+        let name = source.file_stem().unwrap().to_string_lossy().to_string();
+        let input = fs::read(source.with_extension("in")).unwrap_or_default();
+        let expected = fs::read_to_string(source.with_extension("out"))
+            .unwrap_or_else(|_| panic!("{} has no `.out` file", source.display()));
+        Case { name, source, input, expected }
+    }).collect()
+}
+
+const LEVELS: [&str; 3] = ["-O0", "-O1", "-O2"];
+
+/// Compile the case in a mode at a level, return the path of the output, or why it failed.
+fn compile(case: &Case, mode: &str, level: &str, dir: &Path) -> Result<PathBuf, String> {
+    let output = output(dir, &case.name, mode, level);
+    let result = Command::new(COMPILER).arg(mode).arg(&case.source).arg("-o").arg(&output).arg(level).output().unwrap();
+    if result.status.success() {
+        Ok(output)
+    }
+    else {
+        Err(format!("failed to compile:\n{}", String::from_utf8_lossy(&result.stderr)))
+    }
+}
+
+/// Compare the output of a run with the expected one, return why it's different.
+fn check(case: &Case, output: &Output) -> Result<(), String> {
+    let actual = actual(output);
+    if actual == case.expected {
+        return Ok(());
+    }
+    let line = actual.lines().zip(case.expected.lines()).position(|(a, e)| a != e)
+        .unwrap_or(actual.lines().count().min(case.expected.lines().count()));
+    Err(format!(
+        "output differs at line {}:\n  expected: {:?}\n  actual:   {:?}\n{}",
+        line + 1,
+        case.expected.lines().nth(line).unwrap_or("<end>"),
+        actual.lines().nth(line).unwrap_or("<end>"),
+        String::from_utf8_lossy(&output.stderr),
+    ))
+}
+
+/// Compile the case in a mode at a level and execute the output, by the runner in `var` or the compiler in `builtin` mode.
+fn test(case: &Case, dir: &Path, mode: &str, level: &str, var: &str, builtin_mode: &str) -> Result<(), String> {
+    let output = compile(case, mode, level, dir)?;
+    let mut command = runner(var, &output).unwrap_or_else(|| builtin(&output, builtin_mode));
+    check(case, &execute(&mut command, &case.input))
+}
+
+#[test]
+fn programs() {
+    let dir = match env::var("SYSY_TEST_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("cases"),
+    };
+    let temp = Path::new(env!("CARGO_TARGET_TMPDIR")).join("run");
+    fs::create_dir_all(&temp).unwrap();
+
+    let cases = cases(&dir);
+    assert!(!cases.is_empty(), "no programs in {}", dir.display());
+    let mut failures = vec![];
+    for case in &cases {
+        for level in LEVELS {
+            let koopa = test(case, &temp, "-koopa", level, "SYSY_KOOPA_RUNNER", "-interp-koopa");
+            let riscv = test(case, &temp, "-riscv", level, "SYSY_RISCV_RUNNER", "run");
+            for (mode, result) in [("koopa", koopa), ("riscv", riscv)] {
+                if let Err(reason) = result {
+                    failures.push(format!("{} ({} {}): {}", case.name, mode, level, reason));
+                }
+            }
+        }
+        if let Err(reason) = check(case, &execute(&mut builtin(&case.source, "-interp-ast"), &case.input)) {
+            failures.push(format!("{} (ast): {}", case.name, reason));
+        }
+    }
+
+    for failure in &failures {
+        eprintln!("FAIL {}", failure);
+    }
+    assert!(failures.is_empty(), "{} of {} programs failed", failures.len(), cases.len());
+}
+
+#[test]
+fn errors() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("errors");
+    let temp = Path::new(env!("CARGO_TARGET_TMPDIR")).join("errors");
+    fs::create_dir_all(&temp).unwrap();
+
+    let sources = sources(&dir);
+    assert!(!sources.is_empty(), "no programs in {}", dir.display());
+    let mut failures = vec![];
+    for source in &sources {
+        let name = source.file_name().unwrap();
+        let expected = fs::read_to_string(source.with_extension("err"))
+            .unwrap_or_else(|_| panic!("{} has no `.err` file", source.display()));
+        // diagnostics name the file as it's given, so it's given relative to its directory
+        let output = Command::new(COMPILER).current_dir(&dir).arg("-koopa").arg(name)
+            .arg("-o").arg(temp.join(name).with_extension("koopa")).output().unwrap();
+        let actual = format!("{}{}\n", String::from_utf8_lossy(&output.stderr), output.status.code().unwrap_or(-1));
+        if actual != expected {
+            failures.push(format!("{}: stderr differs:\n--- expected\n{}--- actual\n{}", name.to_string_lossy(), expected, actual));
+        }
+    }
+
+    for failure in &failures {
+        eprintln!("FAIL {}", failure);
+    }
+    assert!(failures.is_empty(), "{} of {} programs failed", failures.len(), sources.len());
+}